@@ -0,0 +1,5 @@
+DELETE FROM `user_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'manage_users');
+DELETE FROM `role_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'manage_users');
+DELETE FROM `permissions` WHERE `name` = 'manage_users';
+DROP TABLE IF EXISTS `user_recovery_codes`;
+DROP TABLE IF EXISTS `user_two_factor`;
@@ -0,0 +1,27 @@
+CREATE TABLE IF NOT EXISTS `user_two_factor`
+(
+    `user_id`        char(36)     NOT NULL,
+    `secret`         varchar(255) NOT NULL,
+    `confirmed_at`   timestamp    NULL     DEFAULT NULL,
+    `last_used_step` bigint                DEFAULT NULL,
+    `created_at`     timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    `updated_at`     timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (`user_id`),
+    CONSTRAINT `user_two_factor_user_id_foreign` FOREIGN KEY (`user_id`) REFERENCES `users` (`id`)
+);
+
+CREATE TABLE IF NOT EXISTS `user_recovery_codes`
+(
+    `id`         char(36)     NOT NULL,
+    `user_id`    char(36)     NOT NULL,
+    `code_hash`  varchar(255) NOT NULL,
+    `used_at`    timestamp    NULL     DEFAULT NULL,
+    `created_at` timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    `updated_at` timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (`id`),
+    KEY `user_recovery_codes_user_id_foreign` (`user_id`),
+    CONSTRAINT `user_recovery_codes_user_id_foreign` FOREIGN KEY (`user_id`) REFERENCES `users` (`id`)
+);
+
+INSERT IGNORE INTO `permissions` (`id`, `name`)
+VALUES (UUID(), 'manage_users');
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS `login_attempts`
+(
+    `id`         char(36)     NOT NULL,
+    `username`   varchar(255) NOT NULL,
+    `user_id`    char(36)              DEFAULT NULL,
+    `ip_address` varchar(45)  NOT NULL,
+    `reason`     varchar(32)  NOT NULL,
+    `created_at` timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (`id`),
+    KEY `login_attempts_username_index` (`username`),
+    KEY `login_attempts_ip_address_index` (`ip_address`),
+    KEY `login_attempts_created_at_index` (`created_at`)
+);
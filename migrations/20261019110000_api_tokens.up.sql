@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS `api_tokens`
+(
+    `id`                 char(36)     NOT NULL,
+    `user_id`            char(36)     NOT NULL,
+    `name`               varchar(255) NOT NULL,
+    `token_hash`         char(64)     NOT NULL,
+    `token_prefix`       varchar(16)  NOT NULL,
+    `scopes`             varchar(255) NOT NULL,
+    `created_by_user_id` char(36)              DEFAULT NULL,
+    `last_used_at`       timestamp    NULL     DEFAULT NULL,
+    `expires_at`         timestamp    NULL     DEFAULT NULL,
+    `revoked_at`         timestamp    NULL     DEFAULT NULL,
+    `created_at`         timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    `updated_at`         timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `api_tokens_token_hash_unique` (`token_hash`),
+    KEY `api_tokens_user_id_foreign` (`user_id`),
+    KEY `api_tokens_created_by_user_id_foreign` (`created_by_user_id`),
+    CONSTRAINT `api_tokens_user_id_foreign` FOREIGN KEY (`user_id`) REFERENCES `users` (`id`),
+    CONSTRAINT `api_tokens_created_by_user_id_foreign` FOREIGN KEY (`created_by_user_id`) REFERENCES `users` (`id`)
+);
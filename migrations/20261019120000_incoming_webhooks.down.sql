@@ -0,0 +1,19 @@
+DELETE FROM `user_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'manage_webhooks');
+DELETE FROM `role_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'manage_webhooks');
+DELETE FROM `permissions` WHERE `name` = 'manage_webhooks';
+
+ALTER TABLE `messages`
+    DROP FOREIGN KEY `messages_webhook_id_foreign`,
+    DROP KEY `messages_webhook_id_foreign`,
+    DROP COLUMN `author_avatar_url`,
+    DROP COLUMN `author_display_name`,
+    DROP COLUMN `webhook_id`;
+
+DROP TABLE IF EXISTS `incoming_webhooks`;
+
+-- Messages posted through webhooks belong to the webhook user, which has to
+-- lose them before it can be removed
+DELETE FROM `messages` WHERE `user_id` = '00000000-0000-0000-0000-00000000a001';
+DELETE FROM `user_roles` WHERE `user_id` = '00000000-0000-0000-0000-00000000a001';
+DELETE FROM `user_permissions` WHERE `user_id` = '00000000-0000-0000-0000-00000000a001';
+DELETE FROM `users` WHERE `id` = '00000000-0000-0000-0000-00000000a001';
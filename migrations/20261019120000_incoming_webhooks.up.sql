@@ -0,0 +1,33 @@
+CREATE TABLE IF NOT EXISTS `incoming_webhooks`
+(
+    `id`                     char(36)     NOT NULL,
+    `channel_id`             char(36)     NOT NULL,
+    `name`                   varchar(255) NOT NULL,
+    `avatar_url`             varchar(2048)         DEFAULT NULL,
+    `token_hash`             char(64)     NOT NULL,
+    `rate_limit_per_minute`  int          NOT NULL DEFAULT 30,
+    `created_by_user_id`     char(36)              DEFAULT NULL,
+    `created_at`             timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    `updated_at`             timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    `deleted_at`             timestamp    NULL     DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    KEY `incoming_webhooks_channel_id_foreign` (`channel_id`),
+    KEY `incoming_webhooks_created_by_user_id_foreign` (`created_by_user_id`),
+    CONSTRAINT `incoming_webhooks_channel_id_foreign` FOREIGN KEY (`channel_id`) REFERENCES `channels` (`id`),
+    CONSTRAINT `incoming_webhooks_created_by_user_id_foreign` FOREIGN KEY (`created_by_user_id`) REFERENCES `users` (`id`)
+);
+
+ALTER TABLE `messages`
+    ADD COLUMN `webhook_id`          char(36)      DEFAULT NULL AFTER `channel_id`,
+    ADD COLUMN `author_display_name` varchar(255)  DEFAULT NULL AFTER `webhook_id`,
+    ADD COLUMN `author_avatar_url`   varchar(2048) DEFAULT NULL AFTER `author_display_name`,
+    ADD KEY `messages_webhook_id_foreign` (`webhook_id`),
+    ADD CONSTRAINT `messages_webhook_id_foreign` FOREIGN KEY (`webhook_id`) REFERENCES `incoming_webhooks` (`id`);
+
+-- Messages posted through webhooks are attributed to this system user.
+-- A plain insert, so a user already holding the id or name fails the migration
+INSERT INTO `users` (`id`, `username`, `display_name`, `password`, `is_system_user`)
+VALUES ('00000000-0000-0000-0000-00000000a001', 'webhook', 'Webhook', '!', TRUE);
+
+INSERT IGNORE INTO `permissions` (`id`, `name`)
+VALUES (UUID(), 'manage_webhooks');
@@ -0,0 +1,36 @@
+CREATE TABLE IF NOT EXISTS `webhook_subscriptions`
+(
+    `id`                 char(36)      NOT NULL,
+    `name`               varchar(255)  NOT NULL,
+    `url`                varchar(2048) NOT NULL,
+    `secret`             varchar(255)  NOT NULL,
+    `event_types`        varchar(1024) NOT NULL,
+    `is_active`          boolean       NOT NULL DEFAULT TRUE,
+    `created_by_user_id` char(36)               DEFAULT NULL,
+    `created_at`         timestamp     NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    `updated_at`         timestamp     NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    `deleted_at`         timestamp     NULL     DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    KEY `webhook_subscriptions_created_by_user_id_foreign` (`created_by_user_id`),
+    CONSTRAINT `webhook_subscriptions_created_by_user_id_foreign` FOREIGN KEY (`created_by_user_id`) REFERENCES `users` (`id`)
+);
+
+CREATE TABLE IF NOT EXISTS `webhook_deliveries`
+(
+    `id`                   char(36)    NOT NULL,
+    `subscription_id`      char(36)    NOT NULL,
+    `event_type`           varchar(64) NOT NULL,
+    `payload`              mediumtext  NOT NULL,
+    `status`               varchar(16) NOT NULL DEFAULT 'pending',
+    `attempts`             int         NOT NULL DEFAULT 0,
+    `next_attempt_at`      timestamp   NULL     DEFAULT CURRENT_TIMESTAMP,
+    `last_attempt_at`      timestamp   NULL     DEFAULT NULL,
+    `last_response_status` int                  DEFAULT NULL,
+    `last_error`           text,
+    `created_at`           timestamp   NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    `updated_at`           timestamp   NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (`id`),
+    KEY `webhook_deliveries_subscription_id_foreign` (`subscription_id`),
+    KEY `webhook_deliveries_status_next_attempt_at_index` (`status`, `next_attempt_at`),
+    CONSTRAINT `webhook_deliveries_subscription_id_foreign` FOREIGN KEY (`subscription_id`) REFERENCES `webhook_subscriptions` (`id`)
+);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS `message_reactions`
+(
+    `id`         char(36)    NOT NULL,
+    `message_id` char(36)    NOT NULL,
+    `user_id`    char(36)    NOT NULL,
+    `emoji`      varchar(64) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL,
+    `created_at` timestamp   NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (`id`),
+    UNIQUE KEY `message_reactions_message_id_user_id_emoji_unique` (`message_id`, `user_id`, `emoji`),
+    KEY `message_reactions_user_id_foreign` (`user_id`),
+    CONSTRAINT `message_reactions_message_id_foreign` FOREIGN KEY (`message_id`) REFERENCES `messages` (`id`),
+    CONSTRAINT `message_reactions_user_id_foreign` FOREIGN KEY (`user_id`) REFERENCES `users` (`id`)
+);
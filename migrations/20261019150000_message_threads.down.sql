@@ -0,0 +1,9 @@
+ALTER TABLE `messages`
+    DROP FOREIGN KEY `messages_thread_id_foreign`,
+    DROP FOREIGN KEY `messages_reply_to_message_id_foreign`,
+    DROP KEY `messages_thread_id_created_at_index`,
+    DROP KEY `messages_reply_to_message_id_foreign`,
+    DROP COLUMN `thread_last_reply_at`,
+    DROP COLUMN `thread_reply_count`,
+    DROP COLUMN `thread_id`,
+    DROP COLUMN `reply_to_message_id`;
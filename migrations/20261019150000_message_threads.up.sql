@@ -0,0 +1,9 @@
+ALTER TABLE `messages`
+    ADD COLUMN `reply_to_message_id`  char(36)  DEFAULT NULL AFTER `author_avatar_url`,
+    ADD COLUMN `thread_id`            char(36)  DEFAULT NULL AFTER `reply_to_message_id`,
+    ADD COLUMN `thread_reply_count`   int       NOT NULL DEFAULT 0 AFTER `thread_id`,
+    ADD COLUMN `thread_last_reply_at` timestamp NULL     DEFAULT NULL AFTER `thread_reply_count`,
+    ADD KEY `messages_reply_to_message_id_foreign` (`reply_to_message_id`),
+    ADD KEY `messages_thread_id_created_at_index` (`thread_id`, `created_at`),
+    ADD CONSTRAINT `messages_reply_to_message_id_foreign` FOREIGN KEY (`reply_to_message_id`) REFERENCES `messages` (`id`),
+    ADD CONSTRAINT `messages_thread_id_foreign` FOREIGN KEY (`thread_id`) REFERENCES `messages` (`id`);
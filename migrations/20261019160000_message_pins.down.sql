@@ -0,0 +1,11 @@
+DELETE FROM `user_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'pin_messages');
+DELETE FROM `role_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'pin_messages');
+DELETE FROM `permissions` WHERE `name` = 'pin_messages';
+
+ALTER TABLE `messages`
+    DROP FOREIGN KEY `messages_pinned_by_user_id_foreign`,
+    DROP KEY `messages_pinned_by_user_id_foreign`,
+    DROP KEY `messages_channel_id_pinned_at_index`,
+    DROP COLUMN `pinned_by_user_id`,
+    DROP COLUMN `pinned_at`,
+    DROP COLUMN `message_type`;
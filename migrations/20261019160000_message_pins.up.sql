@@ -0,0 +1,10 @@
+ALTER TABLE `messages`
+    ADD COLUMN `message_type`      varchar(32) NOT NULL DEFAULT 'default' AFTER `channel_id`,
+    ADD COLUMN `pinned_at`         timestamp   NULL     DEFAULT NULL AFTER `thread_last_reply_at`,
+    ADD COLUMN `pinned_by_user_id` char(36)             DEFAULT NULL AFTER `pinned_at`,
+    ADD KEY `messages_channel_id_pinned_at_index` (`channel_id`, `pinned_at`),
+    ADD KEY `messages_pinned_by_user_id_foreign` (`pinned_by_user_id`),
+    ADD CONSTRAINT `messages_pinned_by_user_id_foreign` FOREIGN KEY (`pinned_by_user_id`) REFERENCES `users` (`id`);
+
+INSERT IGNORE INTO `permissions` (`id`, `name`)
+VALUES (UUID(), 'pin_messages');
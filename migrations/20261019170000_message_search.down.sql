@@ -0,0 +1,2 @@
+ALTER TABLE `messages`
+    DROP KEY `messages_content_fulltext`;
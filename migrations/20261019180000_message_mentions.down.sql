@@ -0,0 +1,5 @@
+DELETE FROM `user_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'mention_everyone');
+DELETE FROM `role_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'mention_everyone');
+DELETE FROM `permissions` WHERE `name` = 'mention_everyone';
+
+DROP TABLE IF EXISTS `message_mentions`;
@@ -0,0 +1,19 @@
+CREATE TABLE IF NOT EXISTS `message_mentions`
+(
+    `id`           char(36)    NOT NULL,
+    `message_id`   char(36)    NOT NULL,
+    `mention_type` varchar(16) NOT NULL,
+    `user_id`      char(36)             DEFAULT NULL,
+    `role_id`      char(36)             DEFAULT NULL,
+    `created_at`   timestamp   NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (`id`),
+    KEY `message_mentions_message_id_foreign` (`message_id`),
+    KEY `message_mentions_user_id_foreign` (`user_id`),
+    KEY `message_mentions_role_id_foreign` (`role_id`),
+    CONSTRAINT `message_mentions_message_id_foreign` FOREIGN KEY (`message_id`) REFERENCES `messages` (`id`),
+    CONSTRAINT `message_mentions_user_id_foreign` FOREIGN KEY (`user_id`) REFERENCES `users` (`id`),
+    CONSTRAINT `message_mentions_role_id_foreign` FOREIGN KEY (`role_id`) REFERENCES `roles` (`id`)
+);
+
+INSERT IGNORE INTO `permissions` (`id`, `name`)
+VALUES (UUID(), 'mention_everyone');
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS `channel_read_states`
+(
+    `user_id`              char(36)  NOT NULL,
+    `channel_id`           char(36)  NOT NULL,
+    `last_read_message_id` char(36)  NOT NULL,
+    `last_read_at`         timestamp NOT NULL,
+    `updated_at`           timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (`user_id`, `channel_id`),
+    KEY `channel_read_states_channel_id_foreign` (`channel_id`),
+    KEY `channel_read_states_last_read_message_id_foreign` (`last_read_message_id`),
+    CONSTRAINT `channel_read_states_user_id_foreign` FOREIGN KEY (`user_id`) REFERENCES `users` (`id`),
+    CONSTRAINT `channel_read_states_channel_id_foreign` FOREIGN KEY (`channel_id`) REFERENCES `channels` (`id`),
+    CONSTRAINT `channel_read_states_last_read_message_id_foreign` FOREIGN KEY (`last_read_message_id`) REFERENCES `messages` (`id`)
+);
@@ -0,0 +1,8 @@
+ALTER TABLE `users`
+    DROP COLUMN `dm_privacy`;
+
+DROP TABLE IF EXISTS `channel_members`;
+
+ALTER TABLE `channels`
+    DROP KEY `channels_channel_type_index`,
+    DROP COLUMN `channel_type`;
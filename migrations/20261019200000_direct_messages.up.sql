@@ -0,0 +1,17 @@
+ALTER TABLE `channels`
+    ADD COLUMN `channel_type` varchar(16) NOT NULL DEFAULT 'text' AFTER `name`,
+    ADD KEY `channels_channel_type_index` (`channel_type`);
+
+CREATE TABLE IF NOT EXISTS `channel_members`
+(
+    `channel_id` char(36)  NOT NULL,
+    `user_id`    char(36)  NOT NULL,
+    `created_at` timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (`channel_id`, `user_id`),
+    KEY `channel_members_user_id_foreign` (`user_id`),
+    CONSTRAINT `channel_members_channel_id_foreign` FOREIGN KEY (`channel_id`) REFERENCES `channels` (`id`),
+    CONSTRAINT `channel_members_user_id_foreign` FOREIGN KEY (`user_id`) REFERENCES `users` (`id`)
+);
+
+ALTER TABLE `users`
+    ADD COLUMN `dm_privacy` varchar(16) NOT NULL DEFAULT 'everyone' AFTER `is_system_user`;
@@ -0,0 +1,4 @@
+ALTER TABLE `users`
+    DROP COLUMN `custom_status`;
+
+DROP TABLE IF EXISTS `user_blocks`;
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS `user_blocks`
+(
+    `user_id`         char(36)  NOT NULL,
+    `blocked_user_id` char(36)  NOT NULL,
+    `created_at`      timestamp NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (`user_id`, `blocked_user_id`),
+    KEY `user_blocks_blocked_user_id_foreign` (`blocked_user_id`),
+    CONSTRAINT `user_blocks_user_id_foreign` FOREIGN KEY (`user_id`) REFERENCES `users` (`id`),
+    CONSTRAINT `user_blocks_blocked_user_id_foreign` FOREIGN KEY (`blocked_user_id`) REFERENCES `users` (`id`)
+);
+
+ALTER TABLE `users`
+    ADD COLUMN `custom_status` varchar(128) NULL DEFAULT NULL AFTER `dm_privacy`;
@@ -0,0 +1,2 @@
+ALTER TABLE `users`
+    ADD COLUMN `do_not_disturb` boolean NOT NULL DEFAULT FALSE AFTER `custom_status`;
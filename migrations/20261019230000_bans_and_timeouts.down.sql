@@ -0,0 +1,6 @@
+DELETE FROM `user_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` IN ('ban_users', 'timeout_users'));
+DELETE FROM `role_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` IN ('ban_users', 'timeout_users'));
+DELETE FROM `permissions` WHERE `name` IN ('ban_users', 'timeout_users');
+
+DROP TABLE IF EXISTS `user_timeouts`;
+DROP TABLE IF EXISTS `user_bans`;
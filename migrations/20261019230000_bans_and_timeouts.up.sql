@@ -0,0 +1,41 @@
+CREATE TABLE IF NOT EXISTS `user_bans`
+(
+    `id`                char(36)     NOT NULL,
+    `user_id`           char(36)     NOT NULL,
+    `reason`            varchar(512) NULL     DEFAULT NULL,
+    `banned_by_user_id` char(36)              DEFAULT NULL,
+    `expires_at`        timestamp    NULL     DEFAULT NULL,
+    `lifted_at`         timestamp    NULL     DEFAULT NULL,
+    `lifted_by_user_id` char(36)              DEFAULT NULL,
+    `created_at`        timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (`id`),
+    KEY `user_bans_user_id_lifted_at_index` (`user_id`, `lifted_at`),
+    KEY `user_bans_banned_by_user_id_foreign` (`banned_by_user_id`),
+    KEY `user_bans_lifted_by_user_id_foreign` (`lifted_by_user_id`),
+    CONSTRAINT `user_bans_user_id_foreign` FOREIGN KEY (`user_id`) REFERENCES `users` (`id`),
+    CONSTRAINT `user_bans_banned_by_user_id_foreign` FOREIGN KEY (`banned_by_user_id`) REFERENCES `users` (`id`),
+    CONSTRAINT `user_bans_lifted_by_user_id_foreign` FOREIGN KEY (`lifted_by_user_id`) REFERENCES `users` (`id`)
+);
+
+CREATE TABLE IF NOT EXISTS `user_timeouts`
+(
+    `id`                 char(36)     NOT NULL,
+    `user_id`            char(36)     NOT NULL,
+    `reason`             varchar(512) NULL     DEFAULT NULL,
+    `created_by_user_id` char(36)              DEFAULT NULL,
+    `expires_at`         timestamp    NOT NULL,
+    `lifted_at`          timestamp    NULL     DEFAULT NULL,
+    `lifted_by_user_id`  char(36)              DEFAULT NULL,
+    `created_at`         timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (`id`),
+    KEY `user_timeouts_user_id_lifted_at_index` (`user_id`, `lifted_at`),
+    KEY `user_timeouts_created_by_user_id_foreign` (`created_by_user_id`),
+    KEY `user_timeouts_lifted_by_user_id_foreign` (`lifted_by_user_id`),
+    CONSTRAINT `user_timeouts_user_id_foreign` FOREIGN KEY (`user_id`) REFERENCES `users` (`id`),
+    CONSTRAINT `user_timeouts_created_by_user_id_foreign` FOREIGN KEY (`created_by_user_id`) REFERENCES `users` (`id`),
+    CONSTRAINT `user_timeouts_lifted_by_user_id_foreign` FOREIGN KEY (`lifted_by_user_id`) REFERENCES `users` (`id`)
+);
+
+INSERT IGNORE INTO `permissions` (`id`, `name`)
+VALUES (UUID(), 'ban_users'),
+       (UUID(), 'timeout_users');
@@ -0,0 +1,5 @@
+DELETE FROM `user_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` IN ('view_audit_log', 'kick_users'));
+DELETE FROM `role_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` IN ('view_audit_log', 'kick_users'));
+DELETE FROM `permissions` WHERE `name` IN ('view_audit_log', 'kick_users');
+
+DROP TABLE IF EXISTS `audit_log`;
@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS `audit_log`
+(
+    `id`            char(36)     NOT NULL,
+    `actor_user_id` char(36)              DEFAULT NULL,
+    `action`        varchar(64)  NOT NULL,
+    `target_type`   varchar(64)  NOT NULL,
+    `target_id`     char(36)     NOT NULL,
+    `reason`        varchar(512) NULL     DEFAULT NULL,
+    `changes`       mediumtext   NOT NULL,
+    `created_at`    timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (`id`),
+    KEY `audit_log_created_at_index` (`created_at`),
+    KEY `audit_log_action_created_at_index` (`action`, `created_at`),
+    KEY `audit_log_target_index` (`target_type`, `target_id`),
+    KEY `audit_log_actor_user_id_foreign` (`actor_user_id`),
+    CONSTRAINT `audit_log_actor_user_id_foreign` FOREIGN KEY (`actor_user_id`) REFERENCES `users` (`id`)
+);
+
+INSERT IGNORE INTO `permissions` (`id`, `name`)
+VALUES (UUID(), 'view_audit_log'),
+       (UUID(), 'kick_users');
@@ -0,0 +1,5 @@
+DELETE FROM `user_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'moderate_reports');
+DELETE FROM `role_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'moderate_reports');
+DELETE FROM `permissions` WHERE `name` = 'moderate_reports';
+
+DROP TABLE IF EXISTS `reports`;
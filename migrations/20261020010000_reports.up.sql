@@ -0,0 +1,34 @@
+CREATE TABLE IF NOT EXISTS `reports`
+(
+    `id`                  char(36)     NOT NULL,
+    `reporter_user_id`    char(36)     NOT NULL,
+    `reported_user_id`    char(36)     NOT NULL,
+    `message_id`          char(36)     NULL     DEFAULT NULL,
+    `channel_id`          char(36)     NULL     DEFAULT NULL,
+    `reason`              varchar(512) NULL     DEFAULT NULL,
+    `snapshot`            mediumtext   NOT NULL,
+    `status`              varchar(16)  NOT NULL DEFAULT 'open',
+    `claimed_by_user_id`  char(36)              DEFAULT NULL,
+    `claimed_at`          timestamp    NULL     DEFAULT NULL,
+    `resolved_by_user_id` char(36)              DEFAULT NULL,
+    `resolution`          varchar(16)  NULL     DEFAULT NULL,
+    `resolution_note`     varchar(512) NULL     DEFAULT NULL,
+    `resolved_at`         timestamp    NULL     DEFAULT NULL,
+    `created_at`          timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    `updated_at`          timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    PRIMARY KEY (`id`),
+    KEY `reports_status_created_at_index` (`status`, `created_at`),
+    KEY `reports_reporter_user_id_foreign` (`reporter_user_id`),
+    KEY `reports_reported_user_id_foreign` (`reported_user_id`),
+    KEY `reports_message_id_foreign` (`message_id`),
+    KEY `reports_claimed_by_user_id_foreign` (`claimed_by_user_id`),
+    KEY `reports_resolved_by_user_id_foreign` (`resolved_by_user_id`),
+    CONSTRAINT `reports_reporter_user_id_foreign` FOREIGN KEY (`reporter_user_id`) REFERENCES `users` (`id`),
+    CONSTRAINT `reports_reported_user_id_foreign` FOREIGN KEY (`reported_user_id`) REFERENCES `users` (`id`),
+    CONSTRAINT `reports_message_id_foreign` FOREIGN KEY (`message_id`) REFERENCES `messages` (`id`),
+    CONSTRAINT `reports_claimed_by_user_id_foreign` FOREIGN KEY (`claimed_by_user_id`) REFERENCES `users` (`id`),
+    CONSTRAINT `reports_resolved_by_user_id_foreign` FOREIGN KEY (`resolved_by_user_id`) REFERENCES `users` (`id`)
+);
+
+INSERT IGNORE INTO `permissions` (`id`, `name`)
+VALUES (UUID(), 'moderate_reports');
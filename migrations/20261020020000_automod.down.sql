@@ -0,0 +1,14 @@
+DELETE FROM `user_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'manage_automod');
+DELETE FROM `role_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'manage_automod');
+DELETE FROM `permissions` WHERE `name` = 'manage_automod';
+
+DROP TABLE IF EXISTS `automod_rules`;
+
+-- The automod user files reports and times users out. Its audit entries are
+-- kept without an actor
+UPDATE `audit_log` SET `actor_user_id` = NULL WHERE `actor_user_id` = '00000000-0000-0000-0000-00000000a002';
+DELETE FROM `reports` WHERE `reporter_user_id` = '00000000-0000-0000-0000-00000000a002';
+DELETE FROM `user_timeouts` WHERE `created_by_user_id` = '00000000-0000-0000-0000-00000000a002';
+DELETE FROM `user_roles` WHERE `user_id` = '00000000-0000-0000-0000-00000000a002';
+DELETE FROM `user_permissions` WHERE `user_id` = '00000000-0000-0000-0000-00000000a002';
+DELETE FROM `users` WHERE `id` = '00000000-0000-0000-0000-00000000a002';
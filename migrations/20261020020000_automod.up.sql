@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS `automod_rules`
+(
+    `id`                 char(36)     NOT NULL,
+    `name`               varchar(255) NOT NULL,
+    `rule_type`          varchar(32)  NOT NULL,
+    `config`             mediumtext   NOT NULL,
+    `action`             varchar(16)  NOT NULL,
+    `timeout_seconds`    int          NULL     DEFAULT NULL,
+    `channel_id`         char(36)     NULL     DEFAULT NULL,
+    `is_active`          tinyint(1)   NOT NULL DEFAULT 1,
+    `created_by_user_id` char(36)              DEFAULT NULL,
+    `created_at`         timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    `updated_at`         timestamp    NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+    `deleted_at`         timestamp    NULL     DEFAULT NULL,
+    PRIMARY KEY (`id`),
+    KEY `automod_rules_channel_id_foreign` (`channel_id`),
+    KEY `automod_rules_created_by_user_id_foreign` (`created_by_user_id`),
+    CONSTRAINT `automod_rules_channel_id_foreign` FOREIGN KEY (`channel_id`) REFERENCES `channels` (`id`),
+    CONSTRAINT `automod_rules_created_by_user_id_foreign` FOREIGN KEY (`created_by_user_id`) REFERENCES `users` (`id`)
+);
+
+-- Timeouts and reports issued by automod are attributed to this system user.
+-- A plain insert, so a user already holding the id or name fails the migration
+INSERT INTO `users` (`id`, `username`, `display_name`, `password`, `is_system_user`)
+VALUES ('00000000-0000-0000-0000-00000000a002', 'automod', 'AutoMod', '!', TRUE);
+
+INSERT IGNORE INTO `permissions` (`id`, `name`)
+VALUES (UUID(), 'manage_automod');
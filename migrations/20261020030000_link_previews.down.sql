@@ -0,0 +1,8 @@
+DELETE FROM `audit_log` WHERE `target_type` = 'link_preview';
+ALTER TABLE `audit_log` MODIFY `target_id` char(36) NOT NULL;
+
+DELETE FROM `user_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'manage_link_previews');
+DELETE FROM `role_permissions` WHERE `permission_id` IN (SELECT `id` FROM `permissions` WHERE `name` = 'manage_link_previews');
+DELETE FROM `permissions` WHERE `name` = 'manage_link_previews';
+
+DROP TABLE IF EXISTS `link_previews`;
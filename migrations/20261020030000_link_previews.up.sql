@@ -0,0 +1,20 @@
+-- Fetched link previews, keyed by the SHA-256 of the normalised URL.
+-- Failed fetches are cached as well, with `error` set instead of `preview`.
+CREATE TABLE IF NOT EXISTS `link_previews`
+(
+    `url_hash`        char(64)      NOT NULL,
+    `url`             varchar(2048) NOT NULL,
+    `preview`         mediumtext    NULL     DEFAULT NULL,
+    `error`           varchar(512)  NULL     DEFAULT NULL,
+    `is_rejected_url` tinyint(1)    NOT NULL DEFAULT 0,
+    `fetched_at`      timestamp     NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    `expires_at`      timestamp     NOT NULL,
+    PRIMARY KEY (`url_hash`),
+    KEY `link_previews_expires_at_index` (`expires_at`)
+);
+
+INSERT IGNORE INTO `permissions` (`id`, `name`)
+VALUES (UUID(), 'manage_link_previews');
+
+-- Purges of a single preview are audited against its 64 character url hash
+ALTER TABLE `audit_log` MODIFY `target_id` varchar(64) NOT NULL;
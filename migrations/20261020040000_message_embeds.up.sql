@@ -0,0 +1,13 @@
+-- Link previews unfurled from message content, in the order the links appear
+CREATE TABLE IF NOT EXISTS `message_embeds`
+(
+    `id`         char(36)      NOT NULL,
+    `message_id` char(36)      NOT NULL,
+    `position`   int           NOT NULL,
+    `url`        varchar(2048) NOT NULL,
+    `preview`    mediumtext    NOT NULL,
+    `created_at` timestamp     NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (`id`),
+    KEY `message_embeds_message_id_foreign` (`message_id`),
+    CONSTRAINT `message_embeds_message_id_foreign` FOREIGN KEY (`message_id`) REFERENCES `messages` (`id`)
+);
@@ -0,0 +1,20 @@
+-- One row per direct conversation, keyed by the SHA-256 of its sorted,
+-- comma separated member ids, so the same members never get two of them.
+CREATE TABLE IF NOT EXISTS `direct_conversation_keys`
+(
+    `member_key` char(64) NOT NULL,
+    `channel_id` char(36) NOT NULL,
+    PRIMARY KEY (`member_key`),
+    UNIQUE KEY `direct_conversation_keys_channel_id_unique` (`channel_id`),
+    CONSTRAINT `direct_conversation_keys_channel_id_foreign` FOREIGN KEY (`channel_id`) REFERENCES `channels` (`id`)
+);
+
+-- Conversations duplicated before this migration keep the first one found
+INSERT IGNORE INTO `direct_conversation_keys` (`member_key`, `channel_id`)
+SELECT SHA2(GROUP_CONCAT(CAST(`cm`.`user_id` AS BINARY) ORDER BY CAST(`cm`.`user_id` AS BINARY) SEPARATOR ','), 256),
+       `cm`.`channel_id`
+FROM `channel_members` `cm`
+         JOIN `channels` `c` ON `c`.`id` = `cm`.`channel_id`
+WHERE `c`.`channel_type` = 'direct'
+  AND `c`.`deleted_at` IS NULL
+GROUP BY `cm`.`channel_id`;
@@ -1,14 +1,18 @@
-use crate::models::User;
-use crate::AppState;
+use crate::errors::AppError;
+use crate::{queries, AppState};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 use axum::body::Body;
 use axum::extract::{Request, State};
-use axum::http::{header, StatusCode};
+use axum::http::{header, Method};
 use axum::middleware::Next;
 use axum::response::IntoResponse;
-use axum::Json;
-use jsonwebtoken::{decode, DecodingKey, Validation};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, OnceLock};
+use tracing::warn;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TokenClaims {
@@ -17,20 +21,166 @@ pub struct TokenClaims {
     pub exp: usize,
 }
 
-#[derive(Debug, Serialize)]
-pub struct ErrorResponse {
-    pub status: &'static str,
-    pub message: String,
+/// Long-lived API tokens are opaque random strings with this prefix, which is
+/// how they are told apart from JWTs.
+pub const API_TOKEN_PREFIX: &str = "rsb_";
+pub const API_TOKEN_SCOPE_READ: &str = "read";
+pub const API_TOKEN_SCOPE_WRITE: &str = "write";
+pub const API_TOKEN_SCOPES: [&str; 2] = [API_TOKEN_SCOPE_READ, API_TOKEN_SCOPE_WRITE];
+
+const API_TOKEN_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+const API_TOKEN_LENGTH: usize = 40;
+
+/// How the current request was authenticated. Inserted next to the `User`
+/// extension by `auth`.
+#[derive(Debug, Clone)]
+pub enum AuthContext {
+    Session,
+    ApiToken {
+        token_id: String,
+        scopes: Vec<String>,
+    },
 }
 
-pub fn parse_token(token: &str, jwt_secret: &str) -> Result<TokenClaims, String> {
+impl AuthContext {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match self {
+            AuthContext::Session => true,
+            AuthContext::ApiToken { scopes, .. } => scopes.iter().any(|s| s == scope),
+        }
+    }
+}
+
+pub fn parse_token(
+    token: &str,
+    jwt_secret: &str,
+) -> Result<TokenClaims, jsonwebtoken::errors::Error> {
     decode::<TokenClaims>(
         &token,
         &DecodingKey::from_secret(jwt_secret.as_ref()),
         &Validation::default(),
     )
     .map(|token| token.claims)
-    .map_err(|e| format!("Invalid token: {}", e))
+}
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(API_TOKEN_PREFIX)
+}
+
+/// Generates a random alphanumeric secret of the given length.
+pub fn generate_secret_token(length: usize) -> String {
+    (0..length)
+        .map(|_| {
+            let idx = OsRng.next_u32() as usize % API_TOKEN_ALPHABET.len();
+            API_TOKEN_ALPHABET[idx] as char
+        })
+        .collect()
+}
+
+pub fn generate_api_token() -> String {
+    format!(
+        "{}{}",
+        API_TOKEN_PREFIX,
+        generate_secret_token(API_TOKEN_LENGTH)
+    )
+}
+
+/// API and webhook tokens are stored as SHA-256 hex digests; they are high
+/// entropy so a fast hash is sufficient and allows lookups by hash.
+pub fn hash_api_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Compares a presented token against a stored hash in constant time.
+pub fn token_matches_hash(token: &str, token_hash: &str) -> bool {
+    let presented = hash_api_token(token);
+
+    presented.len() == token_hash.len()
+        && presented
+            .bytes()
+            .zip(token_hash.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Hash of a random password, verified against when the username does not
+/// exist so unknown and known users take the same time to reject.
+fn dummy_password_hash() -> &'static str {
+    static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+    DUMMY_HASH.get_or_init(|| {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(salt.as_str().as_bytes(), &salt)
+            .expect("failed to hash dummy password")
+            .to_string()
+    })
+}
+
+/// Verifies `password` against `password_hash`, falling back to a dummy hash
+/// when there is no user so the Argon2 cost is always paid.
+pub fn verify_password(password_hash: Option<&str>, password: &str) -> bool {
+    let is_known_user = password_hash.is_some();
+    let password_hash = password_hash.unwrap_or_else(|| dummy_password_hash());
+
+    let is_valid = match PasswordHash::new(password_hash) {
+        Ok(parsed_hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .is_ok(),
+        Err(_) => false,
+    };
+
+    is_known_user && is_valid
+}
+
+/// MFA challenge tokens are signed with a key derived from the JWT secret so
+/// they can never be mistaken for an access token by `auth`.
+fn mfa_challenge_secret(jwt_secret: &str) -> String {
+    format!("{}.mfa-challenge", jwt_secret)
+}
+
+fn create_token(
+    user_id: &str,
+    secret: &str,
+    lifetime: chrono::Duration,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let now = chrono::Utc::now();
+    let claims = TokenClaims {
+        sub: user_id.to_string(),
+        iat: now.timestamp() as usize,
+        exp: (now + lifetime).timestamp() as usize,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_ref()),
+    )
+}
+
+pub fn create_access_token(
+    user_id: &str,
+    jwt_secret: &str,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    create_token(user_id, jwt_secret, chrono::Duration::minutes(60))
+}
+
+pub fn create_mfa_challenge_token(
+    user_id: &str,
+    jwt_secret: &str,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    create_token(
+        user_id,
+        &mfa_challenge_secret(jwt_secret),
+        chrono::Duration::minutes(5),
+    )
+}
+
+pub fn parse_mfa_challenge_token(
+    token: &str,
+    jwt_secret: &str,
+) -> Result<TokenClaims, jsonwebtoken::errors::Error> {
+    parse_token(token, &mfa_challenge_secret(jwt_secret))
 }
 
 pub fn extract_user_id(token_claims: &TokenClaims) -> Result<uuid::Uuid, String> {
@@ -41,7 +191,9 @@ pub async fn auth(
     State(data): State<Arc<AppState>>,
     mut request: Request<Body>,
     next: Next,
-) -> Result<impl IntoResponse, (StatusCode, Json<ErrorResponse>)> {
+) -> Result<impl IntoResponse, AppError> {
+    let invalid_token = || AppError::Unauthorized("Invalid token".to_string());
+
     let token = request
         .headers()
         .get(header::AUTHORIZATION)
@@ -54,62 +206,72 @@ pub async fn auth(
             }
         })
         .ok_or_else(|| {
-            let json_error = ErrorResponse {
-                status: "fail",
-                message: "You are not logged in, please provide token.".to_string(),
-            };
-            return (StatusCode::UNAUTHORIZED, Json(json_error));
+            AppError::Unauthorized("You are not logged in, please provide token.".to_string())
         })?;
 
-    let claims = decode::<TokenClaims>(
-        &token,
-        &DecodingKey::from_secret(data.config.jwt_secret.as_ref()),
-        &Validation::default(),
-    )
-    .map_err(|_| {
-        let json_error = ErrorResponse {
-            status: "fail",
-            message: "Invalid token".to_string(),
-        };
-        (StatusCode::UNAUTHORIZED, Json(json_error))
-    })?
-    .claims;
-
-    let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| {
-        let json_error = ErrorResponse {
-            status: "fail",
-            message: "Invalid token".to_string(),
+    let (user_id, auth_context) = if is_api_token(&token) {
+        let api_token =
+            queries::get_active_api_token_by_hash(data.clone(), &hash_api_token(&token))
+                .await?
+                .ok_or_else(invalid_token)?;
+
+        // Safe methods need the read scope, everything else the write scope
+        let required_scope = match *request.method() {
+            Method::GET | Method::HEAD => API_TOKEN_SCOPE_READ,
+            _ => API_TOKEN_SCOPE_WRITE,
         };
-        (StatusCode::UNAUTHORIZED, Json(json_error))
-    })?;
 
-    let query = sqlx::query_as!(
-        User,
-        "SELECT * FROM users WHERE id = ?",
-        user_id.to_string()
-    );
+        if !api_token.has_scope(required_scope) {
+            return Err(AppError::Forbidden(format!(
+                "This API token is missing the '{}' scope",
+                required_scope
+            )));
+        }
 
-    let user = match query.fetch_optional(&data.db).await {
+        if let Err(e) = queries::touch_api_token(data.clone(), &api_token.id).await {
+            warn!("Error updating token usage: {}", e);
+        }
+
+        (
+            api_token.user_id.clone(),
+            AuthContext::ApiToken {
+                token_id: api_token.id.clone(),
+                scopes: api_token.scopes(),
+            },
+        )
+    } else {
+        let claims = decode::<TokenClaims>(
+            &token,
+            &DecodingKey::from_secret(data.config.jwt_secret.as_ref()),
+            &Validation::default(),
+        )
+        .map_err(|_| invalid_token())?
+        .claims;
+
+        let user_id = uuid::Uuid::parse_str(&claims.sub).map_err(|_| invalid_token())?;
+
+        (user_id.to_string(), AuthContext::Session)
+    };
+
+    let user = match queries::get_user_by_id(data.clone(), user_id).await {
         Ok(user) => user,
-        Err(e) => {
-            eprintln!("Error executing query: {}", e);
-            let json_error = ErrorResponse {
-                status: "fail",
-                message: format!("Error fetching user from database: {}", e),
-            };
-            return Err((StatusCode::INTERNAL_SERVER_ERROR, Json(json_error)));
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(AppError::Unauthorized(
+                "The user belonging to this token no longer exists".to_string(),
+            ))
         }
+        Err(e) => return Err(e.into()),
     };
 
-    let user = user.ok_or_else(|| {
-        let json_error = ErrorResponse {
-            status: "fail",
-            message: "The user belonging to this token no longer exists".to_string(),
-        };
-        (StatusCode::UNAUTHORIZED, Json(json_error))
-    })?;
+    if let Some(ban) = queries::get_active_user_ban(data.clone(), &user.id).await? {
+        return Err(AppError::Banned {
+            reason: ban.reason,
+            expires_at: ban.expires_at,
+        });
+    }
 
     request.extensions_mut().insert(user);
+    request.extensions_mut().insert(auth_context);
 
     Ok(next.run(request).await)
 }
@@ -0,0 +1,324 @@
+use axum::extract::Request;
+use axum::http::{header, HeaderName, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::{json, Value};
+use std::fmt;
+use std::time::Duration;
+use tracing::error;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Machine readable error codes shared by REST responses and Socket.IO acks.
+/// Clients branch on these; the messages are for humans and may change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    Banned,
+    TimedOut,
+    Blocked,
+    NotFound,
+    Conflict,
+    /// The recipient asked not to be disturbed.
+    DoNotDisturb,
+    /// The recipient has no open connection.
+    NotConnected,
+    ValidationFailed,
+    Automod,
+    RateLimited,
+    /// The recipient, rather than the sender, has hit a rate limit.
+    RecipientRateLimited,
+    UpstreamFailed,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::Banned => "banned",
+            ErrorCode::TimedOut => "timed_out",
+            ErrorCode::Blocked => "blocked",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::DoNotDisturb => "do_not_disturb",
+            ErrorCode::NotConnected => "not_connected",
+            ErrorCode::ValidationFailed => "validation_failed",
+            ErrorCode::Automod => "automod",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::RecipientRateLimited => "recipient_rate_limited",
+            ErrorCode::UpstreamFailed => "upstream_failed",
+            ErrorCode::Internal => "internal",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden | ErrorCode::Banned | ErrorCode::TimedOut | ErrorCode::Blocked => {
+                StatusCode::FORBIDDEN
+            }
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict | ErrorCode::DoNotDisturb | ErrorCode::NotConnected => {
+                StatusCode::CONFLICT
+            }
+            ErrorCode::ValidationFailed | ErrorCode::Automod => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::RateLimited | ErrorCode::RecipientRateLimited => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            ErrorCode::UpstreamFailed => StatusCode::BAD_GATEWAY,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Every error a handler can answer with. Responses share one envelope:
+/// `{"status", "code", "message", "requestId"}`, plus the fields some codes
+/// carry, such as `retryAfter`.
+#[derive(Debug)]
+pub enum AppError {
+    BadRequest(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Banned {
+        reason: Option<String>,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    },
+    TimedOut {
+        message: String,
+        expires_at: chrono::DateTime<chrono::Utc>,
+    },
+    Blocked(String),
+    NotFound(String),
+    Conflict(String),
+    DoNotDisturb(String),
+    NotConnected(String),
+    Validation(String),
+    Automod(String),
+    RateLimited {
+        message: String,
+        retry_after: Duration,
+    },
+    RecipientRateLimited {
+        message: String,
+        retry_after: Duration,
+    },
+    Upstream(String),
+    Database(sqlx::Error),
+    /// The detail is logged, never sent to the client.
+    Internal(String),
+}
+
+impl AppError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AppError::BadRequest(_) => ErrorCode::BadRequest,
+            AppError::Unauthorized(_) => ErrorCode::Unauthorized,
+            AppError::Forbidden(_) => ErrorCode::Forbidden,
+            AppError::Banned { .. } => ErrorCode::Banned,
+            AppError::TimedOut { .. } => ErrorCode::TimedOut,
+            AppError::Blocked(_) => ErrorCode::Blocked,
+            AppError::NotFound(_) => ErrorCode::NotFound,
+            AppError::Conflict(_) => ErrorCode::Conflict,
+            AppError::DoNotDisturb(_) => ErrorCode::DoNotDisturb,
+            AppError::NotConnected(_) => ErrorCode::NotConnected,
+            AppError::Validation(_) => ErrorCode::ValidationFailed,
+            AppError::Automod(_) => ErrorCode::Automod,
+            AppError::RateLimited { .. } => ErrorCode::RateLimited,
+            AppError::RecipientRateLimited { .. } => ErrorCode::RecipientRateLimited,
+            AppError::Upstream(_) => ErrorCode::UpstreamFailed,
+            AppError::Database(_) | AppError::Internal(_) => ErrorCode::Internal,
+        }
+    }
+
+    /// Fields sent next to the code and message.
+    fn details(&self) -> Value {
+        match self {
+            AppError::Banned { reason, expires_at } => {
+                json!({ "reason": reason, "expiresAt": expires_at })
+            }
+            AppError::TimedOut { expires_at, .. } => json!({ "expiresAt": expires_at }),
+            AppError::RateLimited { retry_after, .. }
+            | AppError::RecipientRateLimited { retry_after, .. } => {
+                json!({ "retryAfter": retry_after.as_secs().max(1) })
+            }
+            _ => json!({}),
+        }
+    }
+
+    /// The Socket.IO acknowledgement for this error. Socket handlers log
+    /// failures themselves, with more context than a request id.
+    pub fn to_ack(&self) -> Value {
+        let mut ack = json!({
+            "success": false,
+            "code": self.code().as_str(),
+            "error": self.to_string(),
+        });
+        merge(&mut ack, self.details());
+
+        ack
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::BadRequest(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::TimedOut { message, .. }
+            | AppError::Blocked(message)
+            | AppError::NotFound(message)
+            | AppError::Conflict(message)
+            | AppError::DoNotDisturb(message)
+            | AppError::NotConnected(message)
+            | AppError::Validation(message)
+            | AppError::Automod(message)
+            | AppError::RateLimited { message, .. }
+            | AppError::RecipientRateLimited { message, .. }
+            | AppError::Upstream(message) => write!(f, "{}", message),
+            AppError::Banned { .. } => write!(f, "You are banned from this server"),
+            AppError::Database(_) | AppError::Internal(_) => write!(f, "Internal error"),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match &e {
+            sqlx::Error::RowNotFound => AppError::NotFound("Resource not found".to_string()),
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                AppError::Conflict("Resource already exists".to_string())
+            }
+            _ => AppError::Database(e),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let request_id = current_request_id();
+        match &self {
+            AppError::Database(e) => error!(
+                "Request {} failed: database error: {}",
+                request_id.as_deref().unwrap_or("-"),
+                e
+            ),
+            AppError::Internal(e) => {
+                error!(
+                    "Request {} failed: {}",
+                    request_id.as_deref().unwrap_or("-"),
+                    e
+                )
+            }
+            _ => {}
+        }
+
+        let code = self.code();
+        let status = code.status();
+        let mut body = json!({
+            "status": if status.is_server_error() { "error" } else { "fail" },
+            "code": code.as_str(),
+            "message": self.to_string(),
+            "requestId": request_id,
+        });
+        merge(&mut body, self.details());
+
+        let mut response = (status, Json(body)).into_response();
+        if let AppError::RateLimited { retry_after, .. }
+        | AppError::RecipientRateLimited { retry_after, .. } = &self
+        {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after.as_secs().max(1).into());
+        }
+
+        response
+    }
+}
+
+fn merge(target: &mut Value, fields: Value) {
+    if let (Some(target), Value::Object(fields)) = (target.as_object_mut(), fields) {
+        target.extend(fields);
+    }
+}
+
+/// The id of the request being handled, if called from within one.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Middleware giving every request an id, echoed in the `X-Request-Id`
+/// header and in error bodies so reports can be matched to the server log.
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let id = uuid::Uuid::new_v4().to_string();
+
+    let mut response = REQUEST_ID.scope(id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn responses_share_one_envelope() {
+        let response = REQUEST_ID
+            .scope("req-1".to_string(), async {
+                AppError::RateLimited {
+                    message: "Slow down".to_string(),
+                    retry_after: Duration::from_secs(30),
+                }
+                .into_response()
+            })
+            .await;
+
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "30");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::from_slice::<Value>(&body).unwrap(),
+            json!({
+                "status": "fail",
+                "code": "rate_limited",
+                "message": "Slow down",
+                "requestId": "req-1",
+                "retryAfter": 30,
+            })
+        );
+    }
+
+    #[test]
+    fn database_errors_are_mapped_and_not_leaked() {
+        let not_found = AppError::from(sqlx::Error::RowNotFound);
+        assert_eq!(not_found.code(), ErrorCode::NotFound);
+        assert_eq!(not_found.code().status(), StatusCode::NOT_FOUND);
+
+        let internal = AppError::from(sqlx::Error::PoolTimedOut);
+        assert_eq!(internal.code(), ErrorCode::Internal);
+        assert_eq!(internal.to_string(), "Internal error");
+        assert_eq!(
+            internal.to_ack(),
+            json!({ "success": false, "code": "internal", "error": "Internal error" })
+        );
+    }
+}
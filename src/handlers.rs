@@ -1,25 +1,71 @@
-use crate::auth::TokenClaims;
-use crate::models::User;
-use crate::requests::{LoginRequest, RegisterRequest};
+use crate::auth::{
+    create_access_token, create_mfa_challenge_token, extract_user_id, generate_api_token,
+    generate_secret_token, parse_mfa_challenge_token, token_matches_hash, verify_password,
+    API_TOKEN_SCOPES,
+};
+use crate::errors::AppError;
+use crate::models::{
+    Channel, Message, Report, User, UserBan, UserTimeout, AUTOMOD_ACTIONS, AUTOMOD_ACTION_TIMEOUT,
+    DM_PRIVACY_SETTINGS, MESSAGE_TYPE_DEFAULT, MESSAGE_TYPE_PIN_ADDED, MESSAGE_TYPE_PIN_REMOVED,
+    REPORT_RESOLUTION_BAN, REPORT_RESOLUTION_DELETE, REPORT_RESOLUTION_DISMISS,
+    REPORT_RESOLUTION_TIMEOUT, REPORT_STATUSES, REPORT_STATUS_CLAIMED, REPORT_STATUS_DISMISSED,
+    REPORT_STATUS_OPEN, REPORT_STATUS_RESOLVED, WEBHOOK_SYSTEM_USER_ID,
+};
+use crate::queries::{
+    AuditLogFilters, AutomodRuleFields, CreateMessageOptions, MessageSearchFilters, PinOutcome,
+};
+use crate::requests::{
+    CreateApiTokenRequest, CreateAutomodRuleRequest, CreateBanRequest, CreateBotRequest,
+    CreateDirectConversationRequest, CreateIncomingWebhookRequest, CreateMessageRequest,
+    CreateTimeoutRequest, CreateWebhookSubscriptionRequest, ExecuteIncomingWebhookRequest,
+    LoginRequest, MarkReadRequest, MfaLoginRequest, RegisterRequest, ResolveReportRequest,
+    TwoFactorConfirmRequest, UpdateAutomodRuleRequest, UpdateIncomingWebhookRequest,
+    UpdateUserSettingsRequest, UpdateWebhookSubscriptionRequest,
+};
 use crate::responses::{
-    ChannelResource, ConnectionStateResource, ServerInfoResource, UserListResource,
+    ApiTokenResource, AutomodRuleResource, ConnectionStateResource, IncomingWebhookResource,
+    LoginAttemptResource, MessageResource, ServerInfoResource, ThreadResource, UserListResource,
+    UserResource, WebhookDeliveryResource, WebhookSubscriptionResource,
+};
+use crate::services::audit_log::{self, audit_actions, audit_target_types};
+use crate::services::automod::{flag_message, screen_message, AutomodVerdict, Filter};
+use crate::services::blocks::{
+    emit_chat_message, emit_message_update, hide_custom_status, is_blocked_in_channel,
+};
+use crate::services::direct_messages::{
+    open_direct_conversation, to_direct_conversation_resources,
+};
+use crate::services::image_proxy::{get_proxied_image, proxy_preview_images};
+use crate::services::mentions::{notify_mentions, record_mentions};
+use crate::services::messages::{
+    attach_reply_references, thread_summary_for, to_message_resources, validate_message_references,
+};
+use crate::services::moderation::{
+    announce_ban, announce_timeout, timed_out_message, MAX_REASON_LENGTH, MAX_TIMEOUT,
 };
-use crate::services::link_preview::get_url_preview;
+use crate::services::outgoing_webhooks::{event_types, spawn_dispatch_event};
+use crate::services::preview_cache::{get_cached_preview, normalize_url, purge_previews, url_hash};
+use crate::services::read_state::{mark_channel_read, to_channel_resources};
+use crate::services::reports::{notify_moderators, MODERATE_REPORTS_PERMISSION};
+use crate::services::search::fulltext_boolean_query;
+use crate::services::two_factor;
+use crate::services::unfurl::spawn_unfurl;
+use crate::socket::events::socket_publish_events;
+use crate::socket::handlers::ReceiveChatMessagePayload;
 use crate::{queries, AppState};
 use argon2::password_hash::rand_core::OsRng;
 use argon2::password_hash::SaltString;
-use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
-use axum::extract::{Path, Query, State};
-use axum::http::StatusCode;
+use argon2::{Argon2, PasswordHasher};
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::{header, StatusCode};
 use axum::response::IntoResponse;
 use axum::{Extension, Json};
-use jsonwebtoken::{encode, EncodingKey, Header};
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
 use serde_json::json;
 use url::Url;
-use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
-use tracing::info;
+use tracing::{info, warn};
 
 pub async fn hello_handler() -> impl IntoResponse {
     "Hello, Rust! V2!"
@@ -27,20 +73,11 @@ pub async fn hello_handler() -> impl IntoResponse {
 
 pub async fn get_server_info(
     State(data): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let channels = queries::get_channels(data).await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({
-                "message": format!("Error: {}", e),
-            })),
-        )
-    })?;
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse, AppError> {
+    let channels = queries::get_channels(data.clone()).await?;
 
-    let channels = channels
-        .iter()
-        .map(|channel| channel.to_resource())
-        .collect::<Vec<ChannelResource>>();
+    let channels = to_channel_resources(data.clone(), &channels, &user.id).await?;
 
     let response = ServerInfoResource {
         id: "27551e8f-8e8d-4c54-b8e8-4c005a56076b".to_string(),
@@ -56,132 +93,566 @@ pub async fn get_server_info(
 
 pub async fn get_channels_handler(
     State(data): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let channels = queries::get_channels(data).await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({
-                "message": format!("Error: {}", e),
-            })),
-        )
-    })?;
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse, AppError> {
+    let channels = queries::get_channels(data.clone()).await?;
 
-    let channel_resources = channels
-        .iter()
-        .map(|channel| channel.to_resource())
-        .collect::<Vec<ChannelResource>>();
+    let channel_resources = to_channel_resources(data.clone(), &channels, &user.id).await?;
 
     return Ok((StatusCode::OK, Json(json!(channel_resources))));
 }
 
 pub async fn get_channel_messages_handler(
     State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
     Path(channel_id): Path<String>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let messages = queries::get_channel_messages(data.clone(), channel_id)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "message": format!("Error: {}", e),
-                })),
-            )
-        })?;
+) -> Result<impl IntoResponse, AppError> {
+    get_accessible_channel(data.clone(), &channel_id, &user).await?;
 
-    // Collect unique user IDs from messages
+    let messages = queries::get_channel_messages(data.clone(), channel_id).await?;
 
-    let unique_user_ids: HashSet<String> = messages
-        .iter()
-        .map(|message| message.user_id.clone())
-        .collect();
+    let message_resources = to_message_resources(data.clone(), messages, Some(&user.id)).await?;
 
-    let unique_user_ids_vec: Vec<String> = unique_user_ids.into_iter().collect();
+    Ok((StatusCode::OK, Json(json!(message_resources))))
+}
 
-    // Fetch only the needed users
+fn thread_not_found() -> AppError {
+    AppError::NotFound("Thread not found".to_string())
+}
 
-    let users = queries::get_users(data.clone(), Some(&unique_user_ids_vec))
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({ "message": format!("Failed to fetch users: {}", e) })),
-            )
+fn channel_not_found() -> AppError {
+    AppError::NotFound("Channel not found".to_string())
+}
+
+/// Text channel or direct conversation `user` takes part in. Other direct
+/// conversations are reported as missing.
+async fn get_accessible_channel(
+    data: Arc<AppState>,
+    channel_id: &str,
+    user: &User,
+) -> Result<Channel, AppError> {
+    queries::get_accessible_channel(data, channel_id, &user.id)
+        .await?
+        .ok_or_else(channel_not_found)
+}
+
+pub async fn get_channel_threads_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(channel_id): Path<String>,
+    Query(params): Query<PaginationQueryParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    get_accessible_channel(data.clone(), &channel_id, &user).await?;
+
+    let parents = queries::get_channel_threads(data.clone(), &channel_id, limit, offset).await?;
+
+    let parent_resources = to_message_resources(data.clone(), parents, Some(&user.id)).await?;
+
+    Ok((StatusCode::OK, Json(json!(parent_resources))))
+}
+
+pub async fn get_message_thread_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path((channel_id, message_id)): Path<(String, String)>,
+    Query(params): Query<PaginationQueryParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    get_accessible_channel(data.clone(), &channel_id, &user).await?;
+
+    // Thread replies cannot have threads of their own
+    let parent = queries::get_message_by_id(data.clone(), &message_id)
+        .await?
+        .filter(|message| message.channel_id == channel_id && message.thread_id.is_none())
+        .ok_or_else(thread_not_found)?;
+
+    let replies = queries::get_thread_messages(data.clone(), &parent.id, limit, offset).await?;
+
+    let mut messages = vec![parent];
+    messages.extend(replies);
+
+    let mut resources = to_message_resources(data.clone(), messages, Some(&user.id))
+        .await?
+        .into_iter();
+
+    let parent = resources.next().ok_or_else(thread_not_found)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!(ThreadResource {
+            parent,
+            replies: resources.collect(),
+        })),
+    ))
+}
+
+const MAX_PINS_PER_CHANNEL: i64 = 50;
+
+fn message_not_found() -> AppError {
+    AppError::NotFound("Message not found".to_string())
+}
+
+async fn get_channel_message(
+    data: Arc<AppState>,
+    channel_id: &str,
+    message_id: &str,
+) -> Result<Message, AppError> {
+    queries::get_message_by_id(data, message_id)
+        .await?
+        .filter(|message| message.channel_id == channel_id)
+        .ok_or_else(message_not_found)
+}
+
+/// Broadcasts the new pin state of `message_id` and posts a system message
+/// quoting it. Returns the updated message as seen by `user`.
+async fn announce_pin_change(
+    data: Arc<AppState>,
+    user: &User,
+    channel: &Channel,
+    message_id: &str,
+    message_type: &'static str,
+) -> Result<MessageResource, AppError> {
+    let message = queries::get_message_by_id(data.clone(), message_id)
+        .await?
+        .ok_or_else(message_not_found)?;
+
+    let broadcast_resource = to_message_resources(data.clone(), vec![message], None)
+        .await?
+        .pop()
+        .ok_or_else(message_not_found)?;
+
+    emit_message_update(data.clone(), channel, &broadcast_resource).await;
+
+    let content = if message_type == MESSAGE_TYPE_PIN_ADDED {
+        format!("{} pinned a message to this channel.", user.display_name)
+    } else {
+        format!(
+            "{} unpinned a message from this channel.",
+            user.display_name
+        )
+    };
+
+    let system_message = queries::create_message(
+        data.clone(),
+        user.id.clone(),
+        channel.id.clone(),
+        Some(content),
+        CreateMessageOptions {
+            reply_to_message_id: Some(message_id.to_string()),
+            message_type: Some(message_type),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    let mut resources = [system_message.to_resource(user.to_resource())];
+    attach_reply_references(data.clone(), &mut resources).await?;
+    let [system_message_resource] = resources;
+
+    let payload = ReceiveChatMessagePayload {
+        message: system_message_resource,
+        thread: None,
+    };
+
+    if !channel.is_direct() {
+        spawn_dispatch_event(
+            data.clone(),
+            event_types::MESSAGE_CREATED,
+            json!({ "message": payload.message }),
+        );
+    }
+
+    emit_chat_message(data.clone(), channel, &payload).await;
+
+    let message = queries::get_message_by_id(data.clone(), message_id)
+        .await?
+        .ok_or_else(message_not_found)?;
+
+    to_message_resources(data.clone(), vec![message], Some(&user.id))
+        .await?
+        .pop()
+        .ok_or_else(message_not_found)
+}
+
+pub async fn get_channel_pins_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(channel_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    get_accessible_channel(data.clone(), &channel_id, &user).await?;
+
+    let messages = queries::get_pinned_messages(data.clone(), &channel_id).await?;
+
+    let message_resources = to_message_resources(data.clone(), messages, Some(&user.id)).await?;
+
+    Ok((StatusCode::OK, Json(json!(message_resources))))
+}
+
+pub async fn put_channel_pin_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path((channel_id, message_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "pin_messages").await?;
+
+    let channel = get_accessible_channel(data.clone(), &channel_id, &user).await?;
+    let message = get_channel_message(data.clone(), &channel_id, &message_id).await?;
+
+    if message.message_type != MESSAGE_TYPE_DEFAULT {
+        return Err(AppError::Validation(
+            "System messages cannot be pinned".to_string(),
+        ));
+    }
+
+    // Pinning twice is a no-op
+    let outcome = if message.pinned_at.is_some() {
+        PinOutcome::AlreadyPinned
+    } else {
+        queries::pin_message(
+            data.clone(),
+            &channel.id,
+            &message.id,
+            &user.id,
+            MAX_PINS_PER_CHANNEL,
+        )
+        .await?
+    };
+
+    match outcome {
+        PinOutcome::Pinned => {}
+        PinOutcome::AlreadyPinned => {
+            // Someone else may have pinned it in the meantime
+            let message = get_channel_message(data.clone(), &channel_id, &message.id).await?;
+            let message_resource =
+                to_message_resources(data.clone(), vec![message], Some(&user.id))
+                    .await?
+                    .pop()
+                    .ok_or_else(message_not_found)?;
+
+            return Ok((StatusCode::OK, Json(json!(message_resource))));
+        }
+        PinOutcome::LimitReached => {
+            return Err(AppError::Conflict(format!(
+                "A channel can have at most {} pinned messages",
+                MAX_PINS_PER_CHANNEL
+            )));
+        }
+    }
+
+    info!("User {} pinned message {}", user.id, message.id);
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::MESSAGE_PINNED,
+        audit_target_types::MESSAGE,
+        &message.id,
+        None,
+        audit_log::diff(&json!({ "pinned": false }), &json!({ "pinned": true })),
+    )
+    .await;
+
+    let message_resource = announce_pin_change(
+        data.clone(),
+        &user,
+        &channel,
+        &message.id,
+        MESSAGE_TYPE_PIN_ADDED,
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(json!(message_resource))))
+}
+
+pub async fn delete_channel_pin_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path((channel_id, message_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "pin_messages").await?;
+
+    let channel = get_accessible_channel(data.clone(), &channel_id, &user).await?;
+    let message = get_channel_message(data.clone(), &channel_id, &message_id).await?;
+
+    let unpinned = queries::unpin_message(data.clone(), &message.id).await?;
+
+    if !unpinned {
+        return Err(AppError::NotFound("Message is not pinned".to_string()));
+    }
+
+    info!("User {} unpinned message {}", user.id, message.id);
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::MESSAGE_UNPINNED,
+        audit_target_types::MESSAGE,
+        &message.id,
+        None,
+        audit_log::diff(&json!({ "pinned": true }), &json!({ "pinned": false })),
+    )
+    .await;
+
+    let message_resource = announce_pin_change(
+        data.clone(),
+        &user,
+        &channel,
+        &message.id,
+        MESSAGE_TYPE_PIN_REMOVED,
+    )
+    .await?;
+
+    Ok((StatusCode::OK, Json(json!(message_resource))))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchMessagesQueryParams {
+    q: Option<String>,
+    #[serde(rename = "authorId")]
+    author_id: Option<String>,
+    #[serde(rename = "channelId")]
+    channel_id: Option<String>,
+    after: Option<chrono::DateTime<chrono::Utc>>,
+    before: Option<chrono::DateTime<chrono::Utc>>,
+    #[serde(rename = "hasAttachment")]
+    has_attachment: Option<bool>,
+    #[serde(rename = "mentionsMe")]
+    mentions_me: Option<bool>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+pub async fn get_search_messages_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Query(params): Query<SearchMessagesQueryParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = params.limit.unwrap_or(25).clamp(1, 100);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let text = params.q.as_deref().map(str::trim).unwrap_or("");
+    let fulltext_query = if text.is_empty() {
+        None
+    } else {
+        let fulltext_query = fulltext_boolean_query(text).ok_or_else(|| {
+            AppError::Validation("Search text contains no searchable words".to_string())
         })?;
 
-    // Build a lookup map for users
-    let user_map: HashMap<String, User> = users.into_iter().map(|u| (u.id.clone(), u)).collect();
+        Some(fulltext_query)
+    };
 
-    // Build message resources
-    let message_resources = messages
-        .into_iter()
-        .map(|msg| {
-            let user = user_map.get(&msg.user_id).map(|u| u.to_resource());
+    let filters = MessageSearchFilters {
+        fulltext_query,
+        author_id: params.author_id,
+        channel_id: params.channel_id,
+        created_after: params.after,
+        created_before: params.before,
+        has_attachment: params.has_attachment,
+        mentions_user_id: params.mentions_me.unwrap_or(false).then(|| user.id.clone()),
+    };
 
-            return msg.to_resource(user.unwrap());
-        })
+    let messages =
+        queries::search_messages(data.clone(), &user.id, &filters, limit, offset).await?;
+
+    let message_resources = to_message_resources(data.clone(), messages, Some(&user.id)).await?;
+
+    Ok((StatusCode::OK, Json(json!(message_resources))))
+}
+
+pub async fn put_channel_read_state_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(channel_id): Path<String>,
+    Json(body): Json<MarkReadRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let read_state =
+        mark_channel_read(data.clone(), &user.id, &channel_id, &body.message_id).await?;
+
+    Ok((StatusCode::OK, Json(json!(read_state))))
+}
+
+pub async fn get_direct_conversations_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse, AppError> {
+    let conversations = queries::get_direct_conversations(data.clone(), &user.id).await?;
+
+    let conversation_resources =
+        to_direct_conversation_resources(data.clone(), &conversations, &user.id).await?;
+
+    Ok((StatusCode::OK, Json(json!(conversation_resources))))
+}
+
+pub async fn post_direct_conversation_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Json(body): Json<CreateDirectConversationRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let channel = open_direct_conversation(data.clone(), &user, &body.user_ids).await?;
+
+    let conversation = queries::get_direct_conversations(data.clone(), &user.id)
+        .await?
+        .into_iter()
+        .filter(|conversation| conversation.id == channel.id)
         .collect::<Vec<_>>();
 
+    let conversation_resource =
+        to_direct_conversation_resources(data.clone(), &conversation, &user.id)
+            .await?
+            .pop()
+            .ok_or_else(channel_not_found)?;
+
+    Ok((StatusCode::OK, Json(json!(conversation_resource))))
+}
+
+pub async fn get_direct_messages_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(channel_id): Path<String>,
+    Query(params): Query<PaginationQueryParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let channel = get_accessible_channel(data.clone(), &channel_id, &user).await?;
+    if !channel.is_direct() {
+        return Err(channel_not_found());
+    }
+
+    let messages =
+        queries::get_channel_messages_page(data.clone(), &channel.id, limit, offset).await?;
+
+    let message_resources = to_message_resources(data.clone(), messages, Some(&user.id)).await?;
+
     Ok((StatusCode::OK, Json(json!(message_resources))))
 }
 
+/// Refuses to issue tokens to users with an active ban.
+async fn ensure_not_banned(data: Arc<AppState>, user_id: &str) -> Result<(), AppError> {
+    let ban = queries::get_active_user_ban(data, user_id).await?;
+
+    match ban {
+        Some(ban) => Err(AppError::Banned {
+            reason: ban.reason,
+            expires_at: ban.expires_at,
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Rejects users who are timed out from posting or reacting.
+async fn ensure_not_timed_out(data: Arc<AppState>, user_id: &str) -> Result<(), AppError> {
+    let timeout = queries::get_active_user_timeout(data, user_id).await?;
+
+    match timeout {
+        Some(timeout) => Err(AppError::TimedOut {
+            message: timed_out_message(&timeout),
+            expires_at: timeout.expires_at,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn invalid_credentials_error() -> AppError {
+    AppError::BadRequest("Invalid username or password".to_string())
+}
+
+fn too_many_attempts_error(retry_after: std::time::Duration) -> AppError {
+    AppError::RateLimited {
+        message: "Too many login attempts, please try again later".to_string(),
+        retry_after,
+    }
+}
+
+fn token_error(e: jsonwebtoken::errors::Error) -> AppError {
+    AppError::Internal(format!("Error while creating token: {}", e))
+}
+
+/// Persists a failed login so admins can review it. Failing to write the
+/// record must not change the outcome of the login itself.
+async fn record_failed_login(
+    data: Arc<AppState>,
+    username: &str,
+    user_id: Option<&str>,
+    ip: IpAddr,
+    reason: &str,
+) {
+    if let Err(e) =
+        queries::create_login_attempt(data, username, user_id, &ip.to_string(), reason).await
+    {
+        warn!("Failed to record login attempt for {}: {}", username, e);
+    }
+}
+
 #[axum_macros::debug_handler]
 pub async fn post_auth_token_handler(
     State(data): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     Json(body): Json<LoginRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<impl IntoResponse, AppError> {
+    let ip = addr.ip();
+
+    if let Some(retry_after) = data.login_throttle.check(&body.username, ip) {
+        data.login_throttle.record_throttled(ip);
+        return Err(too_many_attempts_error(retry_after));
+    }
+
     let user = sqlx::query_as!(
         User,
         "SELECT * FROM users WHERE username = ?",
         body.username
     )
     .fetch_optional(&data.db)
-    .await
-    .map_err(|e| {
-        let error_response = json!({
-            "status": "error",
-            "message": format!("Database error: {}", e),
-        });
-        (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-    })?
-    .ok_or_else(|| {
-        let error_response = json!({
-            "status": "fail",
-            "message": "Invalid username or password1",
-        });
-        (StatusCode::BAD_REQUEST, Json(error_response))
-    })?;
-
-    let is_valid = match PasswordHash::new(&user.password) {
-        Ok(parsed_hash) => Argon2::default()
-            .verify_password(body.password.as_bytes(), &parsed_hash)
-            .map_or(false, |_| true),
-        Err(_) => false,
-    };
+    .await?;
 
-    if !is_valid {
-        let error_response = json!({
-            "status": "fail",
-            "message": "Invalid username or password2"
-        });
-        return Err((StatusCode::BAD_REQUEST, Json(error_response)));
-    }
+    // Always run a verification, even for unknown users, so response times
+    // do not reveal whether the username exists
+    let is_valid = verify_password(
+        user.as_ref().map(|user| user.password.as_str()),
+        &body.password,
+    );
 
-    let now = chrono::Utc::now();
-    let iat = now.timestamp() as usize;
-    let exp = (now + chrono::Duration::minutes(60)).timestamp() as usize;
-    let claims: TokenClaims = TokenClaims {
-        sub: user.id.to_string(),
-        exp,
-        iat,
+    let user = match user {
+        Some(user) if is_valid => user,
+        user => {
+            data.login_throttle.record_failure(&body.username, ip);
+            record_failed_login(
+                data.clone(),
+                &body.username,
+                user.as_ref().map(|user| user.id.as_str()),
+                ip,
+                "invalid_credentials",
+            )
+            .await;
+            return Err(invalid_credentials_error());
+        }
     };
 
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(data.config.jwt_secret.as_ref()),
-    )
-    .unwrap();
+    data.login_throttle.record_success(&body.username);
+
+    // Checked after the password so bans are not revealed to guessers
+    ensure_not_banned(data.clone(), &user.id).await?;
+
+    let two_factor = queries::get_user_two_factor(data.clone(), &user.id).await?;
+
+    // With 2FA enabled the password only buys a short-lived challenge token,
+    // which has to be exchanged at /auth/token/mfa together with a code
+    if two_factor.is_some_and(|two_factor| two_factor.is_enabled()) {
+        let mfa_token =
+            create_mfa_challenge_token(&user.id, &data.config.jwt_secret).map_err(token_error)?;
+
+        return Ok((
+            StatusCode::OK,
+            Json(json!({"mfaRequired": true, "mfaToken": mfa_token})),
+        ));
+    }
+
+    let token = create_access_token(&user.id, &data.config.jwt_secret).map_err(token_error)?;
 
     Ok((
         StatusCode::OK,
@@ -191,47 +662,71 @@ pub async fn post_auth_token_handler(
 
 pub async fn get_auth_me_handler(
     Extension(user): Extension<User>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<impl IntoResponse, AppError> {
     Ok(Json(json!(user.to_auth_me_resource())))
 }
 
+const MAX_CUSTOM_STATUS_LENGTH: usize = 128;
+
+pub async fn patch_auth_me_settings_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Json(body): Json<UpdateUserSettingsRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(dm_privacy) = body.dm_privacy.as_deref() {
+        if !DM_PRIVACY_SETTINGS.contains(&dm_privacy) {
+            return Err(AppError::Validation(format!(
+                "dmPrivacy must be one of: {}",
+                DM_PRIVACY_SETTINGS.join(", ")
+            )));
+        }
+
+        queries::update_user_dm_privacy(data.clone(), &user.id, dm_privacy).await?;
+    }
+
+    if let Some(custom_status) = body.custom_status.as_deref().map(str::trim) {
+        if custom_status.chars().count() > MAX_CUSTOM_STATUS_LENGTH {
+            return Err(AppError::Validation(format!(
+                "customStatus must be at most {} characters",
+                MAX_CUSTOM_STATUS_LENGTH
+            )));
+        }
+
+        let custom_status = Some(custom_status).filter(|status| !status.is_empty());
+        queries::update_user_custom_status(data.clone(), &user.id, custom_status).await?;
+    }
+
+    if let Some(do_not_disturb) = body.do_not_disturb {
+        queries::update_user_do_not_disturb(data.clone(), &user.id, do_not_disturb).await?;
+    }
+
+    let user = queries::get_user_by_id(data.clone(), user.id).await?;
+
+    Ok((StatusCode::OK, Json(json!(user.to_auth_me_resource()))))
+}
+
 pub async fn post_register_user_handler(
     State(data): State<Arc<AppState>>,
     Json(body): Json<RegisterRequest>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<impl IntoResponse, AppError> {
     let user_exists: Option<bool> =
         sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM users WHERE username = ?)")
             .bind(body.username.to_owned())
             .fetch_one(&data.db)
-            .await
-            .map_err(|e| {
-                let error_response = serde_json::json!({
-                    "status": "fail",
-                    "message": format!("Database error: {}", e),
-                });
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-            })?;
+            .await?;
 
     if let Some(exists) = user_exists {
         if exists {
-            let error_response = serde_json::json!({
-                "status": "fail",
-                "message": "User with that username already exists",
-            });
-            return Err((StatusCode::CONFLICT, Json(error_response)));
+            return Err(AppError::Conflict(
+                "User with that username already exists".to_string(),
+            ));
         }
     }
 
     let salt = SaltString::generate(&mut OsRng);
     let hashed_password = Argon2::default()
         .hash_password(body.password.as_bytes(), &salt)
-        .map_err(|e| {
-            let error_response = serde_json::json!({
-                "status": "fail",
-                "message": format!("Error while hashing password: {}", e),
-            });
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-        })
+        .map_err(|e| AppError::Internal(format!("Error while hashing password: {}", e)))
         .map(|hash| hash.to_string())?;
 
     // let user =
@@ -244,15 +739,8 @@ pub async fn post_register_user_handler(
         body.username.to_string(),
         hashed_password
     )
-    .fetch_one(&data.db)
-    .await
-    .map_err(|e| {
-        let error_response = serde_json::json!({
-            "status": "fail",
-            "message": format!("Database error: {}", e),
-        });
-        (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
-    })?;
+    .execute(&data.db)
+    .await?;
 
     let user_response = serde_json::json!({"status": "success"});
 
@@ -261,20 +749,17 @@ pub async fn post_register_user_handler(
 
 pub async fn get_users_handler(
     State(data): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let users = queries::get_users(data.clone(), None).await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({
-                "message": format!("Error: {}", e),
-            })),
-        )
-    })?;
+    Extension(viewer): Extension<User>,
+) -> Result<impl IntoResponse, AppError> {
+    let users = queries::get_users(data.clone(), None).await?;
+
+    let blocker_ids = queries::get_blocker_ids(data.clone(), &viewer.id).await?;
 
     let user_resources = users
         .iter()
         .map(|user| {
-            let user_resource = user.to_resource();
+            let mut user_resource = user.to_resource();
+            hide_custom_status(&mut user_resource, &blocker_ids);
             let connection_state = match data.connected_users.get(&user.id) {
                 Some(connection) => ConnectionStateResource {
                     isOnline: true,
@@ -302,6 +787,59 @@ pub async fn get_users_handler(
     Ok((StatusCode::OK, Json(json!(user_resources))))
 }
 
+pub async fn get_blocked_users_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse, AppError> {
+    let blocked_users = queries::get_blocked_users(data.clone(), &user.id).await?;
+
+    let user_resources = blocked_users
+        .iter()
+        .map(|blocked_user| blocked_user.to_resource())
+        .collect::<Vec<UserResource>>();
+
+    Ok((StatusCode::OK, Json(json!(user_resources))))
+}
+
+pub async fn put_user_block_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(user_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    if user_id == user.id {
+        return Err(AppError::Validation(
+            "You cannot block yourself".to_string(),
+        ));
+    }
+
+    let blocked_user = queries::get_users(data.clone(), Some(&[user_id.clone()][..]))
+        .await?
+        .pop()
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    // Blocking twice is a no-op
+    if queries::block_user(data.clone(), &user.id, &blocked_user.id).await? {
+        info!("User {} blocked user {}", user.id, blocked_user.id);
+    }
+
+    Ok((StatusCode::OK, Json(json!(blocked_user.to_resource()))))
+}
+
+pub async fn delete_user_block_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(user_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let unblocked = queries::unblock_user(data.clone(), &user.id, &user_id).await?;
+
+    if !unblocked {
+        return Err(AppError::NotFound("User is not blocked".to_string()));
+    }
+
+    info!("User {} unblocked user {}", user.id, user_id);
+
+    Ok((StatusCode::OK, Json(json!({ "status": "success" }))))
+}
 
 #[derive(Debug, Deserialize)]
 pub struct LinkPreviewQueryParams {
@@ -311,12 +849,11 @@ pub struct LinkPreviewQueryParams {
 pub async fn get_link_preview_handler(
     State(data): State<Arc<AppState>>,
     Query(params): Query<LinkPreviewQueryParams>,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+) -> Result<impl IntoResponse, AppError> {
     // Check if url param is provided
     let Some(mut url) = params.url else {
-        return Err((
-            StatusCode::UNPROCESSABLE_ENTITY,
-            Json(json!({ "error": "Missing 'url' query parameter."})),
+        return Err(AppError::Validation(
+            "Missing 'url' query parameter.".to_string(),
         ));
     };
 
@@ -327,18 +864,1904 @@ pub async fn get_link_preview_handler(
 
     // Validate URL format
     if Url::parse(&url).is_err() {
-        return Err((
-            StatusCode::UNPROCESSABLE_ENTITY,
-            Json(json!({ "error": "Malformed url"})),
-        )); 
+        return Err(AppError::Validation("Malformed url".to_string()));
     }
 
     // Step 4: Call preview service
-    match get_url_preview(&url).await {
-        Ok(preview) => Ok((StatusCode::OK, Json(json!(preview)))),
-        Err(e) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(json!({ "error": e.to_string() })),
-        )),
+    match get_cached_preview(data.clone(), &url).await {
+        Ok(preview) => {
+            let mut preview = preview.as_ref().clone();
+            proxy_preview_images(&mut preview, &data.config.jwt_secret);
+            Ok((StatusCode::OK, Json(json!(preview))))
+        }
+        Err(failure) if failure.is_rejected_url => Err(AppError::Validation(failure.message)),
+        Err(failure) => Err(AppError::Upstream(failure.message)),
     }
 }
+
+#[derive(Debug, Deserialize)]
+pub struct ImageProxyQueryParams {
+    url: String,
+    sig: String,
+}
+
+/// Serves a remote image signed by this server. Public so that `<img>`
+/// tags, which cannot send a bearer token, can load it.
+pub async fn get_image_proxy_handler(
+    State(data): State<Arc<AppState>>,
+    Query(params): Query<ImageProxyQueryParams>,
+) -> Result<impl IntoResponse, AppError> {
+    let image = get_proxied_image(data.clone(), &params.url, &params.sig).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, image.content_type),
+            (header::CACHE_CONTROL, "public, max-age=86400, immutable"),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ],
+        image.bytes.clone(),
+    ))
+}
+
+pub async fn delete_link_previews_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Query(params): Query<LinkPreviewQueryParams>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_link_previews").await?;
+
+    // Without a url every cached preview is purged
+    let url = match params.url.as_deref() {
+        Some(url) => Some(
+            normalize_url(url).ok_or_else(|| AppError::Validation("Malformed url".to_string()))?,
+        ),
+        None => None,
+    };
+
+    let purged = purge_previews(data.clone(), url.as_ref()).await?;
+
+    info!("User {} purged {} cached link previews", user.id, purged);
+
+    // A full purge has no single preview to point at
+    let target_id = url
+        .as_ref()
+        .map(|url| url_hash(url.as_str()))
+        .unwrap_or_else(|| "*".to_string());
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::LINK_PREVIEWS_PURGED,
+        audit_target_types::LINK_PREVIEW,
+        &target_id,
+        None,
+        audit_log::diff(
+            &json!({ "url": url.as_ref().map(|url| url.as_str()), "purged": purged }),
+            &serde_json::Value::Null,
+        ),
+    )
+    .await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "status": "success", "purged": purged })),
+    ))
+}
+
+async fn require_permission(
+    data: Arc<AppState>,
+    user: &User,
+    permission: &str,
+) -> Result<(), AppError> {
+    let is_allowed = queries::user_has_permission(data, &user.id, permission).await?;
+
+    if !is_allowed {
+        return Err(AppError::Forbidden(format!(
+            "Missing permission: {}",
+            permission
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn post_two_factor_enroll_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse, AppError> {
+    let existing = queries::get_user_two_factor(data.clone(), &user.id).await?;
+
+    if existing.is_some_and(|two_factor| two_factor.is_enabled()) {
+        return Err(AppError::Conflict(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+
+    let secret = two_factor::generate_secret();
+    let otpauth_uri =
+        two_factor::otpauth_uri(&secret, &user.username).map_err(AppError::Internal)?;
+
+    queries::upsert_pending_two_factor(data.clone(), &user.id, &secret).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "secret": secret, "otpauthUri": otpauth_uri })),
+    ))
+}
+
+pub async fn post_two_factor_confirm_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Json(body): Json<TwoFactorConfirmRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let pending = queries::get_user_two_factor(data.clone(), &user.id)
+        .await?
+        .filter(|two_factor| !two_factor.is_enabled())
+        .ok_or_else(|| {
+            AppError::Conflict(
+                "No pending two-factor enrolment, call /auth/2fa/enroll first".to_string(),
+            )
+        })?;
+
+    let used_step = two_factor::verify_code(&pending.secret, &body.code, None)
+        .ok_or_else(|| AppError::BadRequest("Invalid verification code".to_string()))?;
+
+    let recovery_codes = two_factor::generate_recovery_codes();
+    let recovery_code_hashes = recovery_codes
+        .iter()
+        .map(|code| two_factor::hash_recovery_code(code))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(AppError::Internal)?;
+
+    queries::confirm_two_factor(data.clone(), &user.id, used_step, &recovery_code_hashes).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "status": "success", "recoveryCodes": recovery_codes })),
+    ))
+}
+
+async fn reject_mfa_code(
+    data: Arc<AppState>,
+    throttle_key: &str,
+    user_id: &str,
+    ip: IpAddr,
+) -> AppError {
+    data.login_throttle.record_failure(throttle_key, ip);
+    record_failed_login(data, throttle_key, Some(user_id), ip, "invalid_mfa_code").await;
+
+    AppError::BadRequest("Invalid verification code".to_string())
+}
+
+pub async fn post_auth_token_mfa_handler(
+    State(data): State<Arc<AppState>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(body): Json<MfaLoginRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let invalid_challenge = || AppError::Unauthorized("Invalid or expired MFA token".to_string());
+
+    let claims = parse_mfa_challenge_token(&body.mfa_token, &data.config.jwt_secret)
+        .map_err(|_| invalid_challenge())?;
+    let user_id = extract_user_id(&claims)
+        .map_err(|_| invalid_challenge())?
+        .to_string();
+
+    // MFA attempts are throttled per user so the 6 digit code space cannot
+    // be brute forced with a single challenge token
+    let ip = addr.ip();
+    let throttle_key = format!("mfa:{}", user_id);
+
+    if let Some(retry_after) = data.login_throttle.check(&throttle_key, ip) {
+        data.login_throttle.record_throttled(ip);
+        return Err(too_many_attempts_error(retry_after));
+    }
+
+    let two_factor = queries::get_user_two_factor(data.clone(), &user_id)
+        .await?
+        .filter(|two_factor| two_factor.is_enabled())
+        .ok_or_else(invalid_challenge)?;
+
+    if let Some(code) = body.code.as_deref() {
+        let Some(used_step) =
+            two_factor::verify_code(&two_factor.secret, code, two_factor.last_used_step)
+        else {
+            return Err(reject_mfa_code(data.clone(), &throttle_key, &user_id, ip).await);
+        };
+
+        // Two requests racing with the same code both pass `verify_code`;
+        // only the one that advances the step may log in
+        let advanced =
+            queries::update_two_factor_last_used_step(data.clone(), &user_id, used_step).await?;
+
+        if !advanced {
+            return Err(reject_mfa_code(data.clone(), &throttle_key, &user_id, ip).await);
+        }
+    } else if let Some(recovery_code) = body.recovery_code.as_deref() {
+        let codes = queries::get_unused_recovery_codes(data.clone(), &user_id).await?;
+
+        let matched = codes
+            .into_iter()
+            .find(|code| two_factor::verify_recovery_code(recovery_code, &code.code_hash));
+
+        let Some(matched) = matched else {
+            return Err(reject_mfa_code(data.clone(), &throttle_key, &user_id, ip).await);
+        };
+
+        let consumed = queries::mark_recovery_code_used(data.clone(), &matched.id).await?;
+
+        if !consumed {
+            return Err(reject_mfa_code(data.clone(), &throttle_key, &user_id, ip).await);
+        }
+    } else {
+        return Err(AppError::Validation(
+            "Either 'code' or 'recoveryCode' must be provided".to_string(),
+        ));
+    }
+
+    data.login_throttle.record_success(&throttle_key);
+
+    ensure_not_banned(data.clone(), &user_id).await?;
+
+    let token = create_access_token(&user_id, &data.config.jwt_secret).map_err(token_error)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({"accessToken": token, "refreshToken": ""})),
+    ))
+}
+
+fn two_factor_not_set_up() -> AppError {
+    AppError::NotFound("User has no two-factor authentication set up".to_string())
+}
+
+pub async fn delete_user_two_factor_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(user_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_users").await?;
+
+    let two_factor = queries::get_user_two_factor(data.clone(), &user_id)
+        .await?
+        .ok_or_else(two_factor_not_set_up)?;
+
+    if !queries::delete_two_factor(data.clone(), &user_id).await? {
+        return Err(two_factor_not_set_up());
+    }
+
+    info!(
+        "User {} reset two-factor authentication of user {}",
+        user.id, user_id
+    );
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::USER_TWO_FACTOR_RESET,
+        audit_target_types::USER,
+        &user_id,
+        None,
+        audit_log::diff(
+            &json!({ "twoFactorEnabled": two_factor.is_enabled() }),
+            &json!({ "twoFactorEnabled": false }),
+        ),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(json!({ "status": "success" }))))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginAttemptsQueryParams {
+    username: Option<String>,
+    ip: Option<String>,
+    limit: Option<i64>,
+}
+
+pub async fn get_login_attempts_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Query(params): Query<LoginAttemptsQueryParams>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_users").await?;
+
+    let limit = params.limit.unwrap_or(100).clamp(1, 500);
+    let attempts = queries::get_login_attempts(
+        data.clone(),
+        params.username.as_deref(),
+        params.ip.as_deref(),
+        limit,
+    )
+    .await?;
+
+    let attempt_resources = attempts
+        .iter()
+        .map(|attempt| attempt.to_resource())
+        .collect::<Vec<LoginAttemptResource>>();
+
+    Ok((StatusCode::OK, Json(json!(attempt_resources))))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditLogQueryParams {
+    #[serde(rename = "actorId")]
+    actor_id: Option<String>,
+    action: Option<String>,
+    #[serde(rename = "targetType")]
+    target_type: Option<String>,
+    #[serde(rename = "targetId")]
+    target_id: Option<String>,
+    after: Option<chrono::DateTime<chrono::Utc>>,
+    before: Option<chrono::DateTime<chrono::Utc>>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+pub async fn get_audit_log_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Query(params): Query<AuditLogQueryParams>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "view_audit_log").await?;
+
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let filters = AuditLogFilters {
+        actor_user_id: params.actor_id,
+        action: params.action,
+        target_type: params.target_type,
+        target_id: params.target_id,
+        created_after: params.after,
+        created_before: params.before,
+    };
+
+    let entries = queries::get_audit_log_entries(data.clone(), &filters, limit, offset)
+        .await?
+        .iter()
+        .map(|entry| entry.to_resource())
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(json!(entries))))
+}
+
+/// Validates the target and reason shared by bans and timeouts.
+async fn get_sanction_target(
+    data: Arc<AppState>,
+    moderator: &User,
+    user_id: &str,
+    reason: Option<&str>,
+) -> Result<User, AppError> {
+    if user_id == moderator.id {
+        return Err(AppError::Validation(
+            "You cannot sanction yourself".to_string(),
+        ));
+    }
+
+    if reason.is_some_and(|reason| reason.chars().count() > MAX_REASON_LENGTH) {
+        return Err(AppError::Validation(format!(
+            "Reason must be at most {} characters",
+            MAX_REASON_LENGTH
+        )));
+    }
+
+    match queries::get_user_by_id(data, user_id.to_string()).await {
+        Ok(user) => Ok(user),
+        Err(sqlx::Error::RowNotFound) => Err(AppError::NotFound("User not found".to_string())),
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn get_bans_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "ban_users").await?;
+
+    let bans = queries::get_active_user_bans(data.clone())
+        .await?
+        .iter()
+        .map(|ban| ban.to_resource())
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(json!(bans))))
+}
+
+pub async fn post_ban_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Json(body): Json<CreateBanRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "ban_users").await?;
+
+    let ban = ban_user(
+        data.clone(),
+        &user,
+        &body.user_id,
+        body.reason.as_deref(),
+        body.expires_at,
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(json!(ban.to_resource()))))
+}
+
+/// Bans, disconnects and audits. The caller checks the permission.
+async fn ban_user(
+    data: Arc<AppState>,
+    user: &User,
+    user_id: &str,
+    reason: Option<&str>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<UserBan, AppError> {
+    let target = get_sanction_target(data.clone(), user, user_id, reason).await?;
+
+    if expires_at.is_some_and(|expires_at| expires_at <= chrono::Utc::now()) {
+        return Err(AppError::Validation(
+            "expiresAt must be in the future".to_string(),
+        ));
+    }
+
+    let ban =
+        queries::create_user_ban(data.clone(), &target.id, reason, &user.id, expires_at).await?;
+
+    info!("User {} banned user {} ({})", user.id, target.id, ban.id);
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::USER_BANNED,
+        audit_target_types::USER,
+        &target.id,
+        ban.reason.as_deref(),
+        audit_log::diff(&serde_json::Value::Null, &json!(ban.to_resource())),
+    )
+    .await;
+
+    announce_ban(data.clone(), &ban, user).await;
+
+    Ok(ban)
+}
+
+pub async fn delete_ban_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(ban_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "ban_users").await?;
+
+    let ban_not_found = || AppError::NotFound("Ban not found".to_string());
+
+    let ban = queries::get_user_ban(data.clone(), &ban_id)
+        .await?
+        .ok_or_else(ban_not_found)?;
+
+    let lifted = queries::lift_user_ban(data.clone(), &ban.id, &user.id).await?;
+
+    if !lifted {
+        return Err(ban_not_found());
+    }
+
+    info!("User {} lifted ban {}", user.id, ban.id);
+
+    if let Some(lifted_ban) = queries::get_user_ban(data.clone(), &ban.id).await? {
+        audit_log::record(
+            data.clone(),
+            &user.id,
+            audit_actions::USER_UNBANNED,
+            audit_target_types::USER,
+            &ban.user_id,
+            None,
+            audit_log::diff(&json!(ban.to_resource()), &json!(lifted_ban.to_resource())),
+        )
+        .await;
+    }
+
+    Ok((StatusCode::OK, Json(json!({ "status": "success" }))))
+}
+
+pub async fn get_timeouts_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "timeout_users").await?;
+
+    let timeouts = queries::get_active_user_timeouts(data.clone())
+        .await?
+        .iter()
+        .map(|timeout| timeout.to_resource())
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(json!(timeouts))))
+}
+
+pub async fn post_timeout_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Json(body): Json<CreateTimeoutRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "timeout_users").await?;
+
+    let timeout = timeout_user(
+        data.clone(),
+        &user,
+        &body.user_id,
+        body.reason.as_deref(),
+        body.duration_seconds,
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(json!(timeout.to_resource()))))
+}
+
+/// Times out, notifies and audits. The caller checks the permission.
+async fn timeout_user(
+    data: Arc<AppState>,
+    user: &User,
+    user_id: &str,
+    reason: Option<&str>,
+    duration_seconds: i64,
+) -> Result<UserTimeout, AppError> {
+    let target = get_sanction_target(data.clone(), user, user_id, reason).await?;
+
+    let duration = chrono::Duration::seconds(duration_seconds);
+    if duration <= chrono::Duration::zero() || duration > MAX_TIMEOUT {
+        return Err(AppError::Validation(format!(
+            "durationSeconds must be between 1 and {}",
+            MAX_TIMEOUT.num_seconds()
+        )));
+    }
+
+    let timeout = queries::create_user_timeout(
+        data.clone(),
+        &target.id,
+        reason,
+        &user.id,
+        chrono::Utc::now() + duration,
+    )
+    .await?;
+
+    info!(
+        "User {} timed out user {} until {}",
+        user.id, target.id, timeout.expires_at
+    );
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::USER_TIMED_OUT,
+        audit_target_types::USER,
+        &target.id,
+        timeout.reason.as_deref(),
+        audit_log::diff(&serde_json::Value::Null, &json!(timeout.to_resource())),
+    )
+    .await;
+
+    announce_timeout(data.clone(), &timeout, user).await;
+
+    Ok(timeout)
+}
+
+pub async fn delete_timeout_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(timeout_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "timeout_users").await?;
+
+    let timeout_not_found = || AppError::NotFound("Timeout not found".to_string());
+
+    let timeout = queries::get_user_timeout(data.clone(), &timeout_id)
+        .await?
+        .ok_or_else(timeout_not_found)?;
+
+    let lifted = queries::lift_user_timeout(data.clone(), &timeout.id, &user.id).await?;
+
+    if !lifted {
+        return Err(timeout_not_found());
+    }
+
+    info!("User {} lifted timeout {}", user.id, timeout.id);
+
+    if let Some(lifted_timeout) = queries::get_user_timeout(data.clone(), &timeout.id).await? {
+        audit_log::record(
+            data.clone(),
+            &user.id,
+            audit_actions::USER_TIMEOUT_LIFTED,
+            audit_target_types::USER,
+            &timeout.user_id,
+            None,
+            audit_log::diff(
+                &json!(timeout.to_resource()),
+                &json!(lifted_timeout.to_resource()),
+            ),
+        )
+        .await;
+    }
+
+    Ok((StatusCode::OK, Json(json!({ "status": "success" }))))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportsQueryParams {
+    status: Option<String>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+/// The moderation queue. Without `status` only pending reports are listed.
+pub async fn get_reports_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Query(params): Query<ReportsQueryParams>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, MODERATE_REPORTS_PERMISSION).await?;
+
+    let statuses = match params.status.as_deref() {
+        None => vec![REPORT_STATUS_OPEN, REPORT_STATUS_CLAIMED],
+        Some(status) => match REPORT_STATUSES.iter().find(|s| **s == status) {
+            Some(status) => vec![*status],
+            None => {
+                return Err(AppError::Validation(format!(
+                    "status must be one of {:?}",
+                    REPORT_STATUSES
+                )));
+            }
+        },
+    };
+
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let reports = queries::get_reports(data.clone(), &statuses, limit, offset)
+        .await?
+        .iter()
+        .map(|report| report.to_resource())
+        .collect::<Vec<_>>();
+
+    Ok((StatusCode::OK, Json(json!(reports))))
+}
+
+fn report_not_found() -> AppError {
+    AppError::NotFound("Report not found".to_string())
+}
+
+fn report_already_closed() -> AppError {
+    AppError::Conflict("Report is already closed".to_string())
+}
+
+fn report_claimed_by_other() -> AppError {
+    AppError::Forbidden("Report is claimed by another moderator".to_string())
+}
+
+pub async fn post_report_claim_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(report_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, MODERATE_REPORTS_PERMISSION).await?;
+
+    let report = queries::get_report(data.clone(), &report_id)
+        .await?
+        .ok_or_else(report_not_found)?;
+
+    if !report.is_pending() {
+        return Err(report_already_closed());
+    }
+
+    let claimed = queries::claim_report(data.clone(), &report.id, &user.id).await?;
+
+    if !claimed {
+        return Err(AppError::Conflict("Report is already claimed".to_string()));
+    }
+
+    let report = queries::get_report(data.clone(), &report.id)
+        .await?
+        .ok_or_else(report_not_found)?;
+
+    info!("User {} claimed report {}", user.id, report.id);
+
+    notify_moderators(data.clone(), socket_publish_events::UPDATE_REPORT, &report).await;
+
+    Ok((StatusCode::OK, Json(json!(report.to_resource()))))
+}
+
+/// Closes a report, first deleting the reported message, timing out or
+/// banning its author as requested.
+pub async fn post_report_resolve_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(report_id): Path<String>,
+    Json(body): Json<ResolveReportRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, MODERATE_REPORTS_PERMISSION).await?;
+
+    let report = queries::get_report(data.clone(), &report_id)
+        .await?
+        .ok_or_else(report_not_found)?;
+
+    if !report.is_pending() {
+        return Err(report_already_closed());
+    }
+    if report
+        .claimed_by_user_id
+        .as_ref()
+        .is_some_and(|claimant| *claimant != user.id)
+    {
+        return Err(report_claimed_by_other());
+    }
+
+    let note = body
+        .note
+        .as_deref()
+        .map(str::trim)
+        .filter(|note| !note.is_empty());
+    if note.is_some_and(|note| note.chars().count() > MAX_REASON_LENGTH) {
+        return Err(AppError::Validation(format!(
+            "Note must be at most {} characters",
+            MAX_REASON_LENGTH
+        )));
+    }
+
+    // Everything that can be checked up front is, so the report is rarely
+    // reopened below
+    let (status, resolution) = match body.action.as_str() {
+        REPORT_RESOLUTION_DELETE => {
+            if report.message_id.is_none() {
+                return Err(AppError::Validation(
+                    "Only message reports can be resolved by deleting".to_string(),
+                ));
+            }
+
+            (REPORT_STATUS_RESOLVED, REPORT_RESOLUTION_DELETE)
+        }
+        REPORT_RESOLUTION_TIMEOUT => {
+            require_permission(data.clone(), &user, "timeout_users").await?;
+
+            if body.duration_seconds.is_none() {
+                return Err(AppError::Validation(
+                    "durationSeconds is required".to_string(),
+                ));
+            }
+
+            (REPORT_STATUS_RESOLVED, REPORT_RESOLUTION_TIMEOUT)
+        }
+        REPORT_RESOLUTION_BAN => {
+            require_permission(data.clone(), &user, "ban_users").await?;
+
+            (REPORT_STATUS_RESOLVED, REPORT_RESOLUTION_BAN)
+        }
+        REPORT_RESOLUTION_DISMISS => (REPORT_STATUS_DISMISSED, REPORT_RESOLUTION_DISMISS),
+        _ => {
+            return Err(AppError::Validation(
+                "action must be one of delete, timeout, ban or dismiss".to_string(),
+            ));
+        }
+    };
+
+    // Closing first makes concurrent resolutions apply their action once
+    let closed =
+        queries::close_report(data.clone(), &report.id, &user.id, status, resolution, note).await?;
+
+    if !closed {
+        return Err(AppError::Conflict(
+            "Report was closed or claimed by another moderator".to_string(),
+        ));
+    }
+
+    let applied = match resolution {
+        REPORT_RESOLUTION_DELETE => match report.message_id.as_deref() {
+            Some(message_id) => {
+                delete_reported_message(data.clone(), &user, &report, message_id, note).await
+            }
+            None => Ok(()),
+        },
+        REPORT_RESOLUTION_TIMEOUT => timeout_user(
+            data.clone(),
+            &user,
+            &report.reported_user_id,
+            note,
+            body.duration_seconds.unwrap_or_default(),
+        )
+        .await
+        .map(|_| ()),
+        REPORT_RESOLUTION_BAN => ban_user(
+            data.clone(),
+            &user,
+            &report.reported_user_id,
+            note,
+            body.expires_at,
+        )
+        .await
+        .map(|_| ()),
+        _ => Ok(()),
+    };
+
+    if let Err(e) = applied {
+        // Hand the report back to this moderator so it can be retried
+        if let Err(reopen_error) = queries::reopen_report(data.clone(), &report.id, &user.id).await
+        {
+            warn!("Failed to reopen report {}: {}", report.id, reopen_error);
+        }
+        return Err(e);
+    }
+
+    let closed_report = queries::get_report(data.clone(), &report.id)
+        .await?
+        .ok_or_else(report_not_found)?;
+
+    info!(
+        "User {} closed report {} with {}",
+        user.id, report.id, resolution
+    );
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        if status == REPORT_STATUS_DISMISSED {
+            audit_actions::REPORT_DISMISSED
+        } else {
+            audit_actions::REPORT_RESOLVED
+        },
+        audit_target_types::REPORT,
+        &report.id,
+        note,
+        audit_log::diff(
+            &json!(report.to_resource()),
+            &json!(closed_report.to_resource()),
+        ),
+    )
+    .await;
+
+    notify_moderators(
+        data.clone(),
+        socket_publish_events::UPDATE_REPORT,
+        &closed_report,
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(json!(closed_report.to_resource()))))
+}
+
+/// Deletes the reported message and tells everyone who can see it.
+/// Deleting a message that is already gone is not an error.
+async fn delete_reported_message(
+    data: Arc<AppState>,
+    user: &User,
+    report: &Report,
+    message_id: &str,
+    note: Option<&str>,
+) -> Result<(), AppError> {
+    let deleted = queries::delete_message(data.clone(), message_id, &user.id).await?;
+
+    if !deleted {
+        return Ok(());
+    }
+
+    info!(
+        "User {} deleted message {} for report {}",
+        user.id, message_id, report.id
+    );
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::MESSAGE_DELETED,
+        audit_target_types::MESSAGE,
+        message_id,
+        note,
+        audit_log::diff(&json!({ "deleted": false }), &json!({ "deleted": true })),
+    )
+    .await;
+
+    // Direct conversations are looked up through the reporter, who is a
+    // participant
+    let channel = match &report.channel_id {
+        Some(channel_id) => {
+            queries::get_accessible_channel(data.clone(), channel_id, &report.reporter_user_id)
+                .await?
+        }
+        None => None,
+    };
+    let message = queries::get_message_by_id(data.clone(), message_id).await?;
+
+    if let (Some(channel), Some(message)) = (channel, message) {
+        let message_resource = to_message_resources(data.clone(), vec![message], None)
+            .await?
+            .pop();
+
+        if let Some(message_resource) = message_resource {
+            emit_message_update(data.clone(), &channel, &message_resource).await;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn post_bot_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Json(body): Json<CreateBotRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_users").await?;
+
+    let username = body.username.trim();
+    if username.is_empty() {
+        return Err(AppError::Validation(
+            "Username must not be empty".to_string(),
+        ));
+    }
+
+    let display_name = body
+        .display_name
+        .as_deref()
+        .map(|display_name| display_name.trim())
+        .filter(|display_name| !display_name.is_empty())
+        .unwrap_or(username);
+
+    let bot = queries::create_bot_user(data.clone(), username, display_name)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(db_error) if db_error.is_unique_violation() => {
+                AppError::Conflict(
+                    "User with that username or display name already exists".to_string(),
+                )
+            }
+            e => e.into(),
+        })?;
+
+    info!("User {} created bot user {}", user.id, bot.id);
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::BOT_CREATED,
+        audit_target_types::USER,
+        &bot.id,
+        None,
+        audit_log::diff(&serde_json::Value::Null, &json!(bot.to_resource())),
+    )
+    .await;
+
+    Ok((StatusCode::CREATED, Json(json!(bot.to_resource()))))
+}
+
+pub async fn get_bots_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_users").await?;
+
+    let bots = queries::get_bot_users(data.clone()).await?;
+
+    let bot_resources = bots
+        .iter()
+        .map(|bot| bot.to_resource())
+        .collect::<Vec<UserResource>>();
+
+    Ok((StatusCode::OK, Json(json!(bot_resources))))
+}
+
+async fn get_bot_or_404(data: Arc<AppState>, bot_id: String) -> Result<User, AppError> {
+    match queries::get_user_by_id(data, bot_id).await {
+        Ok(bot) if bot.is_bot() => Ok(bot),
+        Ok(_) | Err(sqlx::Error::RowNotFound) => {
+            Err(AppError::NotFound("Bot not found".to_string()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn post_bot_token_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(bot_id): Path<String>,
+    Json(body): Json<CreateApiTokenRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_users").await?;
+
+    let bot = get_bot_or_404(data.clone(), bot_id).await?;
+
+    if body.scopes.is_empty()
+        || body
+            .scopes
+            .iter()
+            .any(|scope| !API_TOKEN_SCOPES.contains(&scope.as_str()))
+    {
+        return Err(AppError::Validation(format!(
+            "Scopes must be a non-empty subset of {:?}",
+            API_TOKEN_SCOPES
+        )));
+    }
+
+    let token = generate_api_token();
+    let api_token = queries::create_api_token(
+        data.clone(),
+        &bot.id,
+        &body.name,
+        &token,
+        &body.scopes,
+        body.expires_at,
+        &user.id,
+    )
+    .await?;
+
+    info!(
+        "User {} created API token {} for bot {}",
+        user.id, api_token.id, bot.id
+    );
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::API_TOKEN_CREATED,
+        audit_target_types::API_TOKEN,
+        &api_token.id,
+        None,
+        audit_log::diff(&serde_json::Value::Null, &json!(api_token.to_resource())),
+    )
+    .await;
+
+    // The plain token is only ever returned here
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "token": token, "apiToken": api_token.to_resource() })),
+    ))
+}
+
+pub async fn get_bot_tokens_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(bot_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_users").await?;
+
+    let bot = get_bot_or_404(data.clone(), bot_id).await?;
+
+    let tokens = queries::get_api_tokens_for_user(data.clone(), &bot.id).await?;
+
+    let token_resources = tokens
+        .iter()
+        .map(|token| token.to_resource())
+        .collect::<Vec<ApiTokenResource>>();
+
+    Ok((StatusCode::OK, Json(json!(token_resources))))
+}
+
+pub async fn delete_bot_token_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path((bot_id, token_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_users").await?;
+
+    let revoked = queries::revoke_api_token(data.clone(), &bot_id, &token_id).await?;
+
+    if !revoked {
+        return Err(AppError::NotFound("Token not found".to_string()));
+    }
+
+    info!(
+        "User {} revoked API token {} of bot {}",
+        user.id, token_id, bot_id
+    );
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::API_TOKEN_REVOKED,
+        audit_target_types::API_TOKEN,
+        &token_id,
+        None,
+        audit_log::diff(&json!({ "revoked": false }), &json!({ "revoked": true })),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(json!({ "status": "success" }))))
+}
+
+pub async fn post_channel_message_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(channel_id): Path<String>,
+    Json(body): Json<CreateMessageRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    if !user.is_bot() {
+        return Err(AppError::Forbidden(
+            "Only bot accounts can post messages over REST".to_string(),
+        ));
+    }
+
+    if body
+        .content
+        .as_deref()
+        .map_or(true, |content| content.trim().is_empty())
+    {
+        return Err(AppError::Validation(
+            "Message content must not be empty".to_string(),
+        ));
+    }
+
+    let channel = get_accessible_channel(data.clone(), &channel_id, &user).await?;
+
+    ensure_not_timed_out(data.clone(), &user.id).await?;
+
+    let is_blocked = is_blocked_in_channel(data.clone(), &user.id, &channel).await?;
+
+    if is_blocked {
+        return Err(AppError::Blocked(
+            "You cannot message this user".to_string(),
+        ));
+    }
+
+    validate_message_references(
+        data.clone(),
+        &channel.id,
+        body.reply_to_message_id.as_deref(),
+        body.thread_id.as_deref(),
+    )
+    .await?;
+
+    let verdict = screen_message(
+        data.clone(),
+        &user,
+        &channel,
+        body.content.as_deref().unwrap_or_default(),
+    )
+    .await?;
+    if let AutomodVerdict::Refuse(violation) = &verdict {
+        return Err(violation.into());
+    }
+
+    let message = queries::create_message(
+        data.clone(),
+        user.id.clone(),
+        channel.id.clone(),
+        body.content,
+        CreateMessageOptions {
+            reply_to_message_id: body.reply_to_message_id,
+            thread_id: body.thread_id,
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    let mentions = record_mentions(data.clone(), &message, &user).await?;
+
+    let mut resources = [message.to_resource(user.to_resource())];
+    attach_reply_references(data.clone(), &mut resources).await?;
+    let [mut message_resource] = resources;
+    message_resource.mentions = mentions
+        .iter()
+        .map(|mention| mention.to_resource())
+        .collect();
+
+    let payload = ReceiveChatMessagePayload {
+        message: message_resource,
+        thread: thread_summary_for(data.clone(), &message).await?,
+    };
+
+    if !channel.is_direct() {
+        spawn_dispatch_event(
+            data.clone(),
+            event_types::MESSAGE_CREATED,
+            json!({ "message": payload.message }),
+        );
+    }
+
+    emit_chat_message(data.clone(), &channel, &payload).await;
+
+    if let AutomodVerdict::Flag(violation) = &verdict {
+        flag_message(data.clone(), &message, &user, violation).await;
+    }
+
+    spawn_unfurl(data.clone(), &message);
+
+    notify_mentions(data.clone(), &channel, &payload.message, &mentions).await;
+
+    Ok((StatusCode::CREATED, Json(json!(payload.message))))
+}
+
+const WEBHOOK_TOKEN_LENGTH: usize = 48;
+const WEBHOOK_DEFAULT_RATE_LIMIT_PER_MINUTE: i32 = 30;
+const WEBHOOK_MAX_RATE_LIMIT_PER_MINUTE: i32 = 600;
+const WEBHOOK_MAX_CONTENT_LENGTH: usize = 4000;
+const WEBHOOK_MAX_USERNAME_LENGTH: usize = 80;
+
+fn webhook_not_found() -> AppError {
+    AppError::NotFound("Webhook not found".to_string())
+}
+
+fn validate_webhook_avatar_url(avatar_url: Option<&str>) -> Result<(), AppError> {
+    let Some(avatar_url) = avatar_url else {
+        return Ok(());
+    };
+
+    match Url::parse(avatar_url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(()),
+        _ => Err(AppError::Validation("Malformed avatar url".to_string())),
+    }
+}
+
+pub async fn get_channel_webhooks_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(channel_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_webhooks").await?;
+
+    let webhooks = queries::get_channel_incoming_webhooks(data.clone(), &channel_id).await?;
+
+    let webhook_resources = webhooks
+        .iter()
+        .map(|webhook| webhook.to_resource())
+        .collect::<Vec<IncomingWebhookResource>>();
+
+    Ok((StatusCode::OK, Json(json!(webhook_resources))))
+}
+
+pub async fn post_channel_webhook_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(channel_id): Path<String>,
+    Json(body): Json<CreateIncomingWebhookRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_webhooks").await?;
+
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(AppError::Validation(
+            "Webhook name must not be empty".to_string(),
+        ));
+    }
+
+    validate_webhook_avatar_url(body.avatar_url.as_deref())?;
+
+    let channel = queries::get_channel_by_id(data.clone(), &channel_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Channel not found".to_string()))?;
+
+    let rate_limit_per_minute = body
+        .rate_limit_per_minute
+        .unwrap_or(WEBHOOK_DEFAULT_RATE_LIMIT_PER_MINUTE)
+        .clamp(1, WEBHOOK_MAX_RATE_LIMIT_PER_MINUTE);
+
+    let token = generate_secret_token(WEBHOOK_TOKEN_LENGTH);
+    let webhook = queries::create_incoming_webhook(
+        data.clone(),
+        &channel.id,
+        name,
+        body.avatar_url.as_deref(),
+        &token,
+        rate_limit_per_minute,
+        &user.id,
+    )
+    .await?;
+
+    info!(
+        "User {} created webhook {} in channel {}",
+        user.id, webhook.id, channel.id
+    );
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::WEBHOOK_CREATED,
+        audit_target_types::WEBHOOK,
+        &webhook.id,
+        None,
+        audit_log::diff(&serde_json::Value::Null, &json!(webhook.to_resource())),
+    )
+    .await;
+
+    // The token is part of the webhook URL and is only ever returned here
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "token": token,
+            "path": format!("/webhooks/{}/{}", webhook.id, token),
+            "webhook": webhook.to_resource(),
+        })),
+    ))
+}
+
+pub async fn patch_webhook_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(webhook_id): Path<String>,
+    Json(body): Json<UpdateIncomingWebhookRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_webhooks").await?;
+
+    let webhook = queries::get_incoming_webhook(data.clone(), &webhook_id)
+        .await?
+        .ok_or_else(webhook_not_found)?;
+
+    validate_webhook_avatar_url(body.avatar_url.as_ref().and_then(|url| url.as_deref()))?;
+
+    let name = body
+        .name
+        .as_deref()
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .unwrap_or(&webhook.name);
+    // An explicit null clears the avatar
+    let avatar_url = match &body.avatar_url {
+        Some(avatar_url) => avatar_url.as_deref(),
+        None => webhook.avatar_url.as_deref(),
+    };
+    let rate_limit_per_minute = body
+        .rate_limit_per_minute
+        .unwrap_or(webhook.rate_limit_per_minute)
+        .clamp(1, WEBHOOK_MAX_RATE_LIMIT_PER_MINUTE);
+
+    queries::update_incoming_webhook(
+        data.clone(),
+        &webhook.id,
+        name,
+        avatar_url,
+        rate_limit_per_minute,
+    )
+    .await?;
+
+    let updated_webhook = queries::get_incoming_webhook(data.clone(), &webhook.id)
+        .await?
+        .ok_or_else(webhook_not_found)?;
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::WEBHOOK_UPDATED,
+        audit_target_types::WEBHOOK,
+        &webhook.id,
+        None,
+        audit_log::diff(
+            &json!(webhook.to_resource()),
+            &json!(updated_webhook.to_resource()),
+        ),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(json!(updated_webhook.to_resource()))))
+}
+
+pub async fn delete_webhook_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(webhook_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_webhooks").await?;
+
+    let webhook = queries::get_incoming_webhook(data.clone(), &webhook_id)
+        .await?
+        .ok_or_else(webhook_not_found)?;
+
+    let deleted = queries::delete_incoming_webhook(data.clone(), &webhook.id).await?;
+
+    if !deleted {
+        return Err(webhook_not_found());
+    }
+
+    info!("User {} deleted webhook {}", user.id, webhook.id);
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::WEBHOOK_DELETED,
+        audit_target_types::WEBHOOK,
+        &webhook.id,
+        None,
+        audit_log::diff(&json!(webhook.to_resource()), &serde_json::Value::Null),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(json!({ "status": "success" }))))
+}
+
+/// Public endpoint external systems post to. Authenticated by the secret
+/// token in the URL instead of the `auth` middleware.
+pub async fn post_execute_webhook_handler(
+    State(data): State<Arc<AppState>>,
+    Path((webhook_id, token)): Path<(String, String)>,
+    Json(body): Json<ExecuteIncomingWebhookRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let webhook = queries::get_incoming_webhook(data.clone(), &webhook_id)
+        .await?
+        .filter(|webhook| token_matches_hash(&token, &webhook.token_hash))
+        .ok_or_else(webhook_not_found)?;
+
+    if let Err(retry_after) = data.webhook_rate_limiter.try_acquire(
+        &webhook.id,
+        webhook.rate_limit_per_minute as u32,
+        std::time::Duration::from_secs(60),
+    ) {
+        return Err(AppError::RateLimited {
+            message: "Webhook rate limit exceeded".to_string(),
+            retry_after,
+        });
+    }
+
+    let content = body
+        .content
+        .filter(|content| !content.trim().is_empty())
+        .ok_or_else(|| AppError::Validation("Message content must not be empty".to_string()))?;
+
+    if content.chars().count() > WEBHOOK_MAX_CONTENT_LENGTH {
+        return Err(AppError::Validation(format!(
+            "Message content must not exceed {} characters",
+            WEBHOOK_MAX_CONTENT_LENGTH
+        )));
+    }
+
+    validate_webhook_avatar_url(body.avatar_url.as_deref())?;
+
+    let author_display_name = body
+        .username
+        .map(|username| {
+            username
+                .trim()
+                .chars()
+                .take(WEBHOOK_MAX_USERNAME_LENGTH)
+                .collect::<String>()
+        })
+        .filter(|username| !username.is_empty())
+        .unwrap_or_else(|| webhook.name.clone());
+
+    let message = queries::create_message(
+        data.clone(),
+        WEBHOOK_SYSTEM_USER_ID.to_string(),
+        webhook.channel_id.clone(),
+        Some(content),
+        CreateMessageOptions {
+            webhook_id: Some(webhook.id.clone()),
+            author_display_name: Some(author_display_name),
+            author_avatar_url: body.avatar_url.or(webhook.avatar_url),
+            ..Default::default()
+        },
+    )
+    .await?;
+
+    let webhook_user =
+        queries::get_user_by_id(data.clone(), WEBHOOK_SYSTEM_USER_ID.to_string()).await?;
+
+    let payload = ReceiveChatMessagePayload {
+        message: message.to_resource(webhook_user.to_resource()),
+        thread: None,
+    };
+
+    spawn_dispatch_event(
+        data.clone(),
+        event_types::MESSAGE_CREATED,
+        json!({ "message": payload.message }),
+    );
+
+    if let Err(e) = data
+        .io
+        .emit(socket_publish_events::RECEIVE_CHAT_MESSAGE, &payload)
+        .await
+    {
+        warn!("Failed to emit message: {}", e);
+    }
+
+    spawn_unfurl(data.clone(), &message);
+
+    Ok((StatusCode::CREATED, Json(json!(payload.message))))
+}
+
+const WEBHOOK_SECRET_LENGTH: usize = 40;
+
+fn webhook_subscription_not_found() -> AppError {
+    AppError::NotFound("Webhook subscription not found".to_string())
+}
+
+fn validate_webhook_subscription(url: &str, event_types: &[String]) -> Result<(), AppError> {
+    match Url::parse(url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+        _ => return Err(AppError::Validation("Malformed url".to_string())),
+    }
+
+    if event_types.is_empty()
+        || event_types
+            .iter()
+            .any(|event_type| !event_types::ALL.contains(&event_type.as_str()))
+    {
+        return Err(AppError::Validation(format!(
+            "Event types must be a non-empty subset of {:?}",
+            event_types::ALL
+        )));
+    }
+
+    Ok(())
+}
+
+pub async fn get_webhook_subscriptions_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_webhooks").await?;
+
+    let subscriptions = queries::get_webhook_subscriptions(data.clone()).await?;
+
+    let subscription_resources = subscriptions
+        .iter()
+        .map(|subscription| subscription.to_resource())
+        .collect::<Vec<WebhookSubscriptionResource>>();
+
+    Ok((StatusCode::OK, Json(json!(subscription_resources))))
+}
+
+pub async fn post_webhook_subscription_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Json(body): Json<CreateWebhookSubscriptionRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_webhooks").await?;
+
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(AppError::Validation(
+            "Webhook name must not be empty".to_string(),
+        ));
+    }
+
+    validate_webhook_subscription(&body.url, &body.event_types)?;
+
+    let secret = generate_secret_token(WEBHOOK_SECRET_LENGTH);
+    let subscription = queries::create_webhook_subscription(
+        data.clone(),
+        name,
+        &body.url,
+        &secret,
+        &body.event_types,
+        &user.id,
+    )
+    .await?;
+
+    info!(
+        "User {} created webhook subscription {}",
+        user.id, subscription.id
+    );
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::WEBHOOK_SUBSCRIPTION_CREATED,
+        audit_target_types::WEBHOOK_SUBSCRIPTION,
+        &subscription.id,
+        None,
+        audit_log::diff(&serde_json::Value::Null, &json!(subscription.to_resource())),
+    )
+    .await;
+
+    // The signing secret is only ever returned here
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({ "secret": secret, "subscription": subscription.to_resource() })),
+    ))
+}
+
+pub async fn patch_webhook_subscription_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(subscription_id): Path<String>,
+    Json(body): Json<UpdateWebhookSubscriptionRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_webhooks").await?;
+
+    let subscription = queries::get_webhook_subscription(data.clone(), &subscription_id)
+        .await?
+        .ok_or_else(webhook_subscription_not_found)?;
+
+    let name = body
+        .name
+        .as_deref()
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .unwrap_or(&subscription.name);
+    let url = body.url.as_deref().unwrap_or(&subscription.url);
+    let event_types = body
+        .event_types
+        .unwrap_or_else(|| subscription.event_types());
+    let is_active = body.is_active.unwrap_or(subscription.is_active != 0);
+
+    validate_webhook_subscription(url, &event_types)?;
+
+    queries::update_webhook_subscription(
+        data.clone(),
+        &subscription.id,
+        name,
+        url,
+        &event_types,
+        is_active,
+    )
+    .await?;
+
+    let updated_subscription = queries::get_webhook_subscription(data.clone(), &subscription.id)
+        .await?
+        .ok_or_else(webhook_subscription_not_found)?;
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::WEBHOOK_SUBSCRIPTION_UPDATED,
+        audit_target_types::WEBHOOK_SUBSCRIPTION,
+        &subscription.id,
+        None,
+        audit_log::diff(
+            &json!(subscription.to_resource()),
+            &json!(updated_subscription.to_resource()),
+        ),
+    )
+    .await;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!(updated_subscription.to_resource())),
+    ))
+}
+
+pub async fn delete_webhook_subscription_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(subscription_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_webhooks").await?;
+
+    let subscription = queries::get_webhook_subscription(data.clone(), &subscription_id)
+        .await?
+        .ok_or_else(webhook_subscription_not_found)?;
+
+    let deleted = queries::delete_webhook_subscription(data.clone(), &subscription.id).await?;
+
+    if !deleted {
+        return Err(webhook_subscription_not_found());
+    }
+
+    info!(
+        "User {} deleted webhook subscription {}",
+        user.id, subscription.id
+    );
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::WEBHOOK_SUBSCRIPTION_DELETED,
+        audit_target_types::WEBHOOK_SUBSCRIPTION,
+        &subscription.id,
+        None,
+        audit_log::diff(&json!(subscription.to_resource()), &serde_json::Value::Null),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(json!({ "status": "success" }))))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PaginationQueryParams {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+pub async fn get_webhook_deliveries_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(subscription_id): Path<String>,
+    Query(params): Query<PaginationQueryParams>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_webhooks").await?;
+
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let deliveries =
+        queries::get_webhook_deliveries(data.clone(), &subscription_id, limit, offset).await?;
+
+    let delivery_resources = deliveries
+        .iter()
+        .map(|delivery| delivery.to_resource())
+        .collect::<Vec<WebhookDeliveryResource>>();
+
+    Ok((StatusCode::OK, Json(json!(delivery_resources))))
+}
+
+fn automod_rule_not_found() -> AppError {
+    AppError::NotFound("Automod rule not found".to_string())
+}
+
+/// Validates the settings shared by new and updated automod rules.
+async fn validate_automod_rule(
+    data: Arc<AppState>,
+    rule_type: &str,
+    fields: &AutomodRuleFields<'_>,
+) -> Result<(), AppError> {
+    let fail = |message: String| Err(AppError::Validation(message));
+
+    if fields.name.is_empty() {
+        return fail("Rule name must not be empty".to_string());
+    }
+
+    let config = serde_json::from_str(fields.config).unwrap_or(serde_json::Value::Null);
+    if let Err(e) = Filter::parse(rule_type, &config) {
+        return fail(e);
+    }
+
+    if !AUTOMOD_ACTIONS.contains(&fields.action) {
+        return fail(format!("action must be one of {:?}", AUTOMOD_ACTIONS));
+    }
+
+    let timeout_is_valid = match fields.timeout_seconds {
+        Some(seconds) => {
+            fields.action == AUTOMOD_ACTION_TIMEOUT
+                && (1..=MAX_TIMEOUT.num_seconds()).contains(&seconds.into())
+        }
+        None => fields.action != AUTOMOD_ACTION_TIMEOUT,
+    };
+    if !timeout_is_valid {
+        return fail(format!(
+            "timeoutSeconds must be between 1 and {} for the timeout action only",
+            MAX_TIMEOUT.num_seconds()
+        ));
+    }
+
+    if let Some(channel_id) = fields.channel_id {
+        let channel = queries::get_channel_by_id(data, channel_id).await?;
+
+        if channel.is_none() {
+            return Err(AppError::NotFound("Channel not found".to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn get_automod_rules_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_automod").await?;
+
+    let rules = queries::get_automod_rules(data.clone()).await?;
+
+    let rule_resources = rules
+        .iter()
+        .map(|rule| rule.to_resource())
+        .collect::<Vec<AutomodRuleResource>>();
+
+    Ok((StatusCode::OK, Json(json!(rule_resources))))
+}
+
+pub async fn post_automod_rule_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Json(body): Json<CreateAutomodRuleRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_automod").await?;
+
+    let config = body.config.unwrap_or(serde_json::Value::Null).to_string();
+    let fields = AutomodRuleFields {
+        name: body.name.trim(),
+        config: &config,
+        action: &body.action,
+        timeout_seconds: body.timeout_seconds,
+        channel_id: body.channel_id.as_deref(),
+        is_active: body.is_active.unwrap_or(true),
+    };
+
+    validate_automod_rule(data.clone(), &body.rule_type, &fields).await?;
+
+    let rule =
+        queries::create_automod_rule(data.clone(), &body.rule_type, &fields, &user.id).await?;
+
+    info!("User {} created automod rule {}", user.id, rule.id);
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::AUTOMOD_RULE_CREATED,
+        audit_target_types::AUTOMOD_RULE,
+        &rule.id,
+        None,
+        audit_log::diff(&serde_json::Value::Null, &json!(rule.to_resource())),
+    )
+    .await;
+
+    Ok((StatusCode::CREATED, Json(json!(rule.to_resource()))))
+}
+
+pub async fn patch_automod_rule_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(rule_id): Path<String>,
+    Json(body): Json<UpdateAutomodRuleRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_automod").await?;
+
+    let rule = queries::get_automod_rule(data.clone(), &rule_id)
+        .await?
+        .ok_or_else(automod_rule_not_found)?;
+
+    let config = body
+        .config
+        .map(|config| config.to_string())
+        .unwrap_or_else(|| rule.config.clone());
+    let action = body.action.as_deref().unwrap_or(&rule.action);
+    // Switching away from the timeout action drops the stored duration
+    let timeout_seconds = match body.timeout_seconds {
+        Some(seconds) => Some(seconds),
+        None if action == AUTOMOD_ACTION_TIMEOUT => rule.timeout_seconds,
+        None => None,
+    };
+    let channel_id = match body.channel_id.as_deref() {
+        Some("") => None,
+        Some(channel_id) => Some(channel_id),
+        None => rule.channel_id.as_deref(),
+    };
+
+    let fields = AutomodRuleFields {
+        name: body.name.as_deref().map_or(&rule.name, |name| name.trim()),
+        config: &config,
+        action,
+        timeout_seconds,
+        channel_id,
+        is_active: body.is_active.unwrap_or(rule.is_active != 0),
+    };
+
+    validate_automod_rule(data.clone(), &rule.rule_type, &fields).await?;
+
+    queries::update_automod_rule(data.clone(), &rule.id, &fields).await?;
+    data.automod_filter_cache.invalidate(&rule.id);
+
+    let updated_rule = queries::get_automod_rule(data.clone(), &rule.id)
+        .await?
+        .ok_or_else(automod_rule_not_found)?;
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::AUTOMOD_RULE_UPDATED,
+        audit_target_types::AUTOMOD_RULE,
+        &rule.id,
+        None,
+        audit_log::diff(
+            &json!(rule.to_resource()),
+            &json!(updated_rule.to_resource()),
+        ),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(json!(updated_rule.to_resource()))))
+}
+
+pub async fn delete_automod_rule_handler(
+    State(data): State<Arc<AppState>>,
+    Extension(user): Extension<User>,
+    Path(rule_id): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    require_permission(data.clone(), &user, "manage_automod").await?;
+
+    let rule = queries::get_automod_rule(data.clone(), &rule_id)
+        .await?
+        .ok_or_else(automod_rule_not_found)?;
+
+    let deleted = queries::delete_automod_rule(data.clone(), &rule.id).await?;
+
+    if !deleted {
+        return Err(automod_rule_not_found());
+    }
+    data.automod_filter_cache.invalidate(&rule.id);
+
+    info!("User {} deleted automod rule {}", user.id, rule.id);
+
+    audit_log::record(
+        data.clone(),
+        &user.id,
+        audit_actions::AUTOMOD_RULE_DELETED,
+        audit_target_types::AUTOMOD_RULE,
+        &rule.id,
+        None,
+        audit_log::diff(&json!(rule.to_resource()), &serde_json::Value::Null),
+    )
+    .await;
+
+    Ok((StatusCode::OK, Json(json!({ "status": "success" }))))
+}
@@ -1,5 +1,6 @@
 mod auth;
 mod config;
+mod errors;
 mod handlers;
 mod models;
 mod queries;
@@ -7,28 +8,59 @@ mod requests;
 mod responses;
 mod services;
 mod socket;
+#[cfg(test)]
+mod test_support;
 
 use crate::auth::auth;
 use crate::config::Config;
+use crate::errors::{request_id, REQUEST_ID_HEADER};
 use crate::handlers::{
-    get_auth_me_handler, get_channel_messages_handler, get_channels_handler,
-    get_link_preview_handler, get_server_info, get_users_handler, hello_handler,
-    post_auth_token_handler, post_register_user_handler,
+    delete_automod_rule_handler, delete_ban_handler, delete_bot_token_handler,
+    delete_channel_pin_handler, delete_link_previews_handler, delete_timeout_handler,
+    delete_user_block_handler, delete_user_two_factor_handler, delete_webhook_handler,
+    delete_webhook_subscription_handler, get_audit_log_handler, get_auth_me_handler,
+    get_automod_rules_handler, get_bans_handler, get_blocked_users_handler, get_bot_tokens_handler,
+    get_bots_handler, get_channel_messages_handler, get_channel_pins_handler,
+    get_channel_threads_handler, get_channel_webhooks_handler, get_channels_handler,
+    get_direct_conversations_handler, get_direct_messages_handler, get_image_proxy_handler,
+    get_link_preview_handler, get_login_attempts_handler, get_message_thread_handler,
+    get_reports_handler, get_search_messages_handler, get_server_info, get_timeouts_handler,
+    get_users_handler, get_webhook_deliveries_handler, get_webhook_subscriptions_handler,
+    hello_handler, patch_auth_me_settings_handler, patch_automod_rule_handler,
+    patch_webhook_handler, patch_webhook_subscription_handler, post_auth_token_handler,
+    post_auth_token_mfa_handler, post_automod_rule_handler, post_ban_handler, post_bot_handler,
+    post_bot_token_handler, post_channel_message_handler, post_channel_webhook_handler,
+    post_direct_conversation_handler, post_execute_webhook_handler, post_register_user_handler,
+    post_report_claim_handler, post_report_resolve_handler, post_timeout_handler,
+    post_two_factor_confirm_handler, post_two_factor_enroll_handler,
+    post_webhook_subscription_handler, put_channel_pin_handler, put_channel_read_state_handler,
+    put_user_block_handler,
 };
 use crate::models::User;
-use crate::socket::connection::on_connect;
+use crate::services::automod::{FilterCache, FloodTracker};
+use crate::services::image_proxy::{ImageProxyCache, PROXY_PATH};
+use crate::services::login_throttle::LoginThrottle;
+use crate::services::outgoing_webhooks::run_delivery_worker;
+use crate::services::preview_cache::PreviewCache;
+use crate::services::rate_limit::RateLimiter;
+use crate::socket::connection::{authenticate_socket, on_connect};
 use argon2::{Argon2, PasswordHasher};
-use argon2::password_hash::{SaltString, PasswordHash, rand_core::OsRng};
-use axum::{middleware, routing::get, routing::post, Router};
+use argon2::password_hash::{SaltString, rand_core::OsRng};
+use axum::{
+    middleware, routing::delete, routing::get, routing::patch, routing::post, routing::put, Router,
+};
 use dotenv::dotenv;
 use serde_json::Value;
 use socketioxide::extract::{Data, SocketRef};
+use socketioxide::handler::ConnectHandler;
 use socketioxide::SocketIo;
 use sqlx::mysql::MySqlPoolOptions;
 use sqlx::MySqlPool;
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tower_http::cors::{Any, CorsLayer};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use tracing_subscriber::FmtSubscriber;
 
 #[derive(Debug)]
@@ -46,6 +78,14 @@ pub struct AppState {
     config: Config,
     cnt: Mutex<i32>,
     connected_users: dashmap::DashMap<String, UserConnection>,
+    login_throttle: LoginThrottle,
+    webhook_rate_limiter: RateLimiter,
+    poke_rate_limiter: RateLimiter,
+    automod_flood_tracker: FloodTracker,
+    automod_filter_cache: FilterCache,
+    link_preview_cache: PreviewCache,
+    image_proxy_cache: ImageProxyCache,
+    io: SocketIo,
 }
 
 #[tokio::main]
@@ -108,7 +148,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .allow_headers([
             axum::http::header::AUTHORIZATION,
             axum::http::header::CONTENT_TYPE,
-        ]);
+        ])
+        .expose_headers([REQUEST_ID_HEADER]);
+
+    // Socket.io Server
+    let (socket_layer, io) = SocketIo::builder().req_path("/server/").build_layer();
 
     // App State
     let app_state = Arc::new(AppState {
@@ -116,22 +160,72 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         config: config.clone(),
         cnt: Mutex::from(0),
         connected_users: dashmap::DashMap::new(),
+        login_throttle: LoginThrottle::new(),
+        webhook_rate_limiter: RateLimiter::new(),
+        poke_rate_limiter: RateLimiter::new(),
+        automod_flood_tracker: FloodTracker::new(),
+        automod_filter_cache: FilterCache::new(),
+        link_preview_cache: PreviewCache::new(),
+        image_proxy_cache: ImageProxyCache::new(),
+        io: io.clone(),
     });
 
-    // Socket.io Server
-    let (socket_layer, io) = SocketIo::builder().req_path("/server/").build_layer();
+    // Periodically forget stale login throttle counters, rate limit buckets,
+    // automod flood history, expired link previews and proxied images
+    let throttle_state = app_state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(10 * 60));
+        loop {
+            interval.tick().await;
+            throttle_state.login_throttle.prune();
+            if let Some(summary) = throttle_state.login_throttle.take_throttled_summary() {
+                warn!(
+                    "Rejected {} throttled login attempts from {} IPs, most from {:?}",
+                    summary.attempts, summary.ips, summary.busiest_ip
+                );
+            }
+            throttle_state
+                .webhook_rate_limiter
+                .prune(Duration::from_secs(10 * 60));
+            throttle_state
+                .poke_rate_limiter
+                .prune(Duration::from_secs(10 * 60));
+            throttle_state.automod_flood_tracker.prune();
+            throttle_state.link_preview_cache.prune();
+            throttle_state.image_proxy_cache.prune();
+            if let Err(e) = queries::delete_expired_link_previews(throttle_state.clone()).await {
+                warn!("Failed to delete expired link previews: {}", e);
+            }
+        }
+    });
+
+    // Deliver queued outgoing webhook events in the background
+    tokio::spawn(run_delivery_worker(app_state.clone()));
 
-    // Create a closure that captures the app state
+    // Create closures that capture the app state; the auth middleware rejects
+    // the handshake before `on_connect` runs if the token is not acceptable
     let state_clone = app_state.clone();
-    io.ns("/", move |socket: SocketRef, Data(data): Data<Value>| {
-        on_connect(socket, Data(data), state_clone)
-    });
+    let auth_state_clone = app_state.clone();
+    io.ns(
+        "/",
+        (move |socket: SocketRef| on_connect(socket, state_clone)).with(
+            move |socket: SocketRef, Data(data): Data<Value>| {
+                authenticate_socket(socket, Data(data), auth_state_clone)
+            },
+        ),
+    );
 
     // Create Axum app
     let app = Router::new()
         .route("/", get(hello_handler))
         .route("/auth/register", post(post_register_user_handler))
         .route("/auth/token", post(post_auth_token_handler))
+        .route("/auth/token/mfa", post(post_auth_token_mfa_handler))
+        .route(
+            "/webhooks/{webhook_id}/{token}",
+            post(post_execute_webhook_handler),
+        )
+        .route(PROXY_PATH, get(get_image_proxy_handler))
         .merge(
             Router::new()
                 .route("/serverinfo", get(get_server_info))
@@ -140,12 +234,115 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     "/channels/{channel_id}/messages/",
                     get(get_channel_messages_handler),
                 )
+                .route(
+                    "/channels/{channel_id}/messages",
+                    post(post_channel_message_handler),
+                )
+                .route(
+                    "/channels/{channel_id}/messages/{message_id}/thread",
+                    get(get_message_thread_handler),
+                )
+                .route(
+                    "/channels/{channel_id}/threads",
+                    get(get_channel_threads_handler),
+                )
+                .route(
+                    "/channels/{channel_id}/read",
+                    put(put_channel_read_state_handler),
+                )
+                .route("/channels/{channel_id}/pins", get(get_channel_pins_handler))
+                .route(
+                    "/channels/{channel_id}/pins/{message_id}",
+                    put(put_channel_pin_handler).delete(delete_channel_pin_handler),
+                )
+                .route(
+                    "/dms",
+                    get(get_direct_conversations_handler).post(post_direct_conversation_handler),
+                )
+                .route(
+                    "/dms/{channel_id}/messages",
+                    get(get_direct_messages_handler),
+                )
+                .route("/search/messages", get(get_search_messages_handler))
                 .route("/users", get(get_users_handler))
+                .route("/blocks", get(get_blocked_users_handler))
+                .route(
+                    "/blocks/{user_id}",
+                    put(put_user_block_handler).delete(delete_user_block_handler),
+                )
                 .route("/auth/me", get(get_auth_me_handler))
+                .route("/auth/me/settings", patch(patch_auth_me_settings_handler))
+                .route("/auth/2fa/enroll", post(post_two_factor_enroll_handler))
+                .route("/auth/2fa/confirm", post(post_two_factor_confirm_handler))
+                .route(
+                    "/users/{user_id}/2fa",
+                    delete(delete_user_two_factor_handler),
+                )
+                .route("/admin/login-attempts", get(get_login_attempts_handler))
+                .route("/admin/bans", get(get_bans_handler).post(post_ban_handler))
+                .route("/admin/bans/{ban_id}", delete(delete_ban_handler))
+                .route(
+                    "/admin/timeouts",
+                    get(get_timeouts_handler).post(post_timeout_handler),
+                )
+                .route(
+                    "/admin/timeouts/{timeout_id}",
+                    delete(delete_timeout_handler),
+                )
+                .route("/audit-log", get(get_audit_log_handler))
+                .route("/reports", get(get_reports_handler))
+                .route(
+                    "/reports/{report_id}/claim",
+                    post(post_report_claim_handler),
+                )
+                .route(
+                    "/reports/{report_id}/resolve",
+                    post(post_report_resolve_handler),
+                )
+                .route(
+                    "/automod/rules",
+                    get(get_automod_rules_handler).post(post_automod_rule_handler),
+                )
+                .route(
+                    "/automod/rules/{rule_id}",
+                    patch(patch_automod_rule_handler).delete(delete_automod_rule_handler),
+                )
+                .route("/bots", get(get_bots_handler).post(post_bot_handler))
+                .route(
+                    "/bots/{bot_id}/tokens",
+                    get(get_bot_tokens_handler).post(post_bot_token_handler),
+                )
+                .route(
+                    "/bots/{bot_id}/tokens/{token_id}",
+                    delete(delete_bot_token_handler),
+                )
+                .route(
+                    "/channels/{channel_id}/webhooks",
+                    get(get_channel_webhooks_handler).post(post_channel_webhook_handler),
+                )
+                .route(
+                    "/webhooks/{webhook_id}",
+                    patch(patch_webhook_handler).delete(delete_webhook_handler),
+                )
+                .route(
+                    "/outgoing-webhooks",
+                    get(get_webhook_subscriptions_handler).post(post_webhook_subscription_handler),
+                )
+                .route(
+                    "/outgoing-webhooks/{subscription_id}",
+                    patch(patch_webhook_subscription_handler)
+                        .delete(delete_webhook_subscription_handler),
+                )
+                .route(
+                    "/outgoing-webhooks/{subscription_id}/deliveries",
+                    get(get_webhook_deliveries_handler),
+                )
                 .route("/fetch-preview-data/", get(get_link_preview_handler))
+                .route("/link-previews", delete(delete_link_previews_handler))
                 .layer(middleware::from_fn_with_state(app_state.clone(), auth)),
         )
         .with_state(app_state)
+        .layer(middleware::from_fn(request_id))
         .layer(socket_layer)
         .layer(cors_layer);
 
@@ -155,7 +352,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     info!("🚀 Server started successfully: http://localhost:3000");
 
     // Run Axum Webserver
-    axum::serve(listener, app).await.unwrap();
+    // Client addresses are needed for per-IP login throttling
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .unwrap();
 
     // Exit gracefully
     return Ok(());
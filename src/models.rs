@@ -1,4 +1,10 @@
-use crate::responses::{AuthMeUserResource, ChannelResource, MessageResource, UserResource};
+use crate::responses::{
+    ApiTokenResource, AuditLogEntryResource, AuthMeUserResource, AutomodRuleResource, BanResource,
+    ChannelReadStateResource, ChannelResource, EmbedResource, IncomingWebhookResource,
+    LoginAttemptResource, MentionResource, MessageReferenceResource, MessageResource,
+    ReactionResource, ReportResource, ThreadSummaryResource, TimeoutResource, UserResource,
+    WebhookDeliveryResource, WebhookSubscriptionResource,
+};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
@@ -6,6 +12,7 @@ use sqlx::FromRow;
 pub struct Channel {
     pub id: String,
     pub name: String,
+    pub channel_type: String,
     pub sort_order: i32,
     pub is_default: i8,
     pub created_at: chrono::DateTime<chrono::Utc>,
@@ -18,20 +25,46 @@ impl Channel {
         return ChannelResource {
             id: self.id.to_owned(),
             name: self.name.to_owned(),
+            channelType: self.channel_type.to_owned(),
             sortOrder: self.sort_order.to_owned(),
             isDefault: self.is_default != 0,
             createdAt: self.created_at.to_owned(),
             updatedAt: self.updated_at.to_owned(),
             deletedAt: self.deleted_at.to_owned(),
+            lastReadMessageId: None,
+            unreadCount: 0,
+            mentionCount: 0,
         };
     }
 }
 
+/// Regular server channel, visible to every user.
+pub const CHANNEL_TYPE_TEXT: &str = "text";
+/// Direct conversation, visible to its members only.
+pub const CHANNEL_TYPE_DIRECT: &str = "direct";
+
+impl Channel {
+    pub fn is_direct(&self) -> bool {
+        self.channel_type == CHANNEL_TYPE_DIRECT
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, FromRow)]
 pub struct Message {
     pub id: String,
     pub user_id: String,
     pub channel_id: String,
+    pub message_type: String,
+    pub webhook_id: Option<String>,
+    pub author_display_name: Option<String>,
+    pub author_avatar_url: Option<String>,
+    pub reply_to_message_id: Option<String>,
+    /// Parent message if this message is a thread reply.
+    pub thread_id: Option<String>,
+    pub thread_reply_count: i32,
+    pub thread_last_reply_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub pinned_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub pinned_by_user_id: Option<String>,
     pub content: Option<String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
@@ -40,11 +73,29 @@ pub struct Message {
 }
 
 impl Message {
-    pub fn to_resource(&self, user: UserResource) -> MessageResource {
+    pub fn to_resource(&self, mut user: UserResource) -> MessageResource {
+        // Webhook messages carry their own author name and avatar
+        if let Some(display_name) = &self.author_display_name {
+            user.displayName = display_name.to_owned();
+        }
+        if let Some(avatar_url) = &self.author_avatar_url {
+            user.profilePicture = Some(avatar_url.to_owned());
+        }
+
         MessageResource {
             id: self.id.to_owned(),
             userId: self.user_id.to_owned(),
             channelId: self.channel_id.to_owned(),
+            messageType: self.message_type.to_owned(),
+            webhookId: self.webhook_id.to_owned(),
+            replyToMessageId: self.reply_to_message_id.to_owned(),
+            replyTo: None,
+            threadId: self.thread_id.to_owned(),
+            threadReplyCount: self.thread_reply_count,
+            threadLastReplyAt: self.thread_last_reply_at.to_owned(),
+            pinned: self.pinned_at.is_some(),
+            pinnedAt: self.pinned_at.to_owned(),
+            pinnedByUserId: self.pinned_by_user_id.to_owned(),
             content: self.content.to_owned(),
             createdAt: self.created_at.to_owned(),
             updatedAt: self.updated_at.to_owned(),
@@ -52,10 +103,53 @@ impl Message {
             deletedByUserId: self.deleted_by_user_id.to_owned(),
             user,
             attachments: vec![],
+            reactions: vec![],
+            mentions: vec![],
+            embeds: vec![],
+            authorBlocked: false,
+        }
+    }
+
+    /// Short form shown when another message quotes this one. The content
+    /// of deleted messages is not revealed.
+    pub fn to_reference_resource(&self) -> MessageReferenceResource {
+        MessageReferenceResource {
+            id: self.id.to_owned(),
+            userId: self.user_id.to_owned(),
+            authorDisplayName: self.author_display_name.to_owned(),
+            content: match self.deleted_at {
+                Some(_) => None,
+                None => self.content.to_owned(),
+            },
+            createdAt: self.created_at.to_owned(),
+            deleted: self.deleted_at.is_some(),
+        }
+    }
+
+    pub fn to_thread_summary_resource(&self) -> ThreadSummaryResource {
+        ThreadSummaryResource {
+            parentMessageId: self.id.to_owned(),
+            replyCount: self.thread_reply_count,
+            lastReplyAt: self.thread_last_reply_at.to_owned(),
         }
     }
 }
 
+/// Regular message written by a user, bot or webhook.
+pub const MESSAGE_TYPE_DEFAULT: &str = "default";
+/// System message announcing that the quoted message was pinned.
+pub const MESSAGE_TYPE_PIN_ADDED: &str = "pin_added";
+/// System message announcing that the quoted message was unpinned.
+pub const MESSAGE_TYPE_PIN_REMOVED: &str = "pin_removed";
+
+/// System user that messages posted through incoming webhooks belong to.
+pub const WEBHOOK_SYSTEM_USER_ID: &str = "00000000-0000-0000-0000-00000000a001";
+/// System user that automod timeouts and reports are attributed to.
+pub const AUTOMOD_SYSTEM_USER_ID: &str = "00000000-0000-0000-0000-00000000a002";
+/// System users owned by the server itself. They are not bots, so nobody
+/// can mint tokens for them.
+pub const RESERVED_SYSTEM_USER_IDS: [&str; 2] = [WEBHOOK_SYSTEM_USER_ID, AUTOMOD_SYSTEM_USER_ID];
+
 #[derive(Debug, Deserialize, Serialize, FromRow, Clone)]
 pub struct User {
     pub id: String,
@@ -63,11 +157,32 @@ pub struct User {
     pub display_name: String,
     pub password: String,
     pub is_system_user: i8,
+    pub dm_privacy: String,
+    pub custom_status: Option<String>,
+    pub do_not_disturb: i8,
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// Anyone, including bots, may start a direct conversation with the user.
+pub const DM_PRIVACY_EVERYONE: &str = "everyone";
+/// Only members of this server may. Bots and banned users are not members.
+pub const DM_PRIVACY_SHARED_SERVER: &str = "shared_server";
+/// Nobody may start a new direct conversation with the user.
+pub const DM_PRIVACY_NOBODY: &str = "nobody";
+
+pub const DM_PRIVACY_SETTINGS: [&str; 3] = [
+    DM_PRIVACY_EVERYONE,
+    DM_PRIVACY_SHARED_SERVER,
+    DM_PRIVACY_NOBODY,
+];
+
 impl User {
+    /// Whether this is a bot account created by an admin.
+    pub fn is_bot(&self) -> bool {
+        self.is_system_user == 1 && !RESERVED_SYSTEM_USER_IDS.contains(&self.id.as_str())
+    }
+
     pub fn to_resource(&self) -> UserResource {
         return UserResource {
             id: self.id.to_owned(),
@@ -78,6 +193,8 @@ impl User {
             isOnline: false,
             currentChannelId: None,
             connectedAt: None,
+            customStatus: self.custom_status.to_owned(),
+            doNotDisturb: self.do_not_disturb == 1,
             createdAt: self.created_at.to_owned(),
             updatedAt: self.updated_at.to_owned(),
         };
@@ -95,7 +212,578 @@ impl User {
             connectedAt: None,
             createdAt: self.created_at.to_owned(),
             updatedAt: self.updated_at.to_owned(),
+            customStatus: self.custom_status.to_owned(),
+            doNotDisturb: self.do_not_disturb == 1,
+            dmPrivacy: self.dm_privacy.to_owned(),
             permissions: vec![],
         };
     }
 }
+
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct UserTwoFactor {
+    pub user_id: String,
+    pub secret: String,
+    pub confirmed_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_used_step: Option<i64>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl UserTwoFactor {
+    pub fn is_enabled(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct UserRecoveryCode {
+    pub id: String,
+    pub user_id: String,
+    pub code_hash: String,
+    pub used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct LoginAttempt {
+    pub id: String,
+    pub username: String,
+    pub user_id: Option<String>,
+    pub ip_address: String,
+    pub reason: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl LoginAttempt {
+    pub fn to_resource(&self) -> LoginAttemptResource {
+        LoginAttemptResource {
+            id: self.id.to_owned(),
+            username: self.username.to_owned(),
+            userId: self.user_id.to_owned(),
+            ipAddress: self.ip_address.to_owned(),
+            reason: self.reason.to_owned(),
+            createdAt: self.created_at.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct ApiToken {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub token_hash: String,
+    pub token_prefix: String,
+    pub scopes: String,
+    pub created_by_user_id: Option<String>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub revoked_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl ApiToken {
+    /// Scopes are stored as a comma separated list.
+    pub fn scopes(&self) -> Vec<String> {
+        self.scopes
+            .split(',')
+            .map(|scope| scope.trim())
+            .filter(|scope| !scope.is_empty())
+            .map(|scope| scope.to_string())
+            .collect()
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes().iter().any(|s| s == scope)
+    }
+
+    pub fn to_resource(&self) -> ApiTokenResource {
+        ApiTokenResource {
+            id: self.id.to_owned(),
+            userId: self.user_id.to_owned(),
+            name: self.name.to_owned(),
+            tokenPrefix: self.token_prefix.to_owned(),
+            scopes: self.scopes(),
+            createdByUserId: self.created_by_user_id.to_owned(),
+            lastUsedAt: self.last_used_at.to_owned(),
+            expiresAt: self.expires_at.to_owned(),
+            revokedAt: self.revoked_at.to_owned(),
+            createdAt: self.created_at.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct IncomingWebhook {
+    pub id: String,
+    pub channel_id: String,
+    pub name: String,
+    pub avatar_url: Option<String>,
+    pub token_hash: String,
+    pub rate_limit_per_minute: i32,
+    pub created_by_user_id: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl IncomingWebhook {
+    pub fn to_resource(&self) -> IncomingWebhookResource {
+        IncomingWebhookResource {
+            id: self.id.to_owned(),
+            channelId: self.channel_id.to_owned(),
+            name: self.name.to_owned(),
+            avatarUrl: self.avatar_url.to_owned(),
+            rateLimitPerMinute: self.rate_limit_per_minute,
+            createdByUserId: self.created_by_user_id.to_owned(),
+            createdAt: self.created_at.to_owned(),
+            updatedAt: self.updated_at.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct WebhookSubscription {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    pub secret: String,
+    pub event_types: String,
+    pub is_active: i8,
+    pub created_by_user_id: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl WebhookSubscription {
+    /// Event types are stored as a comma separated list.
+    pub fn event_types(&self) -> Vec<String> {
+        self.event_types
+            .split(',')
+            .map(|event_type| event_type.trim())
+            .filter(|event_type| !event_type.is_empty())
+            .map(|event_type| event_type.to_string())
+            .collect()
+    }
+
+    pub fn to_resource(&self) -> WebhookSubscriptionResource {
+        WebhookSubscriptionResource {
+            id: self.id.to_owned(),
+            name: self.name.to_owned(),
+            url: self.url.to_owned(),
+            eventTypes: self.event_types(),
+            isActive: self.is_active != 0,
+            createdByUserId: self.created_by_user_id.to_owned(),
+            createdAt: self.created_at.to_owned(),
+            updatedAt: self.updated_at.to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub subscription_id: String,
+    pub event_type: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_attempt_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl WebhookDelivery {
+    pub fn to_resource(&self) -> WebhookDeliveryResource {
+        WebhookDeliveryResource {
+            id: self.id.to_owned(),
+            subscriptionId: self.subscription_id.to_owned(),
+            eventType: self.event_type.to_owned(),
+            payload: serde_json::from_str(&self.payload).unwrap_or(serde_json::Value::Null),
+            status: self.status.to_owned(),
+            attempts: self.attempts,
+            nextAttemptAt: self.next_attempt_at.to_owned(),
+            lastAttemptAt: self.last_attempt_at.to_owned(),
+            lastResponseStatus: self.last_response_status,
+            lastError: self.last_error.to_owned(),
+            createdAt: self.created_at.to_owned(),
+        }
+    }
+}
+
+/// A pending delivery joined with the target of its subscription.
+#[derive(Debug, FromRow)]
+pub struct DueWebhookDelivery {
+    pub id: String,
+    pub event_type: String,
+    pub payload: String,
+    pub attempts: i32,
+    pub url: String,
+    pub secret: String,
+}
+
+/// Number of users that reacted to a message with one emoji. `me` is set if
+/// the requesting user is one of them.
+#[derive(Debug, FromRow)]
+pub struct MessageReactionCount {
+    pub message_id: String,
+    pub emoji: String,
+    pub count: i64,
+    pub me: i64,
+}
+
+impl MessageReactionCount {
+    pub fn to_resource(&self) -> ReactionResource {
+        ReactionResource {
+            emoji: self.emoji.to_owned(),
+            count: self.count,
+            me: self.me != 0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct Role {
+    pub id: String,
+    pub name: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+pub const MENTION_TYPE_USER: &str = "user";
+pub const MENTION_TYPE_ROLE: &str = "role";
+pub const MENTION_TYPE_EVERYONE: &str = "everyone";
+
+/// A resolved mention. `name` is the username or role name at read time.
+#[derive(Debug, Clone, FromRow)]
+pub struct MessageMention {
+    pub id: String,
+    pub message_id: String,
+    pub mention_type: String,
+    pub user_id: Option<String>,
+    pub role_id: Option<String>,
+    pub name: Option<String>,
+}
+
+impl MessageMention {
+    pub fn to_resource(&self) -> MentionResource {
+        MentionResource {
+            type_: self.mention_type.to_owned(),
+            userId: self.user_id.to_owned(),
+            roleId: self.role_id.to_owned(),
+            name: self
+                .name
+                .to_owned()
+                .unwrap_or_else(|| self.mention_type.to_owned()),
+        }
+    }
+}
+
+/// Read marker of a user in a channel with the counts derived from it.
+#[derive(Debug, FromRow)]
+pub struct ChannelUnreadCount {
+    pub channel_id: String,
+    pub last_read_message_id: Option<String>,
+    pub unread_count: i64,
+    pub mention_count: i64,
+}
+
+impl ChannelUnreadCount {
+    pub fn to_resource(&self) -> ChannelReadStateResource {
+        ChannelReadStateResource {
+            channelId: self.channel_id.to_owned(),
+            lastReadMessageId: self.last_read_message_id.to_owned(),
+            unreadCount: self.unread_count,
+            mentionCount: self.mention_count,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct ChannelMember {
+    pub channel_id: String,
+    pub user_id: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A direct conversation of a user with the time of its latest message.
+#[derive(Debug, FromRow)]
+pub struct DirectConversation {
+    pub id: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_message_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct UserBan {
+    pub id: String,
+    pub user_id: String,
+    pub reason: Option<String>,
+    pub banned_by_user_id: Option<String>,
+    /// `None` bans permanently.
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub lifted_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub lifted_by_user_id: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl UserBan {
+    pub fn to_resource(&self) -> BanResource {
+        BanResource {
+            id: self.id.to_owned(),
+            userId: self.user_id.to_owned(),
+            reason: self.reason.to_owned(),
+            bannedByUserId: self.banned_by_user_id.to_owned(),
+            expiresAt: self.expires_at.to_owned(),
+            liftedAt: self.lifted_at.to_owned(),
+            liftedByUserId: self.lifted_by_user_id.to_owned(),
+            createdAt: self.created_at.to_owned(),
+        }
+    }
+}
+
+/// Temporarily keeps a user from sending messages, speaking and reacting.
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct UserTimeout {
+    pub id: String,
+    pub user_id: String,
+    pub reason: Option<String>,
+    pub created_by_user_id: Option<String>,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub lifted_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub lifted_by_user_id: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl UserTimeout {
+    pub fn to_resource(&self) -> TimeoutResource {
+        TimeoutResource {
+            id: self.id.to_owned(),
+            userId: self.user_id.to_owned(),
+            reason: self.reason.to_owned(),
+            createdByUserId: self.created_by_user_id.to_owned(),
+            expiresAt: self.expires_at.to_owned(),
+            liftedAt: self.lifted_at.to_owned(),
+            liftedByUserId: self.lifted_by_user_id.to_owned(),
+            createdAt: self.created_at.to_owned(),
+        }
+    }
+}
+
+/// A privileged action; `changes` holds a JSON diff of the target.
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct AuditLogEntry {
+    pub id: String,
+    pub actor_user_id: Option<String>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub reason: Option<String>,
+    pub changes: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl AuditLogEntry {
+    pub fn to_resource(&self) -> AuditLogEntryResource {
+        AuditLogEntryResource {
+            id: self.id.to_owned(),
+            actorUserId: self.actor_user_id.to_owned(),
+            action: self.action.to_owned(),
+            targetType: self.target_type.to_owned(),
+            targetId: self.target_id.to_owned(),
+            reason: self.reason.to_owned(),
+            changes: serde_json::from_str(&self.changes).unwrap_or(serde_json::Value::Null),
+            createdAt: self.created_at.to_owned(),
+        }
+    }
+}
+
+/// Waiting for a moderator.
+pub const REPORT_STATUS_OPEN: &str = "open";
+/// A moderator is looking into it.
+pub const REPORT_STATUS_CLAIMED: &str = "claimed";
+/// Closed with a delete, timeout or ban.
+pub const REPORT_STATUS_RESOLVED: &str = "resolved";
+/// Closed without action.
+pub const REPORT_STATUS_DISMISSED: &str = "dismissed";
+
+pub const REPORT_STATUSES: [&str; 4] = [
+    REPORT_STATUS_OPEN,
+    REPORT_STATUS_CLAIMED,
+    REPORT_STATUS_RESOLVED,
+    REPORT_STATUS_DISMISSED,
+];
+
+pub const REPORT_RESOLUTION_DELETE: &str = "delete";
+pub const REPORT_RESOLUTION_TIMEOUT: &str = "timeout";
+pub const REPORT_RESOLUTION_BAN: &str = "ban";
+pub const REPORT_RESOLUTION_DISMISS: &str = "dismiss";
+
+/// A message or user flagged by a user. `snapshot` keeps the reported
+/// content as it was when reported, even if it is deleted later.
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct Report {
+    pub id: String,
+    pub reporter_user_id: String,
+    pub reported_user_id: String,
+    /// `None` for reports of a user rather than a message.
+    pub message_id: Option<String>,
+    pub channel_id: Option<String>,
+    pub reason: Option<String>,
+    pub snapshot: String,
+    pub status: String,
+    pub claimed_by_user_id: Option<String>,
+    pub claimed_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub resolved_by_user_id: Option<String>,
+    pub resolution: Option<String>,
+    pub resolution_note: Option<String>,
+    pub resolved_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Report {
+    pub fn is_pending(&self) -> bool {
+        self.status == REPORT_STATUS_OPEN || self.status == REPORT_STATUS_CLAIMED
+    }
+
+    pub fn to_resource(&self) -> ReportResource {
+        ReportResource {
+            id: self.id.to_owned(),
+            reporterUserId: self.reporter_user_id.to_owned(),
+            reportedUserId: self.reported_user_id.to_owned(),
+            messageId: self.message_id.to_owned(),
+            channelId: self.channel_id.to_owned(),
+            reason: self.reason.to_owned(),
+            snapshot: serde_json::from_str(&self.snapshot).unwrap_or(serde_json::Value::Null),
+            status: self.status.to_owned(),
+            claimedByUserId: self.claimed_by_user_id.to_owned(),
+            claimedAt: self.claimed_at.to_owned(),
+            resolvedByUserId: self.resolved_by_user_id.to_owned(),
+            resolution: self.resolution.to_owned(),
+            resolutionNote: self.resolution_note.to_owned(),
+            resolvedAt: self.resolved_at.to_owned(),
+            createdAt: self.created_at.to_owned(),
+            updatedAt: self.updated_at.to_owned(),
+        }
+    }
+}
+
+/// Words or phrases, matched case-insensitively on word boundaries.
+pub const AUTOMOD_RULE_BLOCKED_WORDS: &str = "blocked_words";
+/// Regular expressions matched against the whole content.
+pub const AUTOMOD_RULE_REGEX: &str = "regex";
+/// Invites to other chat communities.
+pub const AUTOMOD_RULE_INVITES: &str = "invites";
+/// Links to domains that are not explicitly allowed.
+pub const AUTOMOD_RULE_LINKS: &str = "links";
+/// Messages written mostly in capital letters.
+pub const AUTOMOD_RULE_CAPS: &str = "caps";
+/// Messages with too many emojis.
+pub const AUTOMOD_RULE_EMOJI_SPAM: &str = "emoji_spam";
+/// The same content sent over and over by one user.
+pub const AUTOMOD_RULE_DUPLICATE_FLOOD: &str = "duplicate_flood";
+
+pub const AUTOMOD_RULE_TYPES: [&str; 7] = [
+    AUTOMOD_RULE_BLOCKED_WORDS,
+    AUTOMOD_RULE_REGEX,
+    AUTOMOD_RULE_INVITES,
+    AUTOMOD_RULE_LINKS,
+    AUTOMOD_RULE_CAPS,
+    AUTOMOD_RULE_EMOJI_SPAM,
+    AUTOMOD_RULE_DUPLICATE_FLOOD,
+];
+
+/// Refuse the message.
+pub const AUTOMOD_ACTION_BLOCK: &str = "block";
+/// Post the message and file a report for moderators.
+pub const AUTOMOD_ACTION_FLAG: &str = "flag";
+/// Refuse the message and time out the sender.
+pub const AUTOMOD_ACTION_TIMEOUT: &str = "timeout";
+
+pub const AUTOMOD_ACTIONS: [&str; 3] = [
+    AUTOMOD_ACTION_BLOCK,
+    AUTOMOD_ACTION_FLAG,
+    AUTOMOD_ACTION_TIMEOUT,
+];
+
+/// A content filter applied before messages are stored. `config` holds the
+/// JSON settings of its `rule_type`.
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct AutomodRule {
+    pub id: String,
+    pub name: String,
+    pub rule_type: String,
+    pub config: String,
+    pub action: String,
+    /// Only set for the timeout action.
+    pub timeout_seconds: Option<i32>,
+    /// `None` applies the rule to every channel.
+    pub channel_id: Option<String>,
+    pub is_active: i8,
+    pub created_by_user_id: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl AutomodRule {
+    pub fn to_resource(&self) -> AutomodRuleResource {
+        AutomodRuleResource {
+            id: self.id.to_owned(),
+            name: self.name.to_owned(),
+            ruleType: self.rule_type.to_owned(),
+            config: serde_json::from_str(&self.config).unwrap_or(serde_json::Value::Null),
+            action: self.action.to_owned(),
+            timeoutSeconds: self.timeout_seconds,
+            channelId: self.channel_id.to_owned(),
+            isActive: self.is_active != 0,
+            createdByUserId: self.created_by_user_id.to_owned(),
+            createdAt: self.created_at.to_owned(),
+            updatedAt: self.updated_at.to_owned(),
+        }
+    }
+}
+
+/// A cached link preview. Exactly one of `preview`, the JSON of a
+/// `UrlPreview`, and `error` is set.
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct LinkPreview {
+    pub url_hash: String,
+    pub url: String,
+    pub preview: Option<String>,
+    pub error: Option<String>,
+    /// Set when the URL itself was refused, e.g. for pointing at an
+    /// internal address.
+    pub is_rejected_url: i8,
+    pub fetched_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A link preview unfurled from a message. `preview` is the JSON of a
+/// `UrlPreview`.
+#[derive(Debug, Deserialize, Serialize, FromRow)]
+pub struct MessageEmbed {
+    pub id: String,
+    pub message_id: String,
+    pub position: i32,
+    pub url: String,
+    pub preview: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl MessageEmbed {
+    pub fn to_resource(&self) -> EmbedResource {
+        EmbedResource {
+            url: self.url.to_owned(),
+            preview: serde_json::from_str(&self.preview).unwrap_or(serde_json::Value::Null),
+        }
+    }
+}
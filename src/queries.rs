@@ -1,5 +1,13 @@
-use crate::models::{Channel, Message, User};
+use crate::auth::hash_api_token;
+use crate::models::{
+    ApiToken, AuditLogEntry, AutomodRule, Channel, ChannelMember, ChannelUnreadCount,
+    DirectConversation, DueWebhookDelivery, IncomingWebhook, LinkPreview, LoginAttempt, Message,
+    MessageEmbed, MessageMention, MessageReactionCount, Report, Role, User, UserBan,
+    UserRecoveryCode, UserTimeout, UserTwoFactor, WebhookDelivery, WebhookSubscription,
+    MESSAGE_TYPE_DEFAULT, RESERVED_SYSTEM_USER_IDS,
+};
 use crate::AppState;
+use sha2::{Digest, Sha256};
 use sqlx::Result;
 use std::sync::Arc;
 use uuid::Uuid;
@@ -11,7 +19,7 @@ pub async fn get_channels(data: Arc<AppState>) -> Result<Vec<Channel>> {
         SELECT
             *
         FROM channels
-        WHERE deleted_at IS NULL
+        WHERE deleted_at IS NULL AND channel_type = 'text'
         ORDER BY sort_order ASC
         "#
     )
@@ -19,6 +27,42 @@ pub async fn get_channels(data: Arc<AppState>) -> Result<Vec<Channel>> {
     .await;
 }
 
+/// Text channel by id. Direct conversations are never returned.
+pub async fn get_channel_by_id(data: Arc<AppState>, channel_id: &str) -> Result<Option<Channel>> {
+    sqlx::query_as!(
+        Channel,
+        r#"
+        SELECT
+            *
+        FROM channels
+        WHERE id = ? AND deleted_at IS NULL AND channel_type = 'text'
+        "#,
+        channel_id
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+/// Channel by id, direct conversations included. Callers must check access
+/// themselves.
+pub async fn get_any_channel_by_id(
+    data: Arc<AppState>,
+    channel_id: &str,
+) -> Result<Option<Channel>> {
+    sqlx::query_as!(
+        Channel,
+        r#"
+        SELECT
+            *
+        FROM channels
+        WHERE id = ? AND deleted_at IS NULL
+        "#,
+        channel_id
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
 pub async fn get_channel_messages(data: Arc<AppState>, channel_id: String) -> Result<Vec<Message>> {
     return sqlx::query_as!(
         Message,
@@ -31,6 +75,7 @@ pub async fn get_channel_messages(data: Arc<AppState>, channel_id: String) -> Re
             FROM messages
             WHERE deleted_at IS NULL
             AND channel_id = ?
+            AND thread_id IS NULL
             ORDER BY created_at DESC
             LIMIT 100
         ) x
@@ -102,30 +147,67 @@ pub async fn get_users(data: Arc<AppState>, user_ids: Option<&[String]>) -> Resu
 //         .await
 // }
 
+/// Optional attributes of a new message beyond author, channel and content.
+#[derive(Debug, Default)]
+pub struct CreateMessageOptions {
+    pub webhook_id: Option<String>,
+    pub author_display_name: Option<String>,
+    pub author_avatar_url: Option<String>,
+    pub reply_to_message_id: Option<String>,
+    pub thread_id: Option<String>,
+    /// Defaults to `MESSAGE_TYPE_DEFAULT`.
+    pub message_type: Option<&'static str>,
+}
+
 pub async fn create_message(
     data: Arc<AppState>,
     user_id: String,
     channel_id: String,
     content: Option<String>,
+    options: CreateMessageOptions,
 ) -> Result<Message> {
     let id = Uuid::new_v4().to_string();
     let now = chrono::Utc::now();
+    let mut tx = data.db.begin().await?;
 
     sqlx::query!(
         r#"
-        INSERT INTO messages (id, user_id, channel_id, content, created_at, updated_at)
-        VALUES (?, ?, ?, ?, ?, ?)
+        INSERT INTO messages (id, user_id, channel_id, message_type, webhook_id, author_display_name, author_avatar_url, reply_to_message_id, thread_id, content, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
         id,
         user_id,
         channel_id,
+        options.message_type.unwrap_or(MESSAGE_TYPE_DEFAULT),
+        options.webhook_id,
+        options.author_display_name,
+        options.author_avatar_url,
+        options.reply_to_message_id,
+        options.thread_id,
         content,
         now,
         now,
     )
-    .execute(&data.db)
+    .execute(&mut *tx)
     .await?;
 
+    if let Some(thread_id) = &options.thread_id {
+        // Keep updated_at untouched, it tracks edits of the parent itself
+        sqlx::query!(
+            r#"
+            UPDATE messages
+            SET thread_reply_count = thread_reply_count + 1, thread_last_reply_at = ?, updated_at = updated_at
+            WHERE id = ?
+            "#,
+            now,
+            thread_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+
     let message = sqlx::query_as!(
         Message,
         r#"
@@ -141,3 +223,2490 @@ pub async fn create_message(
 
     Ok(message)
 }
+
+pub async fn user_has_permission(
+    data: Arc<AppState>,
+    user_id: &str,
+    permission: &str,
+) -> Result<bool> {
+    let has_permission: Option<bool> = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS(
+            SELECT 1
+            FROM user_permissions up
+            JOIN permissions p ON p.id = up.permission_id
+            WHERE up.user_id = ? AND p.name = ?
+            UNION
+            SELECT 1
+            FROM user_roles ur
+            JOIN role_permissions rp ON rp.role_id = ur.role_id
+            JOIN permissions p ON p.id = rp.permission_id
+            WHERE ur.user_id = ? AND p.name = ?
+        )
+        "#,
+    )
+    .bind(user_id)
+    .bind(permission)
+    .bind(user_id)
+    .bind(permission)
+    .fetch_one(&data.db)
+    .await?;
+
+    Ok(has_permission.unwrap_or(false))
+}
+
+pub async fn get_user_two_factor(
+    data: Arc<AppState>,
+    user_id: &str,
+) -> Result<Option<UserTwoFactor>> {
+    sqlx::query_as!(
+        UserTwoFactor,
+        r#"
+        SELECT
+            *
+        FROM user_two_factor
+        WHERE user_id = ?
+        "#,
+        user_id
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+/// Stores a new, unconfirmed secret. Re-enrolling before confirmation
+/// replaces the pending secret.
+pub async fn upsert_pending_two_factor(
+    data: Arc<AppState>,
+    user_id: &str,
+    secret: &str,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO user_two_factor (user_id, secret, confirmed_at, last_used_step)
+        VALUES (?, ?, NULL, NULL)
+        ON DUPLICATE KEY UPDATE secret = VALUES(secret), confirmed_at = NULL, last_used_step = NULL
+        "#,
+        user_id,
+        secret
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+/// Marks the pending secret as confirmed and replaces any previous recovery
+/// codes with the given hashes, atomically.
+pub async fn confirm_two_factor(
+    data: Arc<AppState>,
+    user_id: &str,
+    used_step: i64,
+    recovery_code_hashes: &[String],
+) -> Result<()> {
+    let now = chrono::Utc::now();
+    let mut tx = data.db.begin().await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE user_two_factor
+        SET confirmed_at = ?, last_used_step = ?
+        WHERE user_id = ?
+        "#,
+        now,
+        used_step,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM user_recovery_codes WHERE user_id = ?", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for code_hash in recovery_code_hashes {
+        sqlx::query!(
+            r#"
+            INSERT INTO user_recovery_codes (id, user_id, code_hash)
+            VALUES (?, ?, ?)
+            "#,
+            Uuid::new_v4().to_string(),
+            user_id,
+            code_hash
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+/// Records `used_step` unless the same or a later step was already used.
+/// Returns whether it was recorded.
+pub async fn update_two_factor_last_used_step(
+    data: Arc<AppState>,
+    user_id: &str,
+    used_step: i64,
+) -> Result<bool> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_two_factor
+        SET last_used_step = ?
+        WHERE user_id = ? AND (last_used_step IS NULL OR last_used_step < ?)
+        "#,
+        used_step,
+        user_id,
+        used_step
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// Removes the two-factor setup of `user_id`. Returns whether there was one.
+pub async fn delete_two_factor(data: Arc<AppState>, user_id: &str) -> Result<bool> {
+    let mut tx = data.db.begin().await?;
+
+    sqlx::query!("DELETE FROM user_recovery_codes WHERE user_id = ?", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    let result = sqlx::query!("DELETE FROM user_two_factor WHERE user_id = ?", user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn get_unused_recovery_codes(
+    data: Arc<AppState>,
+    user_id: &str,
+) -> Result<Vec<UserRecoveryCode>> {
+    sqlx::query_as!(
+        UserRecoveryCode,
+        r#"
+        SELECT
+            *
+        FROM user_recovery_codes
+        WHERE user_id = ? AND used_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+/// Consumes a recovery code. Returns `false` if it was already used by a
+/// concurrent request.
+pub async fn mark_recovery_code_used(data: Arc<AppState>, id: &str) -> Result<bool> {
+    let result = sqlx::query!(
+        "UPDATE user_recovery_codes SET used_at = ? WHERE id = ? AND used_at IS NULL",
+        chrono::Utc::now(),
+        id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn create_login_attempt(
+    data: Arc<AppState>,
+    username: &str,
+    user_id: Option<&str>,
+    ip_address: &str,
+    reason: &str,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO login_attempts (id, username, user_id, ip_address, reason)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+        Uuid::new_v4().to_string(),
+        username,
+        user_id,
+        ip_address,
+        reason
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_login_attempts(
+    data: Arc<AppState>,
+    username: Option<&str>,
+    ip_address: Option<&str>,
+    limit: i64,
+) -> Result<Vec<LoginAttempt>> {
+    let mut sql = String::from("SELECT * FROM login_attempts WHERE 1 = 1");
+
+    if username.is_some() {
+        sql.push_str(" AND username = ?");
+    }
+    if ip_address.is_some() {
+        sql.push_str(" AND ip_address = ?");
+    }
+    sql.push_str(" ORDER BY created_at DESC LIMIT ?");
+
+    let mut query = sqlx::query_as::<_, LoginAttempt>(&sql);
+
+    if let Some(username) = username {
+        query = query.bind(username);
+    }
+    if let Some(ip_address) = ip_address {
+        query = query.bind(ip_address);
+    }
+
+    query.bind(limit).fetch_all(&data.db).await
+}
+
+pub async fn create_bot_user(
+    data: Arc<AppState>,
+    username: &str,
+    display_name: &str,
+) -> Result<User> {
+    let id = Uuid::new_v4().to_string();
+
+    // Bots cannot log in with a password; "!" is not a valid PHC string
+    sqlx::query!(
+        r#"
+        INSERT INTO users (id, username, display_name, password, is_system_user)
+        VALUES (?, ?, ?, '!', TRUE)
+        "#,
+        id,
+        username,
+        display_name
+    )
+    .execute(&data.db)
+    .await?;
+
+    get_user_by_id(data, id).await
+}
+
+/// Bot accounts, leaving out the reserved system users.
+pub async fn get_bot_users(data: Arc<AppState>) -> Result<Vec<User>> {
+    sqlx::query_as!(
+        User,
+        r#"
+        SELECT * FROM users
+        WHERE is_system_user = TRUE AND id NOT IN (?, ?)
+        ORDER BY username ASC
+        "#,
+        RESERVED_SYSTEM_USER_IDS[0],
+        RESERVED_SYSTEM_USER_IDS[1]
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+/// Stores a newly generated token. Only its hash and a short prefix (so
+/// users can tell tokens apart) are persisted.
+pub async fn create_api_token(
+    data: Arc<AppState>,
+    user_id: &str,
+    name: &str,
+    token: &str,
+    scopes: &[String],
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    created_by_user_id: &str,
+) -> Result<ApiToken> {
+    let id = Uuid::new_v4().to_string();
+    let token_hash = hash_api_token(token);
+    let token_prefix: String = token.chars().take(12).collect();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (id, user_id, name, token_hash, token_prefix, scopes, expires_at, created_by_user_id)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+        id,
+        user_id,
+        name,
+        token_hash,
+        token_prefix,
+        scopes.join(","),
+        expires_at,
+        created_by_user_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    sqlx::query_as!(ApiToken, "SELECT * FROM api_tokens WHERE id = ?", id)
+        .fetch_one(&data.db)
+        .await
+}
+
+pub async fn get_api_tokens_for_user(data: Arc<AppState>, user_id: &str) -> Result<Vec<ApiToken>> {
+    sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT
+            *
+        FROM api_tokens
+        WHERE user_id = ?
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+/// Looks up a token that is neither revoked nor expired.
+pub async fn get_active_api_token_by_hash(
+    data: Arc<AppState>,
+    token_hash: &str,
+) -> Result<Option<ApiToken>> {
+    sqlx::query_as!(
+        ApiToken,
+        r#"
+        SELECT
+            *
+        FROM api_tokens
+        WHERE token_hash = ?
+        AND revoked_at IS NULL
+        AND (expires_at IS NULL OR expires_at > ?)
+        "#,
+        token_hash,
+        chrono::Utc::now()
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+pub async fn touch_api_token(data: Arc<AppState>, token_id: &str) -> Result<()> {
+    sqlx::query!(
+        "UPDATE api_tokens SET last_used_at = ? WHERE id = ?",
+        chrono::Utc::now(),
+        token_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns `false` if no active token with that id belongs to the user.
+pub async fn revoke_api_token(data: Arc<AppState>, user_id: &str, token_id: &str) -> Result<bool> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = ?
+        WHERE id = ? AND user_id = ? AND revoked_at IS NULL
+        "#,
+        chrono::Utc::now(),
+        token_id,
+        user_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn get_channel_incoming_webhooks(
+    data: Arc<AppState>,
+    channel_id: &str,
+) -> Result<Vec<IncomingWebhook>> {
+    sqlx::query_as!(
+        IncomingWebhook,
+        r#"
+        SELECT
+            *
+        FROM incoming_webhooks
+        WHERE channel_id = ? AND deleted_at IS NULL
+        ORDER BY created_at ASC
+        "#,
+        channel_id
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+pub async fn get_incoming_webhook(
+    data: Arc<AppState>,
+    webhook_id: &str,
+) -> Result<Option<IncomingWebhook>> {
+    sqlx::query_as!(
+        IncomingWebhook,
+        r#"
+        SELECT
+            *
+        FROM incoming_webhooks
+        WHERE id = ? AND deleted_at IS NULL
+        "#,
+        webhook_id
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+pub async fn create_incoming_webhook(
+    data: Arc<AppState>,
+    channel_id: &str,
+    name: &str,
+    avatar_url: Option<&str>,
+    token: &str,
+    rate_limit_per_minute: i32,
+    created_by_user_id: &str,
+) -> Result<IncomingWebhook> {
+    let id = Uuid::new_v4().to_string();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO incoming_webhooks (id, channel_id, name, avatar_url, token_hash, rate_limit_per_minute, created_by_user_id)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+        id,
+        channel_id,
+        name,
+        avatar_url,
+        hash_api_token(token),
+        rate_limit_per_minute,
+        created_by_user_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    sqlx::query_as!(
+        IncomingWebhook,
+        "SELECT * FROM incoming_webhooks WHERE id = ?",
+        id
+    )
+    .fetch_one(&data.db)
+    .await
+}
+
+pub async fn update_incoming_webhook(
+    data: Arc<AppState>,
+    webhook_id: &str,
+    name: &str,
+    avatar_url: Option<&str>,
+    rate_limit_per_minute: i32,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE incoming_webhooks
+        SET name = ?, avatar_url = ?, rate_limit_per_minute = ?
+        WHERE id = ? AND deleted_at IS NULL
+        "#,
+        name,
+        avatar_url,
+        rate_limit_per_minute,
+        webhook_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_incoming_webhook(data: Arc<AppState>, webhook_id: &str) -> Result<bool> {
+    let result = sqlx::query!(
+        "UPDATE incoming_webhooks SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL",
+        chrono::Utc::now(),
+        webhook_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn get_webhook_subscriptions(data: Arc<AppState>) -> Result<Vec<WebhookSubscription>> {
+    sqlx::query_as!(
+        WebhookSubscription,
+        r#"
+        SELECT
+            *
+        FROM webhook_subscriptions
+        WHERE deleted_at IS NULL
+        ORDER BY created_at ASC
+        "#
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+pub async fn get_webhook_subscription(
+    data: Arc<AppState>,
+    subscription_id: &str,
+) -> Result<Option<WebhookSubscription>> {
+    sqlx::query_as!(
+        WebhookSubscription,
+        r#"
+        SELECT
+            *
+        FROM webhook_subscriptions
+        WHERE id = ? AND deleted_at IS NULL
+        "#,
+        subscription_id
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+/// Active subscriptions that want to receive `event_type`.
+pub async fn get_webhook_subscriptions_for_event(
+    data: Arc<AppState>,
+    event_type: &str,
+) -> Result<Vec<WebhookSubscription>> {
+    sqlx::query_as!(
+        WebhookSubscription,
+        r#"
+        SELECT
+            *
+        FROM webhook_subscriptions
+        WHERE deleted_at IS NULL
+        AND is_active = TRUE
+        AND FIND_IN_SET(?, event_types) > 0
+        "#,
+        event_type
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+pub async fn create_webhook_subscription(
+    data: Arc<AppState>,
+    name: &str,
+    url: &str,
+    secret: &str,
+    event_types: &[String],
+    created_by_user_id: &str,
+) -> Result<WebhookSubscription> {
+    let id = Uuid::new_v4().to_string();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO webhook_subscriptions (id, name, url, secret, event_types, created_by_user_id)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+        id,
+        name,
+        url,
+        secret,
+        event_types.join(","),
+        created_by_user_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    sqlx::query_as!(
+        WebhookSubscription,
+        "SELECT * FROM webhook_subscriptions WHERE id = ?",
+        id
+    )
+    .fetch_one(&data.db)
+    .await
+}
+
+pub async fn update_webhook_subscription(
+    data: Arc<AppState>,
+    subscription_id: &str,
+    name: &str,
+    url: &str,
+    event_types: &[String],
+    is_active: bool,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE webhook_subscriptions
+        SET name = ?, url = ?, event_types = ?, is_active = ?
+        WHERE id = ? AND deleted_at IS NULL
+        "#,
+        name,
+        url,
+        event_types.join(","),
+        is_active,
+        subscription_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_webhook_subscription(
+    data: Arc<AppState>,
+    subscription_id: &str,
+) -> Result<bool> {
+    let result = sqlx::query!(
+        "UPDATE webhook_subscriptions SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL",
+        chrono::Utc::now(),
+        subscription_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+pub async fn create_webhook_delivery(
+    data: Arc<AppState>,
+    id: &str,
+    subscription_id: &str,
+    event_type: &str,
+    payload: &str,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO webhook_deliveries (id, subscription_id, event_type, payload, status, next_attempt_at)
+        VALUES (?, ?, ?, ?, 'pending', ?)
+        "#,
+        id,
+        subscription_id,
+        event_type,
+        payload,
+        chrono::Utc::now()
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+/// Pending deliveries whose next attempt is due, oldest first. Deliveries of
+/// deleted or deactivated subscriptions are left alone.
+pub async fn get_due_webhook_deliveries(
+    data: Arc<AppState>,
+    limit: i64,
+) -> Result<Vec<DueWebhookDelivery>> {
+    sqlx::query_as!(
+        DueWebhookDelivery,
+        r#"
+        SELECT
+            d.id,
+            d.event_type,
+            d.payload,
+            d.attempts,
+            s.url,
+            s.secret
+        FROM webhook_deliveries d
+        JOIN webhook_subscriptions s ON s.id = d.subscription_id
+        WHERE d.status = 'pending'
+        AND d.next_attempt_at <= ?
+        AND s.deleted_at IS NULL
+        AND s.is_active = TRUE
+        ORDER BY d.next_attempt_at ASC
+        LIMIT ?
+        "#,
+        chrono::Utc::now(),
+        limit
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+/// Records the outcome of one delivery attempt. `next_attempt_at` is `None`
+/// once the delivery succeeded or was given up.
+pub async fn record_webhook_delivery_attempt(
+    data: Arc<AppState>,
+    delivery_id: &str,
+    status: &str,
+    attempts: i32,
+    next_attempt_at: Option<chrono::DateTime<chrono::Utc>>,
+    response_status: Option<i32>,
+    error: Option<&str>,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE webhook_deliveries
+        SET status = ?, attempts = ?, next_attempt_at = ?, last_attempt_at = ?, last_response_status = ?, last_error = ?
+        WHERE id = ?
+        "#,
+        status,
+        attempts,
+        next_attempt_at,
+        chrono::Utc::now(),
+        response_status,
+        error,
+        delivery_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_webhook_deliveries(
+    data: Arc<AppState>,
+    subscription_id: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<WebhookDelivery>> {
+    sqlx::query_as!(
+        WebhookDelivery,
+        r#"
+        SELECT
+            *
+        FROM webhook_deliveries
+        WHERE subscription_id = ?
+        ORDER BY created_at DESC
+        LIMIT ? OFFSET ?
+        "#,
+        subscription_id,
+        limit,
+        offset
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+pub async fn get_message_by_id(data: Arc<AppState>, message_id: &str) -> Result<Option<Message>> {
+    sqlx::query_as!(
+        Message,
+        r#"
+        SELECT
+            *
+        FROM messages
+        WHERE id = ? AND deleted_at IS NULL
+        "#,
+        message_id
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+/// Returns `false` if the user already reacted with this emoji.
+pub async fn add_message_reaction(
+    data: Arc<AppState>,
+    message_id: &str,
+    user_id: &str,
+    emoji: &str,
+) -> Result<bool> {
+    let id = Uuid::new_v4().to_string();
+
+    let result = sqlx::query!(
+        r#"
+        INSERT IGNORE INTO message_reactions (id, message_id, user_id, emoji)
+        VALUES (?, ?, ?, ?)
+        "#,
+        id,
+        message_id,
+        user_id,
+        emoji
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn remove_message_reaction(
+    data: Arc<AppState>,
+    message_id: &str,
+    user_id: &str,
+    emoji: &str,
+) -> Result<bool> {
+    let result = sqlx::query!(
+        "DELETE FROM message_reactions WHERE message_id = ? AND user_id = ? AND emoji = ?",
+        message_id,
+        user_id,
+        emoji
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn count_message_reactions(
+    data: Arc<AppState>,
+    message_id: &str,
+    emoji: &str,
+) -> Result<i64> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS count
+        FROM message_reactions
+        WHERE message_id = ? AND emoji = ?
+        "#,
+        message_id,
+        emoji
+    )
+    .fetch_one(&data.db)
+    .await?;
+
+    Ok(row.count)
+}
+
+/// Aggregated reactions of the given messages, in the order each emoji was
+/// first used on its message.
+pub async fn get_message_reaction_counts(
+    data: Arc<AppState>,
+    message_ids: &[String],
+    user_id: &str,
+) -> Result<Vec<MessageReactionCount>> {
+    if message_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let placeholders = message_ids
+        .iter()
+        .map(|_| "?")
+        .collect::<Vec<_>>()
+        .join(", ");
+    let sql = format!(
+        r#"
+        SELECT
+            message_id,
+            emoji,
+            COUNT(*) AS count,
+            CAST(MAX(user_id = ?) AS SIGNED) AS me
+        FROM message_reactions
+        WHERE message_id IN ({})
+        GROUP BY message_id, emoji
+        ORDER BY MIN(created_at) ASC
+        "#,
+        placeholders
+    );
+
+    let mut query = sqlx::query_as::<_, MessageReactionCount>(&sql).bind(user_id);
+
+    for message_id in message_ids {
+        query = query.bind(message_id);
+    }
+
+    query.fetch_all(&data.db).await
+}
+
+/// Loads messages by id, including deleted ones so quotes of them can still
+/// be rendered.
+pub async fn get_messages_by_ids(
+    data: Arc<AppState>,
+    message_ids: &[String],
+) -> Result<Vec<Message>> {
+    if message_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let placeholders = message_ids
+        .iter()
+        .map(|_| "?")
+        .collect::<Vec<_>>()
+        .join(", ");
+    let sql = format!("SELECT * FROM messages WHERE id IN ({})", placeholders);
+
+    let mut query = sqlx::query_as::<_, Message>(&sql);
+
+    for message_id in message_ids {
+        query = query.bind(message_id);
+    }
+
+    query.fetch_all(&data.db).await
+}
+
+pub async fn get_thread_messages(
+    data: Arc<AppState>,
+    thread_id: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Message>> {
+    sqlx::query_as!(
+        Message,
+        r#"
+        SELECT
+            *
+        FROM messages
+        WHERE thread_id = ? AND deleted_at IS NULL
+        ORDER BY created_at ASC
+        LIMIT ? OFFSET ?
+        "#,
+        thread_id,
+        limit,
+        offset
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+/// Parent messages of the channel that have replies, most recently active
+/// thread first.
+pub async fn get_channel_threads(
+    data: Arc<AppState>,
+    channel_id: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Message>> {
+    sqlx::query_as!(
+        Message,
+        r#"
+        SELECT
+            *
+        FROM messages
+        WHERE channel_id = ? AND deleted_at IS NULL AND thread_reply_count > 0
+        ORDER BY thread_last_reply_at DESC
+        LIMIT ? OFFSET ?
+        "#,
+        channel_id,
+        limit,
+        offset
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+pub async fn get_pinned_messages(data: Arc<AppState>, channel_id: &str) -> Result<Vec<Message>> {
+    sqlx::query_as!(
+        Message,
+        r#"
+        SELECT
+            *
+        FROM messages
+        WHERE channel_id = ? AND pinned_at IS NOT NULL AND deleted_at IS NULL
+        ORDER BY pinned_at DESC
+        "#,
+        channel_id
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PinOutcome {
+    Pinned,
+    AlreadyPinned,
+    LimitReached,
+}
+
+/// Pins a message of `channel_id` unless the channel already has
+/// `max_pins` pinned messages. The channel row stays locked until the pin
+/// is written, so concurrent pins cannot exceed the limit.
+pub async fn pin_message(
+    data: Arc<AppState>,
+    channel_id: &str,
+    message_id: &str,
+    user_id: &str,
+    max_pins: i64,
+) -> Result<PinOutcome> {
+    let mut tx = data.db.begin().await?;
+
+    sqlx::query!(
+        "SELECT id FROM channels WHERE id = ? FOR UPDATE",
+        channel_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let pin_count = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) AS count
+        FROM messages
+        WHERE channel_id = ? AND pinned_at IS NOT NULL AND deleted_at IS NULL
+        "#,
+        channel_id
+    )
+    .fetch_one(&mut *tx)
+    .await?
+    .count;
+
+    if pin_count >= max_pins {
+        return Ok(PinOutcome::LimitReached);
+    }
+
+    let result = sqlx::query!(
+        r#"
+        UPDATE messages
+        SET pinned_at = ?, pinned_by_user_id = ?, updated_at = updated_at
+        WHERE id = ? AND channel_id = ? AND pinned_at IS NULL
+        "#,
+        chrono::Utc::now(),
+        user_id,
+        message_id,
+        channel_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    if result.rows_affected() > 0 {
+        Ok(PinOutcome::Pinned)
+    } else {
+        Ok(PinOutcome::AlreadyPinned)
+    }
+}
+
+/// Returns `false` if the message was not pinned.
+pub async fn unpin_message(data: Arc<AppState>, message_id: &str) -> Result<bool> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE messages
+        SET pinned_at = NULL, pinned_by_user_id = NULL, updated_at = updated_at
+        WHERE id = ? AND pinned_at IS NOT NULL
+        "#,
+        message_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Filters of a message search. Every filter that is set must match.
+#[derive(Debug, Default)]
+pub struct MessageSearchFilters {
+    /// Boolean mode FULLTEXT query on the message content.
+    pub fulltext_query: Option<String>,
+    pub author_id: Option<String>,
+    pub channel_id: Option<String>,
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_before: Option<chrono::DateTime<chrono::Utc>>,
+    pub has_attachment: Option<bool>,
+    /// Only messages mentioning this user directly, via one of their roles
+    /// or via `@everyone`.
+    pub mentions_user_id: Option<String>,
+}
+
+/// Searches regular messages of all channels visible to `viewer_user_id`,
+/// newest first.
+pub async fn search_messages(
+    data: Arc<AppState>,
+    viewer_user_id: &str,
+    filters: &MessageSearchFilters,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Message>> {
+    let mut sql = String::from(
+        r#"
+        SELECT
+            m.*
+        FROM messages m
+        INNER JOIN channels c ON c.id = m.channel_id AND c.deleted_at IS NULL
+        WHERE m.deleted_at IS NULL
+        AND m.message_type = ?
+        AND (
+            c.channel_type = 'text'
+            OR EXISTS (SELECT 1 FROM channel_members cm WHERE cm.channel_id = c.id AND cm.user_id = ?)
+        )
+        "#,
+    );
+
+    if filters.fulltext_query.is_some() {
+        sql.push_str(" AND MATCH (m.content) AGAINST (? IN BOOLEAN MODE)");
+    }
+    if filters.author_id.is_some() {
+        sql.push_str(" AND m.user_id = ?");
+    }
+    if filters.channel_id.is_some() {
+        sql.push_str(" AND m.channel_id = ?");
+    }
+    if filters.created_after.is_some() {
+        sql.push_str(" AND m.created_at >= ?");
+    }
+    if filters.created_before.is_some() {
+        sql.push_str(" AND m.created_at < ?");
+    }
+    match filters.has_attachment {
+        Some(true) => {
+            sql.push_str(" AND EXISTS (SELECT 1 FROM attachments a WHERE a.model_id = m.id)")
+        }
+        Some(false) => {
+            sql.push_str(" AND NOT EXISTS (SELECT 1 FROM attachments a WHERE a.model_id = m.id)")
+        }
+        None => {}
+    }
+    if filters.mentions_user_id.is_some() {
+        sql.push_str(
+            r#"
+            AND EXISTS (
+                SELECT 1
+                FROM message_mentions mm
+                WHERE mm.message_id = m.id
+                AND (
+                    mm.user_id = ?
+                    OR mm.mention_type = 'everyone'
+                    OR mm.role_id IN (SELECT ur.role_id FROM user_roles ur WHERE ur.user_id = ?)
+                )
+            )
+            "#,
+        );
+    }
+    sql.push_str(" ORDER BY m.created_at DESC LIMIT ? OFFSET ?");
+
+    let mut query = sqlx::query_as::<_, Message>(&sql)
+        .bind(MESSAGE_TYPE_DEFAULT)
+        .bind(viewer_user_id);
+
+    if let Some(fulltext_query) = &filters.fulltext_query {
+        query = query.bind(fulltext_query);
+    }
+    if let Some(author_id) = &filters.author_id {
+        query = query.bind(author_id);
+    }
+    if let Some(channel_id) = &filters.channel_id {
+        query = query.bind(channel_id);
+    }
+    if let Some(created_after) = filters.created_after {
+        query = query.bind(created_after);
+    }
+    if let Some(created_before) = filters.created_before {
+        query = query.bind(created_before);
+    }
+    if let Some(mentions_user_id) = &filters.mentions_user_id {
+        query = query.bind(mentions_user_id).bind(mentions_user_id);
+    }
+
+    query.bind(limit).bind(offset).fetch_all(&data.db).await
+}
+
+pub async fn get_users_by_usernames(
+    data: Arc<AppState>,
+    usernames: &[String],
+) -> Result<Vec<User>> {
+    if usernames.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let placeholders = usernames.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+    let sql = format!("SELECT * FROM users WHERE username IN ({})", placeholders);
+
+    let mut query = sqlx::query_as::<_, User>(&sql);
+
+    for username in usernames {
+        query = query.bind(username);
+    }
+
+    query.fetch_all(&data.db).await
+}
+
+pub async fn get_roles_by_names(data: Arc<AppState>, names: &[String]) -> Result<Vec<Role>> {
+    if names.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let placeholders = names.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+    let sql = format!("SELECT * FROM roles WHERE name IN ({})", placeholders);
+
+    let mut query = sqlx::query_as::<_, Role>(&sql);
+
+    for name in names {
+        query = query.bind(name);
+    }
+
+    query.fetch_all(&data.db).await
+}
+
+pub async fn get_role_member_ids(data: Arc<AppState>, role_ids: &[String]) -> Result<Vec<String>> {
+    if role_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let placeholders = role_ids.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+    let sql = format!(
+        "SELECT DISTINCT user_id FROM user_roles WHERE role_id IN ({})",
+        placeholders
+    );
+
+    let mut query = sqlx::query_scalar::<_, String>(&sql);
+
+    for role_id in role_ids {
+        query = query.bind(role_id);
+    }
+
+    query.fetch_all(&data.db).await
+}
+
+/// Stores the mentions of a message. `name` is ignored.
+pub async fn create_message_mentions(
+    data: Arc<AppState>,
+    mentions: &[MessageMention],
+) -> Result<()> {
+    let mut tx = data.db.begin().await?;
+
+    for mention in mentions {
+        sqlx::query!(
+            r#"
+            INSERT INTO message_mentions (id, message_id, mention_type, user_id, role_id)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+            mention.id,
+            mention.message_id,
+            mention.mention_type,
+            mention.user_id,
+            mention.role_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+pub async fn get_message_mentions(
+    data: Arc<AppState>,
+    message_ids: &[String],
+) -> Result<Vec<MessageMention>> {
+    if message_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let placeholders = message_ids
+        .iter()
+        .map(|_| "?")
+        .collect::<Vec<_>>()
+        .join(", ");
+    let sql = format!(
+        r#"
+        SELECT
+            mm.id,
+            mm.message_id,
+            mm.mention_type,
+            mm.user_id,
+            mm.role_id,
+            COALESCE(u.username, r.name) AS name
+        FROM message_mentions mm
+        LEFT JOIN users u ON u.id = mm.user_id
+        LEFT JOIN roles r ON r.id = mm.role_id
+        WHERE mm.message_id IN ({})
+        ORDER BY mm.created_at ASC
+        "#,
+        placeholders
+    );
+
+    let mut query = sqlx::query_as::<_, MessageMention>(&sql);
+
+    for message_id in message_ids {
+        query = query.bind(message_id);
+    }
+
+    query.fetch_all(&data.db).await
+}
+
+/// Moves the read marker of `user_id` in `channel_id` to `message`, unless
+/// it already points at a later message by `(created_at, id)`.
+pub async fn upsert_channel_read_state(
+    data: Arc<AppState>,
+    user_id: &str,
+    channel_id: &str,
+    message: &Message,
+) -> Result<()> {
+    // Assignments run left to right, so `last_read_at` follows the message
+    // id only if the first assignment moved it
+    sqlx::query!(
+        r#"
+        INSERT INTO channel_read_states (user_id, channel_id, last_read_message_id, last_read_at)
+        VALUES (?, ?, ?, ?)
+        ON DUPLICATE KEY UPDATE
+            last_read_message_id = IF(
+                (VALUES(last_read_at), VALUES(last_read_message_id)) > (last_read_at, last_read_message_id),
+                VALUES(last_read_message_id),
+                last_read_message_id
+            ),
+            last_read_at = IF(
+                last_read_message_id = VALUES(last_read_message_id),
+                VALUES(last_read_at),
+                last_read_at
+            )
+        "#,
+        user_id,
+        channel_id,
+        message.id,
+        message.created_at
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+/// Unread and unread mention counts of `user_id` per channel, optionally
+/// limited to one channel. Only top level messages of others count, and
+/// channels without a read marker count everything as unread. Messages are
+/// ordered by `(created_at, id)` since timestamps have second precision.
+pub async fn get_channel_unread_counts(
+    data: Arc<AppState>,
+    user_id: &str,
+    channel_id: Option<&str>,
+) -> Result<Vec<ChannelUnreadCount>> {
+    let mut sql = String::from(
+        r#"
+        SELECT
+            c.id AS channel_id,
+            rs.last_read_message_id,
+            (
+                SELECT COUNT(*)
+                FROM messages m
+                WHERE m.channel_id = c.id
+                AND m.deleted_at IS NULL
+                AND m.thread_id IS NULL
+                AND m.user_id <> ?
+                AND (
+                    rs.last_read_at IS NULL
+                    OR (m.created_at, m.id) > (rs.last_read_at, rs.last_read_message_id)
+                )
+            ) AS unread_count,
+            (
+                SELECT COUNT(*)
+                FROM messages m
+                WHERE m.channel_id = c.id
+                AND m.deleted_at IS NULL
+                AND m.thread_id IS NULL
+                AND m.user_id <> ?
+                AND (
+                    rs.last_read_at IS NULL
+                    OR (m.created_at, m.id) > (rs.last_read_at, rs.last_read_message_id)
+                )
+                AND EXISTS (
+                    SELECT 1
+                    FROM message_mentions mm
+                    WHERE mm.message_id = m.id
+                    AND (
+                        mm.user_id = ?
+                        OR mm.mention_type = 'everyone'
+                        OR mm.role_id IN (SELECT ur.role_id FROM user_roles ur WHERE ur.user_id = ?)
+                    )
+                )
+            ) AS mention_count
+        FROM channels c
+        LEFT JOIN channel_read_states rs ON rs.channel_id = c.id AND rs.user_id = ?
+        WHERE c.deleted_at IS NULL
+        AND (
+            c.channel_type = 'text'
+            OR EXISTS (SELECT 1 FROM channel_members cm WHERE cm.channel_id = c.id AND cm.user_id = ?)
+        )
+        "#,
+    );
+
+    if channel_id.is_some() {
+        sql.push_str(" AND c.id = ?");
+    }
+
+    let mut query = sqlx::query_as::<_, ChannelUnreadCount>(&sql)
+        .bind(user_id)
+        .bind(user_id)
+        .bind(user_id)
+        .bind(user_id)
+        .bind(user_id)
+        .bind(user_id);
+
+    if let Some(channel_id) = channel_id {
+        query = query.bind(channel_id);
+    }
+
+    query.fetch_all(&data.db).await
+}
+
+/// Channel by id if `user_id` may see it: any text channel, or a direct
+/// conversation the user is a member of.
+pub async fn get_accessible_channel(
+    data: Arc<AppState>,
+    channel_id: &str,
+    user_id: &str,
+) -> Result<Option<Channel>> {
+    sqlx::query_as!(
+        Channel,
+        r#"
+        SELECT
+            c.*
+        FROM channels c
+        WHERE c.id = ? AND c.deleted_at IS NULL
+        AND (
+            c.channel_type = 'text'
+            OR EXISTS (SELECT 1 FROM channel_members cm WHERE cm.channel_id = c.id AND cm.user_id = ?)
+        )
+        "#,
+        channel_id,
+        user_id
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+pub async fn get_channel_members(
+    data: Arc<AppState>,
+    channel_ids: &[String],
+) -> Result<Vec<ChannelMember>> {
+    if channel_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let placeholders = channel_ids
+        .iter()
+        .map(|_| "?")
+        .collect::<Vec<_>>()
+        .join(", ");
+    let sql = format!(
+        "SELECT * FROM channel_members WHERE channel_id IN ({}) ORDER BY created_at ASC",
+        placeholders
+    );
+
+    let mut query = sqlx::query_as::<_, ChannelMember>(&sql);
+
+    for channel_id in channel_ids {
+        query = query.bind(channel_id);
+    }
+
+    query.fetch_all(&data.db).await
+}
+
+/// Direct conversations of `user_id`, most recently active first.
+pub async fn get_direct_conversations(
+    data: Arc<AppState>,
+    user_id: &str,
+) -> Result<Vec<DirectConversation>> {
+    sqlx::query_as::<_, DirectConversation>(
+        r#"
+        SELECT
+            x.*
+        FROM (
+            SELECT
+                c.id,
+                c.created_at,
+                (
+                    SELECT MAX(m.created_at)
+                    FROM messages m
+                    WHERE m.channel_id = c.id AND m.deleted_at IS NULL
+                ) AS last_message_at
+            FROM channels c
+            INNER JOIN channel_members cm ON cm.channel_id = c.id AND cm.user_id = ?
+            WHERE c.channel_type = 'direct' AND c.deleted_at IS NULL
+        ) x
+        ORDER BY COALESCE(x.last_message_at, x.created_at) DESC
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(&data.db)
+    .await
+}
+
+/// Identifies a direct conversation by its members, whatever their order.
+fn direct_conversation_key(member_ids: &[String]) -> String {
+    let mut member_ids = member_ids.to_vec();
+    member_ids.sort();
+    member_ids.dedup();
+
+    format!("{:x}", Sha256::digest(member_ids.join(",").as_bytes()))
+}
+
+/// Direct conversation whose members are exactly `member_ids`, if any.
+pub async fn find_direct_conversation(
+    data: Arc<AppState>,
+    member_ids: &[String],
+) -> Result<Option<Channel>> {
+    let member_key = direct_conversation_key(member_ids);
+
+    sqlx::query_as!(
+        Channel,
+        r#"
+        SELECT
+            c.*
+        FROM channels c
+        JOIN direct_conversation_keys k ON k.channel_id = c.id
+        WHERE k.member_key = ? AND c.deleted_at IS NULL
+        "#,
+        member_key
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+/// Creates the conversation between `member_ids` and returns it with `true`.
+/// If a concurrent request created it first, that conversation is returned
+/// with `false` instead.
+pub async fn create_direct_conversation(
+    data: Arc<AppState>,
+    member_ids: &[String],
+) -> Result<(Channel, bool)> {
+    let id = Uuid::new_v4().to_string();
+    let member_key = direct_conversation_key(member_ids);
+    let mut tx = data.db.begin().await?;
+
+    // Channel names are unique and direct conversations have none to show
+    sqlx::query!(
+        r#"
+        INSERT INTO channels (id, name, channel_type, sort_order)
+        VALUES (?, ?, 'direct', 0)
+        "#,
+        id,
+        format!("dm-{}", id)
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let inserted = sqlx::query!(
+        "INSERT INTO direct_conversation_keys (member_key, channel_id) VALUES (?, ?)",
+        member_key,
+        id
+    )
+    .execute(&mut *tx)
+    .await;
+
+    match inserted {
+        Ok(_) => {}
+        Err(sqlx::Error::Database(db_error)) if db_error.is_unique_violation() => {
+            tx.rollback().await?;
+
+            let channel = find_direct_conversation(data, member_ids)
+                .await?
+                .ok_or(sqlx::Error::RowNotFound)?;
+
+            return Ok((channel, false));
+        }
+        Err(e) => return Err(e),
+    }
+
+    for member_id in member_ids {
+        sqlx::query!(
+            "INSERT INTO channel_members (channel_id, user_id) VALUES (?, ?)",
+            id,
+            member_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let channel = sqlx::query_as!(
+        Channel,
+        r#"
+        SELECT
+            *
+        FROM channels
+        WHERE id = ?
+        "#,
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((channel, true))
+}
+
+/// A page of top level messages, `offset` counting back from the newest.
+/// The page itself is in chronological order.
+pub async fn get_channel_messages_page(
+    data: Arc<AppState>,
+    channel_id: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Message>> {
+    sqlx::query_as!(
+        Message,
+        r#"
+        SELECT
+            x.*
+        FROM (
+            SELECT
+                *
+            FROM messages
+            WHERE deleted_at IS NULL
+            AND channel_id = ?
+            AND thread_id IS NULL
+            ORDER BY created_at DESC
+            LIMIT ? OFFSET ?
+        ) x
+        ORDER BY x.created_at ASC
+        "#,
+        channel_id,
+        limit,
+        offset
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+pub async fn update_user_dm_privacy(
+    data: Arc<AppState>,
+    user_id: &str,
+    dm_privacy: &str,
+) -> Result<()> {
+    sqlx::query!(
+        "UPDATE users SET dm_privacy = ? WHERE id = ?",
+        dm_privacy,
+        user_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn update_user_custom_status(
+    data: Arc<AppState>,
+    user_id: &str,
+    custom_status: Option<&str>,
+) -> Result<()> {
+    sqlx::query!(
+        "UPDATE users SET custom_status = ? WHERE id = ?",
+        custom_status,
+        user_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn update_user_do_not_disturb(
+    data: Arc<AppState>,
+    user_id: &str,
+    do_not_disturb: bool,
+) -> Result<()> {
+    sqlx::query!(
+        "UPDATE users SET do_not_disturb = ? WHERE id = ?",
+        do_not_disturb,
+        user_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns `false` if `user_id` had already blocked `blocked_user_id`.
+pub async fn block_user(data: Arc<AppState>, user_id: &str, blocked_user_id: &str) -> Result<bool> {
+    let result = sqlx::query!(
+        "INSERT IGNORE INTO user_blocks (user_id, blocked_user_id) VALUES (?, ?)",
+        user_id,
+        blocked_user_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn unblock_user(
+    data: Arc<AppState>,
+    user_id: &str,
+    blocked_user_id: &str,
+) -> Result<bool> {
+    let result = sqlx::query!(
+        "DELETE FROM user_blocks WHERE user_id = ? AND blocked_user_id = ?",
+        user_id,
+        blocked_user_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Users blocked by `user_id`, most recently blocked first.
+pub async fn get_blocked_users(data: Arc<AppState>, user_id: &str) -> Result<Vec<User>> {
+    sqlx::query_as!(
+        User,
+        r#"
+        SELECT
+            u.*
+        FROM user_blocks b
+        INNER JOIN users u ON u.id = b.blocked_user_id
+        WHERE b.user_id = ?
+        ORDER BY b.created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+/// Ids of the users `user_id` has blocked.
+pub async fn get_blocked_user_ids(data: Arc<AppState>, user_id: &str) -> Result<Vec<String>> {
+    sqlx::query_scalar!(
+        "SELECT blocked_user_id FROM user_blocks WHERE user_id = ?",
+        user_id
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+/// Ids of the users who have blocked `user_id`.
+pub async fn get_blocker_ids(data: Arc<AppState>, user_id: &str) -> Result<Vec<String>> {
+    sqlx::query_scalar!(
+        "SELECT user_id FROM user_blocks WHERE blocked_user_id = ?",
+        user_id
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+pub async fn create_user_ban(
+    data: Arc<AppState>,
+    user_id: &str,
+    reason: Option<&str>,
+    banned_by_user_id: &str,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<UserBan> {
+    let id = Uuid::new_v4().to_string();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO user_bans (id, user_id, reason, banned_by_user_id, expires_at)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+        id,
+        user_id,
+        reason,
+        banned_by_user_id,
+        expires_at
+    )
+    .execute(&data.db)
+    .await?;
+
+    sqlx::query_as!(UserBan, "SELECT * FROM user_bans WHERE id = ?", id)
+        .fetch_one(&data.db)
+        .await
+}
+
+/// The ban of `user_id` that ends last among those neither lifted nor
+/// expired, if any.
+pub async fn get_user_ban(data: Arc<AppState>, ban_id: &str) -> Result<Option<UserBan>> {
+    sqlx::query_as!(UserBan, "SELECT * FROM user_bans WHERE id = ?", ban_id)
+        .fetch_optional(&data.db)
+        .await
+}
+
+pub async fn get_active_user_ban(data: Arc<AppState>, user_id: &str) -> Result<Option<UserBan>> {
+    sqlx::query_as!(
+        UserBan,
+        r#"
+        SELECT
+            *
+        FROM user_bans
+        WHERE user_id = ?
+        AND lifted_at IS NULL
+        AND (expires_at IS NULL OR expires_at > ?)
+        ORDER BY expires_at IS NULL DESC, expires_at DESC
+        LIMIT 1
+        "#,
+        user_id,
+        chrono::Utc::now()
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+pub async fn get_active_user_bans(data: Arc<AppState>) -> Result<Vec<UserBan>> {
+    sqlx::query_as!(
+        UserBan,
+        r#"
+        SELECT
+            *
+        FROM user_bans
+        WHERE lifted_at IS NULL
+        AND (expires_at IS NULL OR expires_at > ?)
+        ORDER BY created_at DESC
+        "#,
+        chrono::Utc::now()
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+/// Returns `false` if no active ban with that id exists.
+pub async fn lift_user_ban(
+    data: Arc<AppState>,
+    ban_id: &str,
+    lifted_by_user_id: &str,
+) -> Result<bool> {
+    let now = chrono::Utc::now();
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_bans
+        SET lifted_at = ?, lifted_by_user_id = ?
+        WHERE id = ?
+        AND lifted_at IS NULL
+        AND (expires_at IS NULL OR expires_at > ?)
+        "#,
+        now,
+        lifted_by_user_id,
+        ban_id,
+        now
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn create_user_timeout(
+    data: Arc<AppState>,
+    user_id: &str,
+    reason: Option<&str>,
+    created_by_user_id: &str,
+    expires_at: chrono::DateTime<chrono::Utc>,
+) -> Result<UserTimeout> {
+    let id = Uuid::new_v4().to_string();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO user_timeouts (id, user_id, reason, created_by_user_id, expires_at)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+        id,
+        user_id,
+        reason,
+        created_by_user_id,
+        expires_at
+    )
+    .execute(&data.db)
+    .await?;
+
+    sqlx::query_as!(UserTimeout, "SELECT * FROM user_timeouts WHERE id = ?", id)
+        .fetch_one(&data.db)
+        .await
+}
+
+/// The timeout of `user_id` that ends last among those neither lifted nor
+/// expired, if any.
+pub async fn get_user_timeout(
+    data: Arc<AppState>,
+    timeout_id: &str,
+) -> Result<Option<UserTimeout>> {
+    sqlx::query_as!(
+        UserTimeout,
+        "SELECT * FROM user_timeouts WHERE id = ?",
+        timeout_id
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+pub async fn get_active_user_timeout(
+    data: Arc<AppState>,
+    user_id: &str,
+) -> Result<Option<UserTimeout>> {
+    sqlx::query_as!(
+        UserTimeout,
+        r#"
+        SELECT
+            *
+        FROM user_timeouts
+        WHERE user_id = ?
+        AND lifted_at IS NULL
+        AND expires_at > ?
+        ORDER BY expires_at DESC
+        LIMIT 1
+        "#,
+        user_id,
+        chrono::Utc::now()
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+pub async fn get_active_user_timeouts(data: Arc<AppState>) -> Result<Vec<UserTimeout>> {
+    sqlx::query_as!(
+        UserTimeout,
+        r#"
+        SELECT
+            *
+        FROM user_timeouts
+        WHERE lifted_at IS NULL
+        AND expires_at > ?
+        ORDER BY created_at DESC
+        "#,
+        chrono::Utc::now()
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+/// Returns `false` if no active timeout with that id exists.
+pub async fn lift_user_timeout(
+    data: Arc<AppState>,
+    timeout_id: &str,
+    lifted_by_user_id: &str,
+) -> Result<bool> {
+    let now = chrono::Utc::now();
+    let result = sqlx::query!(
+        r#"
+        UPDATE user_timeouts
+        SET lifted_at = ?, lifted_by_user_id = ?
+        WHERE id = ?
+        AND lifted_at IS NULL
+        AND expires_at > ?
+        "#,
+        now,
+        lifted_by_user_id,
+        timeout_id,
+        now
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn create_audit_log_entry(
+    data: Arc<AppState>,
+    actor_user_id: &str,
+    action: &str,
+    target_type: &str,
+    target_id: &str,
+    reason: Option<&str>,
+    changes: &str,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (id, actor_user_id, action, target_type, target_id, reason, changes)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+        Uuid::new_v4().to_string(),
+        actor_user_id,
+        action,
+        target_type,
+        target_id,
+        reason,
+        changes
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct AuditLogFilters {
+    pub actor_user_id: Option<String>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub created_after: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_before: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Newest entries first.
+pub async fn get_audit_log_entries(
+    data: Arc<AppState>,
+    filters: &AuditLogFilters,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<AuditLogEntry>> {
+    let mut sql = String::from("SELECT * FROM audit_log WHERE 1 = 1");
+
+    if filters.actor_user_id.is_some() {
+        sql.push_str(" AND actor_user_id = ?");
+    }
+    if filters.action.is_some() {
+        sql.push_str(" AND action = ?");
+    }
+    if filters.target_type.is_some() {
+        sql.push_str(" AND target_type = ?");
+    }
+    if filters.target_id.is_some() {
+        sql.push_str(" AND target_id = ?");
+    }
+    if filters.created_after.is_some() {
+        sql.push_str(" AND created_at > ?");
+    }
+    if filters.created_before.is_some() {
+        sql.push_str(" AND created_at < ?");
+    }
+    sql.push_str(" ORDER BY created_at DESC, id DESC LIMIT ? OFFSET ?");
+
+    let mut query = sqlx::query_as::<_, AuditLogEntry>(&sql);
+
+    if let Some(actor_user_id) = &filters.actor_user_id {
+        query = query.bind(actor_user_id);
+    }
+    if let Some(action) = &filters.action {
+        query = query.bind(action);
+    }
+    if let Some(target_type) = &filters.target_type {
+        query = query.bind(target_type);
+    }
+    if let Some(target_id) = &filters.target_id {
+        query = query.bind(target_id);
+    }
+    if let Some(created_after) = filters.created_after {
+        query = query.bind(created_after);
+    }
+    if let Some(created_before) = filters.created_before {
+        query = query.bind(created_before);
+    }
+
+    query.bind(limit).bind(offset).fetch_all(&data.db).await
+}
+
+/// Soft-deletes a message. Returns `false` if it was already deleted.
+pub async fn delete_message(
+    data: Arc<AppState>,
+    message_id: &str,
+    deleted_by_user_id: &str,
+) -> Result<bool> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE messages
+        SET deleted_at = ?, deleted_by_user_id = ?
+        WHERE id = ? AND deleted_at IS NULL
+        "#,
+        chrono::Utc::now(),
+        deleted_by_user_id,
+        message_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Users granted `permission` directly or through one of their roles.
+pub async fn get_user_ids_with_permission(
+    data: Arc<AppState>,
+    permission: &str,
+) -> Result<Vec<String>> {
+    sqlx::query_scalar(
+        r#"
+        SELECT up.user_id
+        FROM user_permissions up
+        JOIN permissions p ON p.id = up.permission_id
+        WHERE p.name = ?
+        UNION
+        SELECT ur.user_id
+        FROM user_roles ur
+        JOIN role_permissions rp ON rp.role_id = ur.role_id
+        JOIN permissions p ON p.id = rp.permission_id
+        WHERE p.name = ?
+        "#,
+    )
+    .bind(permission)
+    .bind(permission)
+    .fetch_all(&data.db)
+    .await
+}
+
+pub async fn create_report(
+    data: Arc<AppState>,
+    reporter_user_id: &str,
+    reported_user_id: &str,
+    message_id: Option<&str>,
+    channel_id: Option<&str>,
+    reason: Option<&str>,
+    snapshot: &str,
+) -> Result<Report> {
+    let id = Uuid::new_v4().to_string();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO reports (id, reporter_user_id, reported_user_id, message_id, channel_id, reason, snapshot)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        "#,
+        id,
+        reporter_user_id,
+        reported_user_id,
+        message_id,
+        channel_id,
+        reason,
+        snapshot
+    )
+    .execute(&data.db)
+    .await?;
+
+    sqlx::query_as!(Report, "SELECT * FROM reports WHERE id = ?", id)
+        .fetch_one(&data.db)
+        .await
+}
+
+pub async fn get_report(data: Arc<AppState>, report_id: &str) -> Result<Option<Report>> {
+    sqlx::query_as!(Report, "SELECT * FROM reports WHERE id = ?", report_id)
+        .fetch_optional(&data.db)
+        .await
+}
+
+/// Whether `reporter_user_id` already has a pending report of the same
+/// message, or of the same user if `message_id` is `None`.
+pub async fn has_pending_report(
+    data: Arc<AppState>,
+    reporter_user_id: &str,
+    reported_user_id: &str,
+    message_id: Option<&str>,
+) -> Result<bool> {
+    let has_pending_report: Option<bool> = sqlx::query_scalar(
+        r#"
+        SELECT EXISTS(
+            SELECT 1
+            FROM reports
+            WHERE reporter_user_id = ?
+            AND reported_user_id = ?
+            AND message_id <=> ?
+            AND status IN ('open', 'claimed')
+        )
+        "#,
+    )
+    .bind(reporter_user_id)
+    .bind(reported_user_id)
+    .bind(message_id)
+    .fetch_one(&data.db)
+    .await?;
+
+    Ok(has_pending_report.unwrap_or(false))
+}
+
+/// Reports in any of `statuses`, oldest first so the queue is worked in
+/// order.
+pub async fn get_reports(
+    data: Arc<AppState>,
+    statuses: &[&str],
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Report>> {
+    if statuses.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let placeholders = statuses.iter().map(|_| "?").collect::<Vec<_>>().join(", ");
+    let sql = format!(
+        "SELECT * FROM reports WHERE status IN ({}) ORDER BY created_at ASC, id ASC LIMIT ? OFFSET ?",
+        placeholders
+    );
+
+    let mut query = sqlx::query_as::<_, Report>(&sql);
+
+    for status in statuses {
+        query = query.bind(status);
+    }
+
+    query.bind(limit).bind(offset).fetch_all(&data.db).await
+}
+
+/// Returns `false` unless the report was open.
+pub async fn claim_report(
+    data: Arc<AppState>,
+    report_id: &str,
+    claimed_by_user_id: &str,
+) -> Result<bool> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE reports
+        SET status = 'claimed', claimed_by_user_id = ?, claimed_at = ?
+        WHERE id = ? AND status = 'open'
+        "#,
+        claimed_by_user_id,
+        chrono::Utc::now(),
+        report_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Closes a pending report that is open or claimed by the resolver, who
+/// becomes its claimant. Returns `false` if it was already closed or is
+/// claimed by someone else.
+pub async fn close_report(
+    data: Arc<AppState>,
+    report_id: &str,
+    resolved_by_user_id: &str,
+    status: &str,
+    resolution: &str,
+    resolution_note: Option<&str>,
+) -> Result<bool> {
+    let now = chrono::Utc::now();
+    let result = sqlx::query!(
+        r#"
+        UPDATE reports
+        SET status = ?, resolved_by_user_id = ?, resolution = ?, resolution_note = ?, resolved_at = ?,
+            claimed_by_user_id = ?, claimed_at = COALESCE(claimed_at, ?)
+        WHERE id = ? AND (status = 'open' OR (status = 'claimed' AND claimed_by_user_id = ?))
+        "#,
+        status,
+        resolved_by_user_id,
+        resolution,
+        resolution_note,
+        now,
+        resolved_by_user_id,
+        now,
+        report_id,
+        resolved_by_user_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Puts a report closed by `claimed_by_user_id` back into their claim,
+/// for when its resolution could not be applied.
+pub async fn reopen_report(
+    data: Arc<AppState>,
+    report_id: &str,
+    claimed_by_user_id: &str,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE reports
+        SET status = 'claimed', resolved_by_user_id = NULL, resolution = NULL, resolution_note = NULL, resolved_at = NULL
+        WHERE id = ? AND claimed_by_user_id = ? AND status IN ('resolved', 'dismissed')
+        "#,
+        report_id,
+        claimed_by_user_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+/// Editable settings of an automod rule.
+#[derive(Debug)]
+pub struct AutomodRuleFields<'a> {
+    pub name: &'a str,
+    pub config: &'a str,
+    pub action: &'a str,
+    pub timeout_seconds: Option<i32>,
+    pub channel_id: Option<&'a str>,
+    pub is_active: bool,
+}
+
+pub async fn create_automod_rule(
+    data: Arc<AppState>,
+    rule_type: &str,
+    fields: &AutomodRuleFields<'_>,
+    created_by_user_id: &str,
+) -> Result<AutomodRule> {
+    let id = Uuid::new_v4().to_string();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO automod_rules (id, name, rule_type, config, action, timeout_seconds, channel_id, is_active, created_by_user_id)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+        id,
+        fields.name,
+        rule_type,
+        fields.config,
+        fields.action,
+        fields.timeout_seconds,
+        fields.channel_id,
+        fields.is_active,
+        created_by_user_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    sqlx::query_as!(AutomodRule, "SELECT * FROM automod_rules WHERE id = ?", id)
+        .fetch_one(&data.db)
+        .await
+}
+
+pub async fn get_automod_rule(data: Arc<AppState>, rule_id: &str) -> Result<Option<AutomodRule>> {
+    sqlx::query_as!(
+        AutomodRule,
+        "SELECT * FROM automod_rules WHERE id = ? AND deleted_at IS NULL",
+        rule_id
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+pub async fn get_automod_rules(data: Arc<AppState>) -> Result<Vec<AutomodRule>> {
+    sqlx::query_as!(
+        AutomodRule,
+        r#"
+        SELECT
+            *
+        FROM automod_rules
+        WHERE deleted_at IS NULL
+        ORDER BY created_at ASC
+        "#
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+/// Active rules that apply to every channel or to `channel_id`.
+pub async fn get_active_automod_rules(
+    data: Arc<AppState>,
+    channel_id: &str,
+) -> Result<Vec<AutomodRule>> {
+    sqlx::query_as!(
+        AutomodRule,
+        r#"
+        SELECT
+            *
+        FROM automod_rules
+        WHERE deleted_at IS NULL
+        AND is_active = TRUE
+        AND (channel_id IS NULL OR channel_id = ?)
+        ORDER BY created_at ASC
+        "#,
+        channel_id
+    )
+    .fetch_all(&data.db)
+    .await
+}
+
+pub async fn update_automod_rule(
+    data: Arc<AppState>,
+    rule_id: &str,
+    fields: &AutomodRuleFields<'_>,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        UPDATE automod_rules
+        SET name = ?, config = ?, action = ?, timeout_seconds = ?, channel_id = ?, is_active = ?
+        WHERE id = ? AND deleted_at IS NULL
+        "#,
+        fields.name,
+        fields.config,
+        fields.action,
+        fields.timeout_seconds,
+        fields.channel_id,
+        fields.is_active,
+        rule_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_automod_rule(data: Arc<AppState>, rule_id: &str) -> Result<bool> {
+    let result = sqlx::query!(
+        "UPDATE automod_rules SET deleted_at = ? WHERE id = ? AND deleted_at IS NULL",
+        chrono::Utc::now(),
+        rule_id
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+/// The cached preview of the URL hashed to `url_hash`, if it has not expired.
+pub async fn get_link_preview(data: Arc<AppState>, url_hash: &str) -> Result<Option<LinkPreview>> {
+    sqlx::query_as!(
+        LinkPreview,
+        "SELECT * FROM link_previews WHERE url_hash = ? AND expires_at > ?",
+        url_hash,
+        chrono::Utc::now()
+    )
+    .fetch_optional(&data.db)
+    .await
+}
+
+pub async fn upsert_link_preview(
+    data: Arc<AppState>,
+    url_hash: &str,
+    url: &str,
+    preview: Option<&str>,
+    error: Option<&str>,
+    is_rejected_url: bool,
+    expires_at: chrono::DateTime<chrono::Utc>,
+) -> Result<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO link_previews (url_hash, url, preview, error, is_rejected_url, fetched_at, expires_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        ON DUPLICATE KEY UPDATE
+            preview = VALUES(preview),
+            error = VALUES(error),
+            is_rejected_url = VALUES(is_rejected_url),
+            fetched_at = VALUES(fetched_at),
+            expires_at = VALUES(expires_at)
+        "#,
+        url_hash,
+        url,
+        preview,
+        error,
+        is_rejected_url,
+        chrono::Utc::now(),
+        expires_at
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes the cached preview of one URL, or of every URL if `url_hash` is
+/// `None`. Returns how many were deleted.
+pub async fn delete_link_previews(data: Arc<AppState>, url_hash: Option<&str>) -> Result<u64> {
+    let result = match url_hash {
+        Some(url_hash) => {
+            sqlx::query!("DELETE FROM link_previews WHERE url_hash = ?", url_hash)
+                .execute(&data.db)
+                .await?
+        }
+        None => {
+            sqlx::query!("DELETE FROM link_previews")
+                .execute(&data.db)
+                .await?
+        }
+    };
+
+    Ok(result.rows_affected())
+}
+
+pub async fn delete_expired_link_previews(data: Arc<AppState>) -> Result<u64> {
+    let result = sqlx::query!(
+        "DELETE FROM link_previews WHERE expires_at <= ?",
+        chrono::Utc::now()
+    )
+    .execute(&data.db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Stores the previews of the links in a message, `(url, preview JSON)` in
+/// the order the links appear.
+pub async fn create_message_embeds(
+    data: Arc<AppState>,
+    message_id: &str,
+    embeds: &[(String, String)],
+) -> Result<()> {
+    let mut tx = data.db.begin().await?;
+
+    for (position, (url, preview)) in embeds.iter().enumerate() {
+        sqlx::query!(
+            r#"
+            INSERT INTO message_embeds (id, message_id, position, url, preview)
+            VALUES (?, ?, ?, ?, ?)
+            "#,
+            Uuid::new_v4().to_string(),
+            message_id,
+            position as i32,
+            url,
+            preview
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+pub async fn get_message_embeds(
+    data: Arc<AppState>,
+    message_ids: &[String],
+) -> Result<Vec<MessageEmbed>> {
+    if message_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let placeholders = message_ids
+        .iter()
+        .map(|_| "?")
+        .collect::<Vec<_>>()
+        .join(", ");
+    let sql = format!(
+        r#"
+        SELECT
+            *
+        FROM message_embeds
+        WHERE message_id IN ({})
+        ORDER BY position ASC
+        "#,
+        placeholders
+    );
+
+    let mut query = sqlx::query_as::<_, MessageEmbed>(&sql);
+
+    for message_id in message_ids {
+        query = query.bind(message_id);
+    }
+
+    query.fetch_all(&data.db).await
+}
@@ -1,4 +1,13 @@
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+
+/// Tells an explicit `null`, `Some(None)`, apart from a missing field, `None`.
+fn double_option<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
 
 #[derive(Debug, Deserialize)]
 pub struct LoginRequest {
@@ -11,3 +20,175 @@ pub struct RegisterRequest {
     pub username: String,
     pub password: String,
 }
+
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorConfirmRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MfaLoginRequest {
+    #[serde(rename = "mfaToken")]
+    pub mfa_token: String,
+    pub code: Option<String>,
+    #[serde(rename = "recoveryCode")]
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBotRequest {
+    pub username: String,
+    #[serde(rename = "displayName")]
+    pub display_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    pub scopes: Vec<String>,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateMessageRequest {
+    pub content: Option<String>,
+    #[serde(rename = "replyToMessageId")]
+    pub reply_to_message_id: Option<String>,
+    #[serde(rename = "threadId")]
+    pub thread_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateIncomingWebhookRequest {
+    pub name: String,
+    #[serde(rename = "avatarUrl")]
+    pub avatar_url: Option<String>,
+    #[serde(rename = "rateLimitPerMinute")]
+    pub rate_limit_per_minute: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateIncomingWebhookRequest {
+    pub name: Option<String>,
+    /// `null` removes the avatar.
+    #[serde(rename = "avatarUrl", default, deserialize_with = "double_option")]
+    pub avatar_url: Option<Option<String>>,
+    #[serde(rename = "rateLimitPerMinute")]
+    pub rate_limit_per_minute: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExecuteIncomingWebhookRequest {
+    pub content: Option<String>,
+    pub username: Option<String>,
+    #[serde(rename = "avatarUrl")]
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookSubscriptionRequest {
+    pub name: String,
+    pub url: String,
+    #[serde(rename = "eventTypes")]
+    pub event_types: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookSubscriptionRequest {
+    pub name: Option<String>,
+    pub url: Option<String>,
+    #[serde(rename = "eventTypes")]
+    pub event_types: Option<Vec<String>>,
+    #[serde(rename = "isActive")]
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MarkReadRequest {
+    #[serde(rename = "messageId")]
+    pub message_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDirectConversationRequest {
+    #[serde(rename = "userIds")]
+    pub user_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateUserSettingsRequest {
+    #[serde(rename = "dmPrivacy")]
+    pub dm_privacy: Option<String>,
+
+    /// An empty status clears it.
+    #[serde(rename = "customStatus")]
+    pub custom_status: Option<String>,
+
+    #[serde(rename = "doNotDisturb")]
+    pub do_not_disturb: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBanRequest {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    pub reason: Option<String>,
+    /// Omit for a permanent ban.
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTimeoutRequest {
+    #[serde(rename = "userId")]
+    pub user_id: String,
+    pub reason: Option<String>,
+    #[serde(rename = "durationSeconds")]
+    pub duration_seconds: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveReportRequest {
+    /// One of `delete`, `timeout`, `ban` or `dismiss`.
+    pub action: String,
+    pub note: Option<String>,
+    /// Required for `timeout`.
+    #[serde(rename = "durationSeconds")]
+    pub duration_seconds: Option<i64>,
+    /// For `ban`; omit for a permanent ban.
+    #[serde(rename = "expiresAt")]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAutomodRuleRequest {
+    pub name: String,
+    #[serde(rename = "ruleType")]
+    pub rule_type: String,
+    /// Settings of the rule type; omit for types without settings.
+    pub config: Option<serde_json::Value>,
+    pub action: String,
+    /// Required for the timeout action.
+    #[serde(rename = "timeoutSeconds")]
+    pub timeout_seconds: Option<i32>,
+    /// Omit to apply the rule to every channel.
+    #[serde(rename = "channelId")]
+    pub channel_id: Option<String>,
+    #[serde(rename = "isActive")]
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateAutomodRuleRequest {
+    pub name: Option<String>,
+    pub config: Option<serde_json::Value>,
+    pub action: Option<String>,
+    #[serde(rename = "timeoutSeconds")]
+    pub timeout_seconds: Option<i32>,
+    /// An empty id applies the rule to every channel.
+    #[serde(rename = "channelId")]
+    pub channel_id: Option<String>,
+    #[serde(rename = "isActive")]
+    pub is_active: Option<bool>,
+}
@@ -18,11 +18,24 @@ pub struct AttachmentResource {
 pub struct ChannelResource {
     pub id: String,
     pub name: String,
+    pub channelType: String,
     pub sortOrder: i32,
     pub isDefault: bool,
     pub createdAt: chrono::DateTime<chrono::Utc>,
     pub updatedAt: chrono::DateTime<chrono::Utc>,
     pub deletedAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub lastReadMessageId: Option<String>,
+    pub unreadCount: i64,
+    pub mentionCount: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct ChannelReadStateResource {
+    pub channelId: String,
+    pub lastReadMessageId: Option<String>,
+    pub unreadCount: i64,
+    pub mentionCount: i64,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -31,6 +44,16 @@ pub struct MessageResource {
     pub id: String,
     pub userId: String,
     pub channelId: String,
+    pub messageType: String,
+    pub webhookId: Option<String>,
+    pub replyToMessageId: Option<String>,
+    pub replyTo: Option<MessageReferenceResource>,
+    pub threadId: Option<String>,
+    pub threadReplyCount: i32,
+    pub threadLastReplyAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub pinned: bool,
+    pub pinnedAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub pinnedByUserId: Option<String>,
     pub content: Option<String>,
     pub createdAt: chrono::DateTime<chrono::Utc>,
     pub updatedAt: chrono::DateTime<chrono::Utc>,
@@ -38,6 +61,64 @@ pub struct MessageResource {
     pub deletedByUserId: Option<String>,
     pub user: UserResource,
     pub attachments: Vec<AttachmentResource>,
+    pub reactions: Vec<ReactionResource>,
+    pub mentions: Vec<MentionResource>,
+    /// Previews of the links in `content`, filled in shortly after sending.
+    pub embeds: Vec<EmbedResource>,
+    /// The viewer has blocked the author; clients may collapse the message.
+    pub authorBlocked: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct EmbedResource {
+    /// The link as it appears in the message.
+    pub url: String,
+    pub preview: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct MentionResource {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub userId: Option<String>,
+    pub roleId: Option<String>,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct MessageReferenceResource {
+    pub id: String,
+    pub userId: String,
+    pub authorDisplayName: Option<String>,
+    pub content: Option<String>,
+    pub createdAt: chrono::DateTime<chrono::Utc>,
+    pub deleted: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct ThreadSummaryResource {
+    pub parentMessageId: String,
+    pub replyCount: i32,
+    pub lastReplyAt: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct ThreadResource {
+    pub parent: MessageResource,
+    pub replies: Vec<MessageResource>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct ReactionResource {
+    pub emoji: String,
+    pub count: i64,
+    pub me: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -62,6 +143,10 @@ pub struct UserResource {
     pub isOnline: bool,
     pub currentChannelId: Option<String>,
     pub connectedAt: Option<chrono::DateTime<chrono::Utc>>,
+    /// Hidden from users the owner has blocked.
+    pub customStatus: Option<String>,
+    /// Pokes to the user are rejected while set.
+    pub doNotDisturb: bool,
     pub createdAt: chrono::DateTime<chrono::Utc>,
     pub updatedAt: chrono::DateTime<chrono::Utc>,
 }
@@ -96,5 +181,166 @@ pub struct AuthMeUserResource {
     pub connectedAt: Option<chrono::DateTime<chrono::Utc>>,
     pub createdAt: chrono::DateTime<chrono::Utc>,
     pub updatedAt: chrono::DateTime<chrono::Utc>,
+    pub customStatus: Option<String>,
+    pub doNotDisturb: bool,
+    pub dmPrivacy: String,
     pub permissions: Vec<String>,
 }
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct LoginAttemptResource {
+    pub id: String,
+    pub username: String,
+    pub userId: Option<String>,
+    pub ipAddress: String,
+    pub reason: String,
+    pub createdAt: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct ApiTokenResource {
+    pub id: String,
+    pub userId: String,
+    pub name: String,
+    pub tokenPrefix: String,
+    pub scopes: Vec<String>,
+    pub createdByUserId: Option<String>,
+    pub lastUsedAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub expiresAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub revokedAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub createdAt: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct IncomingWebhookResource {
+    pub id: String,
+    pub channelId: String,
+    pub name: String,
+    pub avatarUrl: Option<String>,
+    pub rateLimitPerMinute: i32,
+    pub createdByUserId: Option<String>,
+    pub createdAt: chrono::DateTime<chrono::Utc>,
+    pub updatedAt: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct WebhookSubscriptionResource {
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    pub eventTypes: Vec<String>,
+    pub isActive: bool,
+    pub createdByUserId: Option<String>,
+    pub createdAt: chrono::DateTime<chrono::Utc>,
+    pub updatedAt: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct WebhookDeliveryResource {
+    pub id: String,
+    pub subscriptionId: String,
+    pub eventType: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i32,
+    pub nextAttemptAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub lastAttemptAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub lastResponseStatus: Option<i32>,
+    pub lastError: Option<String>,
+    pub createdAt: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct DirectConversationResource {
+    pub id: String,
+    pub participants: Vec<UserResource>,
+    pub lastMessageAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub lastReadMessageId: Option<String>,
+    pub unreadCount: i64,
+    pub mentionCount: i64,
+    pub createdAt: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct BanResource {
+    pub id: String,
+    pub userId: String,
+    pub reason: Option<String>,
+    pub bannedByUserId: Option<String>,
+    pub expiresAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub liftedAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub liftedByUserId: Option<String>,
+    pub createdAt: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct TimeoutResource {
+    pub id: String,
+    pub userId: String,
+    pub reason: Option<String>,
+    pub createdByUserId: Option<String>,
+    pub expiresAt: chrono::DateTime<chrono::Utc>,
+    pub liftedAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub liftedByUserId: Option<String>,
+    pub createdAt: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct AuditLogEntryResource {
+    pub id: String,
+    pub actorUserId: Option<String>,
+    pub action: String,
+    pub targetType: String,
+    pub targetId: String,
+    pub reason: Option<String>,
+    /// Changed fields as `{"field": {"old": .., "new": ..}}`.
+    pub changes: serde_json::Value,
+    pub createdAt: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct ReportResource {
+    pub id: String,
+    pub reporterUserId: String,
+    pub reportedUserId: String,
+    pub messageId: Option<String>,
+    pub channelId: Option<String>,
+    pub reason: Option<String>,
+    /// The reported message or user as it was when reported.
+    pub snapshot: serde_json::Value,
+    pub status: String,
+    pub claimedByUserId: Option<String>,
+    pub claimedAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub resolvedByUserId: Option<String>,
+    pub resolution: Option<String>,
+    pub resolutionNote: Option<String>,
+    pub resolvedAt: Option<chrono::DateTime<chrono::Utc>>,
+    pub createdAt: chrono::DateTime<chrono::Utc>,
+    pub updatedAt: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[allow(non_snake_case)]
+pub struct AutomodRuleResource {
+    pub id: String,
+    pub name: String,
+    pub ruleType: String,
+    pub config: serde_json::Value,
+    pub action: String,
+    pub timeoutSeconds: Option<i32>,
+    pub channelId: Option<String>,
+    pub isActive: bool,
+    pub createdByUserId: Option<String>,
+    pub createdAt: chrono::DateTime<chrono::Utc>,
+    pub updatedAt: chrono::DateTime<chrono::Utc>,
+}
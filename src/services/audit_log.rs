@@ -0,0 +1,129 @@
+use crate::queries;
+use crate::AppState;
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use tracing::warn;
+
+pub mod audit_actions {
+    pub const USER_KICKED: &str = "user.kicked";
+    pub const USER_BANNED: &str = "user.banned";
+    pub const USER_UNBANNED: &str = "user.unbanned";
+    pub const USER_TIMED_OUT: &str = "user.timed_out";
+    pub const USER_TIMEOUT_LIFTED: &str = "user.timeout_lifted";
+    pub const USER_TWO_FACTOR_RESET: &str = "user.two_factor_reset";
+    pub const MESSAGE_PINNED: &str = "message.pinned";
+    pub const MESSAGE_UNPINNED: &str = "message.unpinned";
+    pub const MESSAGE_DELETED: &str = "message.deleted";
+    pub const REPORT_RESOLVED: &str = "report.resolved";
+    pub const REPORT_DISMISSED: &str = "report.dismissed";
+    pub const BOT_CREATED: &str = "bot.created";
+    pub const API_TOKEN_CREATED: &str = "api_token.created";
+    pub const API_TOKEN_REVOKED: &str = "api_token.revoked";
+    pub const WEBHOOK_CREATED: &str = "webhook.created";
+    pub const WEBHOOK_UPDATED: &str = "webhook.updated";
+    pub const WEBHOOK_DELETED: &str = "webhook.deleted";
+    pub const WEBHOOK_SUBSCRIPTION_CREATED: &str = "webhook_subscription.created";
+    pub const WEBHOOK_SUBSCRIPTION_UPDATED: &str = "webhook_subscription.updated";
+    pub const WEBHOOK_SUBSCRIPTION_DELETED: &str = "webhook_subscription.deleted";
+    pub const AUTOMOD_RULE_CREATED: &str = "automod_rule.created";
+    pub const AUTOMOD_RULE_UPDATED: &str = "automod_rule.updated";
+    pub const AUTOMOD_RULE_DELETED: &str = "automod_rule.deleted";
+    pub const LINK_PREVIEWS_PURGED: &str = "link_previews.purged";
+}
+
+pub mod audit_target_types {
+    pub const USER: &str = "user";
+    pub const MESSAGE: &str = "message";
+    pub const REPORT: &str = "report";
+    pub const API_TOKEN: &str = "api_token";
+    pub const WEBHOOK: &str = "webhook";
+    pub const WEBHOOK_SUBSCRIPTION: &str = "webhook_subscription";
+    pub const AUTOMOD_RULE: &str = "automod_rule";
+    pub const LINK_PREVIEW: &str = "link_preview";
+}
+
+/// Top-level fields that differ between two JSON objects, as
+/// `{"field": {"old": .., "new": ..}}`. Pass `Value::Null` as `old` for a
+/// creation and as `new` for a deletion.
+pub fn diff(old: &Value, new: &Value) -> Value {
+    let empty = Map::new();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+
+    let mut changes = Map::new();
+    for key in old.keys().chain(new.keys()) {
+        let old_value = old.get(key).unwrap_or(&Value::Null);
+        let new_value = new.get(key).unwrap_or(&Value::Null);
+
+        if old_value != new_value && !changes.contains_key(key) {
+            changes.insert(key.clone(), json!({ "old": old_value, "new": new_value }));
+        }
+    }
+
+    Value::Object(changes)
+}
+
+/// Writes an audit log entry. Failures are logged and never fail the
+/// action that is being recorded.
+pub async fn record(
+    app_state: Arc<AppState>,
+    actor_user_id: &str,
+    action: &'static str,
+    target_type: &'static str,
+    target_id: &str,
+    reason: Option<&str>,
+    changes: Value,
+) {
+    if let Err(e) = queries::create_audit_log_entry(
+        app_state,
+        actor_user_id,
+        action,
+        target_type,
+        target_id,
+        reason,
+        &changes.to_string(),
+    )
+    .await
+    {
+        warn!(
+            "Failed to write audit log entry {} by user {} for {} {}: {}",
+            action, actor_user_id, target_type, target_id, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_reports_only_changed_fields() {
+        let old = json!({ "name": "a", "url": "https://x", "isActive": true });
+        let new = json!({ "name": "b", "url": "https://x", "isActive": true });
+
+        assert_eq!(
+            diff(&old, &new),
+            json!({ "name": { "old": "a", "new": "b" } })
+        );
+    }
+
+    #[test]
+    fn diff_of_creation_and_deletion_lists_every_field() {
+        let resource = json!({ "id": "1", "name": "a" });
+
+        assert_eq!(
+            diff(&Value::Null, &resource),
+            json!({
+                "id": { "old": null, "new": "1" },
+                "name": { "old": null, "new": "a" },
+            })
+        );
+        assert_eq!(
+            diff(&resource, &Value::Null),
+            json!({
+                "id": { "old": "1", "new": null },
+                "name": { "old": "a", "new": null },
+            })
+        );
+    }
+}
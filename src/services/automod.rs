@@ -0,0 +1,680 @@
+use crate::errors::AppError;
+use crate::models::{
+    AutomodRule, Channel, Message, User, AUTOMOD_ACTION_BLOCK, AUTOMOD_ACTION_FLAG,
+    AUTOMOD_ACTION_TIMEOUT, AUTOMOD_RULE_BLOCKED_WORDS, AUTOMOD_RULE_CAPS,
+    AUTOMOD_RULE_DUPLICATE_FLOOD, AUTOMOD_RULE_EMOJI_SPAM, AUTOMOD_RULE_INVITES,
+    AUTOMOD_RULE_LINKS, AUTOMOD_RULE_REGEX, AUTOMOD_RULE_TYPES, AUTOMOD_SYSTEM_USER_ID,
+};
+use crate::queries;
+use crate::services::audit_log::{self, audit_actions, audit_target_types};
+use crate::services::moderation::announce_timeout;
+use crate::services::reports::notify_moderators;
+use crate::socket::events::socket_publish_events;
+use crate::AppState;
+use dashmap::DashMap;
+use regex::{Regex, RegexBuilder};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use url::Url;
+
+/// Words or patterns a single rule may list.
+pub const MAX_PATTERNS_PER_RULE: usize = 100;
+/// Longest window duplicate flood detection can look back.
+pub const MAX_FLOOD_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// Keeps a single rule from making every message expensive to check.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+/// Recent messages remembered per user for flood detection.
+const MAX_TRACKED_MESSAGES: usize = 50;
+
+#[derive(Debug, Deserialize)]
+struct BlockedWordsConfig {
+    words: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RegexConfig {
+    patterns: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LinksConfig {
+    /// Subdomains of allowed domains are allowed as well.
+    #[serde(rename = "allowedDomains", default)]
+    allowed_domains: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CapsConfig {
+    /// Shorter messages are never flagged.
+    #[serde(rename = "minLength")]
+    min_length: usize,
+    #[serde(rename = "maxPercent")]
+    max_percent: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct EmojiSpamConfig {
+    #[serde(rename = "maxEmojis")]
+    max_emojis: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct DuplicateFloodConfig {
+    #[serde(rename = "maxDuplicates")]
+    max_duplicates: usize,
+    #[serde(rename = "windowSeconds")]
+    window_seconds: u64,
+}
+
+/// The settings of a rule, parsed and compiled.
+#[derive(Debug)]
+pub enum Filter {
+    BlockedWords(Regex),
+    Patterns(Vec<Regex>),
+    Invites,
+    Links {
+        allowed_domains: Vec<String>,
+    },
+    Caps {
+        min_length: usize,
+        max_percent: usize,
+    },
+    EmojiSpam {
+        max_emojis: usize,
+    },
+    DuplicateFlood {
+        max_duplicates: usize,
+        window: Duration,
+    },
+}
+
+fn parse_config<T: DeserializeOwned>(config: &Value) -> Result<T, String> {
+    let config = if config.is_null() {
+        json!({})
+    } else {
+        config.clone()
+    };
+
+    serde_json::from_value(config).map_err(|e| format!("Invalid config: {}", e))
+}
+
+fn build_regex(pattern: &str, case_insensitive: bool) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {}", e))
+}
+
+fn invite_regex() -> &'static Regex {
+    static INVITE_REGEX: OnceLock<Regex> = OnceLock::new();
+    INVITE_REGEX.get_or_init(|| {
+        Regex::new(
+            r"(?i)(?:discord(?:app)?\.com/invite|discord\.gg|t\.me/(?:joinchat/|\+)|chat\.whatsapp\.com|matrix\.to/#/[#!])/?\S+",
+        )
+        .expect("invite pattern is valid")
+    })
+}
+
+fn link_regex() -> &'static Regex {
+    static LINK_REGEX: OnceLock<Regex> = OnceLock::new();
+    LINK_REGEX.get_or_init(|| {
+        Regex::new(r"(?i)\b(?:https?://|www\.)[^\s<>]+").expect("link pattern is valid")
+    })
+}
+
+/// Pictographs, symbols and regional indicators. A flag counts twice.
+fn is_emoji(c: char) -> bool {
+    matches!(c as u32, 0x1F000..=0x1FAFF | 0x2600..=0x27BF | 0x2B00..=0x2BFF)
+}
+
+impl Filter {
+    pub fn parse(rule_type: &str, config: &Value) -> Result<Filter, String> {
+        match rule_type {
+            AUTOMOD_RULE_BLOCKED_WORDS => {
+                let config: BlockedWordsConfig = parse_config(config)?;
+                let words: Vec<String> = config
+                    .words
+                    .iter()
+                    .map(|word| word.trim())
+                    .filter(|word| !word.is_empty())
+                    .map(regex::escape)
+                    .collect();
+                if words.is_empty() || words.len() > MAX_PATTERNS_PER_RULE {
+                    return Err(format!(
+                        "config.words must list 1 to {} words",
+                        MAX_PATTERNS_PER_RULE
+                    ));
+                }
+
+                // Whole words only, so "class" does not match "ass"
+                let pattern = format!(r"(?:^|\W)(?:{})(?:\W|$)", words.join("|"));
+
+                Ok(Filter::BlockedWords(build_regex(&pattern, true)?))
+            }
+            AUTOMOD_RULE_REGEX => {
+                let config: RegexConfig = parse_config(config)?;
+                if config.patterns.is_empty() || config.patterns.len() > MAX_PATTERNS_PER_RULE {
+                    return Err(format!(
+                        "config.patterns must list 1 to {} patterns",
+                        MAX_PATTERNS_PER_RULE
+                    ));
+                }
+
+                let patterns = config
+                    .patterns
+                    .iter()
+                    .map(|pattern| build_regex(pattern, false))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(Filter::Patterns(patterns))
+            }
+            AUTOMOD_RULE_INVITES => Ok(Filter::Invites),
+            AUTOMOD_RULE_LINKS => {
+                let config: LinksConfig = parse_config(config)?;
+                let allowed_domains = config
+                    .allowed_domains
+                    .iter()
+                    .map(|domain| domain.trim().trim_start_matches('.').to_lowercase())
+                    .filter(|domain| !domain.is_empty())
+                    .collect();
+
+                Ok(Filter::Links { allowed_domains })
+            }
+            AUTOMOD_RULE_CAPS => {
+                let config: CapsConfig = parse_config(config)?;
+                if !(1..100).contains(&config.max_percent) {
+                    return Err("config.maxPercent must be between 1 and 99".to_string());
+                }
+
+                Ok(Filter::Caps {
+                    min_length: config.min_length,
+                    max_percent: config.max_percent,
+                })
+            }
+            AUTOMOD_RULE_EMOJI_SPAM => {
+                let config: EmojiSpamConfig = parse_config(config)?;
+
+                Ok(Filter::EmojiSpam {
+                    max_emojis: config.max_emojis,
+                })
+            }
+            AUTOMOD_RULE_DUPLICATE_FLOOD => {
+                let config: DuplicateFloodConfig = parse_config(config)?;
+                if config.max_duplicates == 0 {
+                    return Err("config.maxDuplicates must be at least 1".to_string());
+                }
+                if config.window_seconds == 0 || config.window_seconds > MAX_FLOOD_WINDOW.as_secs()
+                {
+                    return Err(format!(
+                        "config.windowSeconds must be between 1 and {}",
+                        MAX_FLOOD_WINDOW.as_secs()
+                    ));
+                }
+
+                Ok(Filter::DuplicateFlood {
+                    max_duplicates: config.max_duplicates,
+                    window: Duration::from_secs(config.window_seconds),
+                })
+            }
+            _ => Err(format!("ruleType must be one of {:?}", AUTOMOD_RULE_TYPES)),
+        }
+    }
+
+    /// Returns the reason `content` violates the filter. `duplicates_within`
+    /// counts how often the sender sent the same content within a window,
+    /// including this message.
+    pub fn check(
+        &self,
+        content: &str,
+        duplicates_within: impl Fn(Duration) -> usize,
+    ) -> Option<String> {
+        match self {
+            Filter::BlockedWords(regex) => regex
+                .is_match(content)
+                .then(|| "Your message contains a blocked word".to_string()),
+            Filter::Patterns(patterns) => patterns
+                .iter()
+                .any(|pattern| pattern.is_match(content))
+                .then(|| "Your message contains blocked content".to_string()),
+            Filter::Invites => invite_regex()
+                .is_match(content)
+                .then(|| "Invites are not allowed here".to_string()),
+            Filter::Links { allowed_domains } => link_regex().find_iter(content).find_map(|link| {
+                let link = link.as_str();
+                let url = if link.to_lowercase().starts_with("www.") {
+                    Url::parse(&format!("http://{}", link))
+                } else {
+                    Url::parse(link)
+                };
+                let host = url.ok()?.host_str()?.to_lowercase();
+
+                let allowed = allowed_domains
+                    .iter()
+                    .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)));
+
+                (!allowed).then(|| format!("Links to {} are not allowed here", host))
+            }),
+            Filter::Caps {
+                min_length,
+                max_percent,
+            } => {
+                let letters = content.chars().filter(|c| c.is_alphabetic()).count();
+                let uppercase = content.chars().filter(|c| c.is_uppercase()).count();
+
+                (letters >= *min_length && uppercase * 100 > max_percent * letters)
+                    .then(|| "Your message contains too many capital letters".to_string())
+            }
+            Filter::EmojiSpam { max_emojis } => (content.chars().filter(|c| is_emoji(*c)).count()
+                > *max_emojis)
+                .then(|| "Your message contains too many emojis".to_string()),
+            Filter::DuplicateFlood {
+                max_duplicates,
+                window,
+            } => (duplicates_within(*window) > *max_duplicates)
+                .then(|| "You are sending the same message too often".to_string()),
+        }
+    }
+}
+
+/// Filters compiled from rules, by rule id. An entry is reused only while
+/// the rule has the same `updated_at`; rule changes also invalidate it.
+/// Invalid rules are remembered as `None` so they are reported once.
+#[derive(Default)]
+pub struct FilterCache {
+    filters: DashMap<String, (chrono::DateTime<chrono::Utc>, Option<Arc<Filter>>)>,
+}
+
+impl FilterCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The compiled filter of `rule`, or `None` if its config is invalid.
+    pub fn get_or_compile(&self, rule: &AutomodRule) -> Option<Arc<Filter>> {
+        if let Some(entry) = self.filters.get(&rule.id) {
+            if entry.0 == rule.updated_at {
+                return entry.1.clone();
+            }
+        }
+
+        let config = serde_json::from_str(&rule.config).unwrap_or(Value::Null);
+        let filter = match Filter::parse(&rule.rule_type, &config) {
+            Ok(filter) => Some(Arc::new(filter)),
+            Err(e) => {
+                warn!("Skipping invalid automod rule {}: {}", rule.id, e);
+                None
+            }
+        };
+
+        self.filters
+            .insert(rule.id.clone(), (rule.updated_at, filter.clone()));
+
+        filter
+    }
+
+    pub fn invalidate(&self, rule_id: &str) {
+        self.filters.remove(rule_id);
+    }
+}
+
+/// Recent message fingerprints per user, for duplicate flood detection.
+#[derive(Debug, Default)]
+pub struct FloodTracker {
+    recent: DashMap<String, VecDeque<(u64, Instant)>>,
+}
+
+impl FloodTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remembers that `user_id` sent `content` and returns when they sent
+    /// the same content before, now included. Whitespace and case are
+    /// ignored.
+    pub fn record(&self, user_id: &str, content: &str) -> Vec<Instant> {
+        let now = Instant::now();
+
+        let mut hasher = DefaultHasher::new();
+        content
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+            .hash(&mut hasher);
+        let fingerprint = hasher.finish();
+
+        let mut recent = self.recent.entry(user_id.to_string()).or_default();
+        recent.retain(|(_, sent_at)| now.duration_since(*sent_at) <= MAX_FLOOD_WINDOW);
+        if recent.len() >= MAX_TRACKED_MESSAGES {
+            recent.pop_front();
+        }
+        recent.push_back((fingerprint, now));
+
+        recent
+            .iter()
+            .filter(|(other, _)| *other == fingerprint)
+            .map(|(_, sent_at)| *sent_at)
+            .collect()
+    }
+
+    /// Forgets users who have not sent anything within the longest window.
+    pub fn prune(&self) {
+        let now = Instant::now();
+
+        self.recent.retain(|_, recent| {
+            recent
+                .back()
+                .is_some_and(|(_, sent_at)| now.duration_since(*sent_at) <= MAX_FLOOD_WINDOW)
+        });
+    }
+}
+
+#[derive(Debug)]
+pub struct AutomodViolation {
+    pub rule_id: String,
+    pub rule_name: String,
+    pub reason: String,
+}
+
+impl From<&AutomodViolation> for AppError {
+    fn from(violation: &AutomodViolation) -> Self {
+        AppError::Automod(violation.reason.clone())
+    }
+}
+
+#[derive(Debug)]
+pub enum AutomodVerdict {
+    Allow,
+    /// Post the message, then pass it to `flag_message`.
+    Flag(AutomodViolation),
+    Refuse(AutomodViolation),
+}
+
+fn action_severity(action: &str) -> u8 {
+    match action {
+        AUTOMOD_ACTION_TIMEOUT => 3,
+        AUTOMOD_ACTION_BLOCK => 2,
+        AUTOMOD_ACTION_FLAG => 1,
+        _ => 0,
+    }
+}
+
+/// Runs the rules of `channel` against a message `user` is about to send.
+/// If several rules match, the one with the harshest action wins. Timeouts
+/// are applied here.
+pub async fn screen_message(
+    app_state: Arc<AppState>,
+    user: &User,
+    channel: &Channel,
+    content: &str,
+) -> sqlx::Result<AutomodVerdict> {
+    let rules = queries::get_active_automod_rules(app_state.clone(), &channel.id).await?;
+    if rules.is_empty() {
+        return Ok(AutomodVerdict::Allow);
+    }
+
+    let filters: Vec<(AutomodRule, Arc<Filter>)> = rules
+        .into_iter()
+        .filter_map(|rule| {
+            let filter = app_state.automod_filter_cache.get_or_compile(&rule)?;
+            Some((rule, filter))
+        })
+        .collect();
+
+    let sent_at = if filters
+        .iter()
+        .any(|(_, filter)| matches!(filter.as_ref(), Filter::DuplicateFlood { .. }))
+    {
+        app_state.automod_flood_tracker.record(&user.id, content)
+    } else {
+        vec![]
+    };
+    let duplicates_within = |window: Duration| {
+        sent_at
+            .iter()
+            .filter(|sent_at| sent_at.elapsed() <= window)
+            .count()
+    };
+
+    let mut matched: Option<(&AutomodRule, String)> = None;
+    for (rule, filter) in &filters {
+        let Some(reason) = filter.check(content, duplicates_within) else {
+            continue;
+        };
+
+        if matched.as_ref().map_or(true, |(current, _)| {
+            action_severity(&rule.action) > action_severity(&current.action)
+        }) {
+            matched = Some((rule, reason));
+        }
+    }
+
+    let Some((rule, reason)) = matched else {
+        return Ok(AutomodVerdict::Allow);
+    };
+
+    info!(
+        "Automod rule {} matched a message of user {} in channel {}",
+        rule.id, user.id, channel.id
+    );
+
+    let violation = AutomodViolation {
+        rule_id: rule.id.clone(),
+        rule_name: rule.name.clone(),
+        reason,
+    };
+
+    match rule.action.as_str() {
+        AUTOMOD_ACTION_FLAG => Ok(AutomodVerdict::Flag(violation)),
+        AUTOMOD_ACTION_TIMEOUT => {
+            if let Some(timeout_seconds) = rule.timeout_seconds {
+                time_out_sender(app_state, user, &violation, timeout_seconds).await;
+            }
+
+            Ok(AutomodVerdict::Refuse(violation))
+        }
+        _ => Ok(AutomodVerdict::Refuse(violation)),
+    }
+}
+
+async fn time_out_sender(
+    app_state: Arc<AppState>,
+    user: &User,
+    violation: &AutomodViolation,
+    timeout_seconds: i32,
+) {
+    let automod_user = match queries::get_user_by_id(
+        app_state.clone(),
+        AUTOMOD_SYSTEM_USER_ID.to_string(),
+    )
+    .await
+    {
+        Ok(automod_user) => automod_user,
+        Err(e) => {
+            warn!("Failed to load automod user: {}", e);
+            return;
+        }
+    };
+
+    let reason = format!("Automod: {}", violation.rule_name);
+    let timeout = match queries::create_user_timeout(
+        app_state.clone(),
+        &user.id,
+        Some(&reason),
+        &automod_user.id,
+        chrono::Utc::now() + chrono::Duration::seconds(timeout_seconds.into()),
+    )
+    .await
+    {
+        Ok(timeout) => timeout,
+        Err(e) => {
+            warn!("Failed to time out user {} by automod: {}", user.id, e);
+            return;
+        }
+    };
+
+    audit_log::record(
+        app_state.clone(),
+        &automod_user.id,
+        audit_actions::USER_TIMED_OUT,
+        audit_target_types::USER,
+        &user.id,
+        Some(&reason),
+        audit_log::diff(&Value::Null, &json!(timeout.to_resource())),
+    )
+    .await;
+
+    announce_timeout(app_state, &timeout, &automod_user).await;
+}
+
+/// Files a report of a posted message that matched a flag rule.
+pub async fn flag_message(
+    app_state: Arc<AppState>,
+    message: &Message,
+    author: &User,
+    violation: &AutomodViolation,
+) {
+    let snapshot = json!(message.to_resource(author.to_resource()));
+    let reason = format!("{} (rule \"{}\")", violation.reason, violation.rule_name);
+
+    let report = match queries::create_report(
+        app_state.clone(),
+        AUTOMOD_SYSTEM_USER_ID,
+        &message.user_id,
+        Some(&message.id),
+        Some(&message.channel_id),
+        Some(&reason),
+        &snapshot.to_string(),
+    )
+    .await
+    {
+        Ok(report) => report,
+        Err(e) => {
+            warn!(
+                "Failed to report message {} flagged by automod rule {}: {}",
+                message.id, violation.rule_id, e
+            );
+            return;
+        }
+    };
+
+    notify_moderators(app_state, socket_publish_events::RECEIVE_REPORT, &report).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(rule_type: &str, config: Value, content: &str) -> Option<String> {
+        Filter::parse(rule_type, &config)
+            .unwrap()
+            .check(content, |_| 1)
+    }
+
+    #[test]
+    fn blocked_words_match_whole_words_only() {
+        let config = json!({ "words": ["ass"] });
+
+        assert!(check(AUTOMOD_RULE_BLOCKED_WORDS, config.clone(), "what an ASS!").is_some());
+        assert!(check(AUTOMOD_RULE_BLOCKED_WORDS, config, "first class").is_none());
+    }
+
+    #[test]
+    fn links_allow_listed_domains_and_subdomains() {
+        let config = json!({ "allowedDomains": ["example.com"] });
+
+        assert!(check(
+            AUTOMOD_RULE_LINKS,
+            config.clone(),
+            "see https://docs.example.com/a"
+        )
+        .is_none());
+        assert_eq!(
+            check(AUTOMOD_RULE_LINKS, config, "see www.evil.test/x"),
+            Some("Links to www.evil.test are not allowed here".to_string())
+        );
+    }
+
+    #[test]
+    fn invites_are_detected() {
+        assert!(check(AUTOMOD_RULE_INVITES, Value::Null, "join discord.gg/abc").is_some());
+        assert!(check(AUTOMOD_RULE_INVITES, Value::Null, "discord is down").is_none());
+    }
+
+    #[test]
+    fn caps_and_emoji_spam_use_thresholds() {
+        let caps = json!({ "minLength": 5, "maxPercent": 70 });
+        assert!(check(AUTOMOD_RULE_CAPS, caps.clone(), "STOP SHOUTING").is_some());
+        assert!(check(AUTOMOD_RULE_CAPS, caps.clone(), "OK").is_none());
+        assert!(check(AUTOMOD_RULE_CAPS, caps, "Hello There").is_none());
+
+        let emojis = json!({ "maxEmojis": 2 });
+        assert!(check(AUTOMOD_RULE_EMOJI_SPAM, emojis.clone(), "🎉🎉🎉").is_some());
+        assert!(check(AUTOMOD_RULE_EMOJI_SPAM, emojis, "nice 🎉🎉").is_none());
+    }
+
+    #[test]
+    fn duplicate_flood_counts_within_window() {
+        let filter = Filter::parse(
+            AUTOMOD_RULE_DUPLICATE_FLOOD,
+            &json!({ "maxDuplicates": 2, "windowSeconds": 30 }),
+        )
+        .unwrap();
+
+        assert!(filter.check("spam", |_| 2).is_none());
+        assert!(filter.check("spam", |_| 3).is_some());
+
+        let tracker = FloodTracker::new();
+        tracker.record("u1", "Spam  spam");
+        assert_eq!(tracker.record("u1", "spam SPAM").len(), 2);
+        assert_eq!(tracker.record("u2", "spam spam").len(), 1);
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        assert!(Filter::parse(AUTOMOD_RULE_REGEX, &json!({ "patterns": ["("] })).is_err());
+        assert!(Filter::parse(AUTOMOD_RULE_BLOCKED_WORDS, &json!({ "words": [] })).is_err());
+        assert!(Filter::parse("unknown", &Value::Null).is_err());
+    }
+
+    #[test]
+    fn filter_cache_recompiles_changed_rules() {
+        let now = chrono::Utc::now();
+        let mut rule = AutomodRule {
+            id: "rule-1".to_string(),
+            name: "Words".to_string(),
+            rule_type: AUTOMOD_RULE_BLOCKED_WORDS.to_string(),
+            config: json!({ "words": ["spam"] }).to_string(),
+            action: AUTOMOD_ACTION_BLOCK.to_string(),
+            timeout_seconds: None,
+            channel_id: None,
+            is_active: 1,
+            created_by_user_id: None,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+        };
+        let cache = FilterCache::new();
+
+        let first = cache.get_or_compile(&rule).unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.get_or_compile(&rule).unwrap()));
+
+        rule.config = json!({ "words": ["eggs"] }).to_string();
+        rule.updated_at = now + chrono::Duration::seconds(1);
+        let second = cache.get_or_compile(&rule).unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+        assert!(second.check("eggs", |_| 1).is_some());
+
+        rule.config = "{".to_string();
+        cache.invalidate(&rule.id);
+        assert!(cache.get_or_compile(&rule).is_none());
+    }
+}
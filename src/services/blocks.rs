@@ -0,0 +1,155 @@
+use crate::models::Channel;
+use crate::queries;
+use crate::responses::{MessageResource, UserResource};
+use crate::services::direct_messages::{emit_to_channel_except, emit_to_channel_users};
+use crate::socket::events::socket_publish_events;
+use crate::socket::handlers::ReceiveChatMessagePayload;
+use crate::AppState;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Whether any of `user_ids` has blocked `user_id`.
+pub async fn is_blocked_by_any(
+    app_state: Arc<AppState>,
+    user_id: &str,
+    user_ids: &[String],
+) -> sqlx::Result<bool> {
+    let blocker_ids = queries::get_blocker_ids(app_state, user_id).await?;
+
+    Ok(user_ids.iter().any(|user_id| blocker_ids.contains(user_id)))
+}
+
+/// Whether `user_id` is barred from posting to `channel` because another
+/// participant of the direct conversation has blocked them. Blocks do not
+/// apply to text channels.
+pub async fn is_blocked_in_channel(
+    app_state: Arc<AppState>,
+    user_id: &str,
+    channel: &Channel,
+) -> sqlx::Result<bool> {
+    if !channel.is_direct() {
+        return Ok(false);
+    }
+
+    let member_ids: Vec<String> =
+        queries::get_channel_members(app_state.clone(), &[channel.id.clone()])
+            .await?
+            .into_iter()
+            .map(|member| member.user_id)
+            .collect();
+
+    is_blocked_by_any(app_state, user_id, &member_ids).await
+}
+
+/// Hides the custom status of users who have blocked the viewer.
+pub fn hide_custom_status(user: &mut UserResource, blocker_ids: &[String]) {
+    if blocker_ids.contains(&user.id) {
+        user.customStatus = None;
+    }
+}
+
+/// Flags messages by users the viewer has blocked and hides the custom
+/// status of authors who have blocked the viewer.
+pub async fn apply_viewer_blocks(
+    app_state: Arc<AppState>,
+    messages: &mut [MessageResource],
+    viewer_user_id: &str,
+) -> sqlx::Result<()> {
+    let blocked_user_ids = queries::get_blocked_user_ids(app_state.clone(), viewer_user_id).await?;
+    let blocker_ids = queries::get_blocker_ids(app_state, viewer_user_id).await?;
+
+    for message in messages {
+        message.authorBlocked = blocked_user_ids.contains(&message.userId);
+        hide_custom_status(&mut message.user, &blocker_ids);
+    }
+
+    Ok(())
+}
+
+/// Delivers a new message to everyone who may see `channel`. Users who
+/// blocked the author receive it flagged with `authorBlocked`, users the
+/// author blocked receive it without the author's custom status.
+pub async fn emit_chat_message(
+    app_state: Arc<AppState>,
+    channel: &Channel,
+    payload: &ReceiveChatMessagePayload,
+) {
+    emit_message_event(
+        app_state,
+        channel,
+        socket_publish_events::RECEIVE_CHAT_MESSAGE,
+        &payload.message.userId,
+        &json!(payload),
+    )
+    .await;
+}
+
+/// Pushes `updateMessage` with the current state of `message`, flagged per
+/// recipient like `emit_chat_message`.
+pub async fn emit_message_update(
+    app_state: Arc<AppState>,
+    channel: &Channel,
+    message: &MessageResource,
+) {
+    emit_message_event(
+        app_state,
+        channel,
+        socket_publish_events::UPDATE_MESSAGE,
+        &message.userId,
+        &json!({ "message": message }),
+    )
+    .await;
+}
+
+/// Emits `event` with `payload`, whose `message` was written by `author_id`,
+/// adjusting the message for recipients on either side of a block.
+async fn emit_message_event(
+    app_state: Arc<AppState>,
+    channel: &Channel,
+    event: &'static str,
+    author_id: &str,
+    payload: &Value,
+) {
+    let (blocker_ids, blocked_user_ids) = match tokio::try_join!(
+        queries::get_blocker_ids(app_state.clone(), author_id),
+        queries::get_blocked_user_ids(app_state.clone(), author_id),
+    ) {
+        Ok(ids) => ids,
+        Err(e) => {
+            warn!("Failed to load blocks of user {}: {}", author_id, e);
+            (vec![], vec![])
+        }
+    };
+
+    let mut except_user_ids = blocker_ids.clone();
+    except_user_ids.extend(blocked_user_ids.iter().cloned());
+
+    emit_to_channel_except(app_state.clone(), channel, &except_user_ids, event, payload).await;
+
+    // Each recipient falls into exactly one of these groups
+    for (author_blocked, hide_status) in [(true, false), (false, true), (true, true)] {
+        let user_ids: Vec<String> = except_user_ids
+            .iter()
+            .filter(|user_id| {
+                blocker_ids.contains(user_id) == author_blocked
+                    && blocked_user_ids.contains(user_id) == hide_status
+            })
+            .cloned()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        if user_ids.is_empty() {
+            continue;
+        }
+
+        let mut flagged = payload.clone();
+        flagged["message"]["authorBlocked"] = json!(author_blocked);
+        if hide_status {
+            flagged["message"]["user"]["customStatus"] = json!(null);
+        }
+
+        emit_to_channel_users(app_state.clone(), channel, &user_ids, event, &flagged).await;
+    }
+}
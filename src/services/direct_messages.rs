@@ -0,0 +1,367 @@
+use crate::errors::AppError;
+use crate::models::{
+    Channel, DirectConversation, User, DM_PRIVACY_EVERYONE, DM_PRIVACY_SHARED_SERVER,
+};
+use crate::queries;
+use crate::responses::{ChannelReadStateResource, DirectConversationResource, UserResource};
+use crate::services::blocks::hide_custom_status;
+use crate::services::outgoing_webhooks::{event_types, spawn_dispatch_event};
+use crate::socket::connection::user_room;
+use crate::AppState;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Upper bound of participants in a group conversation, initiator included.
+pub const MAX_PARTICIPANTS: usize = 10;
+
+#[derive(Debug)]
+pub enum DirectConversationError {
+    NoRecipients,
+    TooManyParticipants,
+    UserNotFound,
+    Blocked(String),
+    NotAccepting(String),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for DirectConversationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DirectConversationError::NoRecipients => {
+                write!(f, "At least one other user is required")
+            }
+            DirectConversationError::TooManyParticipants => write!(
+                f,
+                "A direct conversation can have at most {} participants",
+                MAX_PARTICIPANTS
+            ),
+            DirectConversationError::UserNotFound => write!(f, "User not found"),
+            DirectConversationError::Blocked(username) => {
+                write!(f, "You cannot message {}", username)
+            }
+            DirectConversationError::NotAccepting(username) => {
+                write!(f, "{} does not accept direct messages", username)
+            }
+            DirectConversationError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<DirectConversationError> for AppError {
+    fn from(e: DirectConversationError) -> Self {
+        match e {
+            DirectConversationError::UserNotFound => AppError::NotFound(e.to_string()),
+            DirectConversationError::Blocked(_) => AppError::Blocked(e.to_string()),
+            DirectConversationError::NotAccepting(_) => AppError::Forbidden(e.to_string()),
+            DirectConversationError::Database(e) => AppError::from(e),
+            e => AppError::Validation(e.to_string()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for DirectConversationError {
+    fn from(e: sqlx::Error) -> Self {
+        DirectConversationError::Database(e)
+    }
+}
+
+/// Whether `user` belongs to this server. System users such as bots never
+/// do, and a ban revokes membership for as long as it lasts.
+async fn is_server_member(app_state: Arc<AppState>, user: &User) -> sqlx::Result<bool> {
+    if user.is_system_user == 1 {
+        return Ok(false);
+    }
+
+    Ok(queries::get_active_user_ban(app_state, &user.id)
+        .await?
+        .is_none())
+}
+
+/// Whether `recipient` lets `initiator` start a conversation with them.
+async fn accepts_direct_messages(
+    app_state: Arc<AppState>,
+    recipient: &User,
+    initiator: &User,
+) -> sqlx::Result<bool> {
+    match recipient.dm_privacy.as_str() {
+        DM_PRIVACY_EVERYONE => Ok(true),
+        DM_PRIVACY_SHARED_SERVER => Ok(is_server_member(app_state.clone(), initiator).await?
+            && is_server_member(app_state, recipient).await?),
+        _ => Ok(false),
+    }
+}
+
+/// Returns the conversation between `initiator` and `user_ids`, creating
+/// it if it does not exist yet. Privacy settings only guard new ones, but
+/// nobody who blocked the initiator can be messaged.
+pub async fn open_direct_conversation(
+    app_state: Arc<AppState>,
+    initiator: &User,
+    user_ids: &[String],
+) -> Result<Channel, DirectConversationError> {
+    let mut seen = HashSet::new();
+    let recipient_ids: Vec<String> = user_ids
+        .iter()
+        .filter(|user_id| **user_id != initiator.id && seen.insert(user_id.as_str()))
+        .cloned()
+        .collect();
+
+    if recipient_ids.is_empty() {
+        return Err(DirectConversationError::NoRecipients);
+    }
+    if recipient_ids.len() + 1 > MAX_PARTICIPANTS {
+        return Err(DirectConversationError::TooManyParticipants);
+    }
+
+    let recipients = queries::get_users(app_state.clone(), Some(&recipient_ids)).await?;
+    if recipients.len() != recipient_ids.len() {
+        return Err(DirectConversationError::UserNotFound);
+    }
+
+    let blocker_ids = queries::get_blocker_ids(app_state.clone(), &initiator.id).await?;
+    if let Some(recipient) = recipients
+        .iter()
+        .find(|recipient| blocker_ids.contains(&recipient.id))
+    {
+        return Err(DirectConversationError::Blocked(recipient.username.clone()));
+    }
+
+    let mut member_ids = recipient_ids;
+    member_ids.push(initiator.id.clone());
+
+    if let Some(channel) = queries::find_direct_conversation(app_state.clone(), &member_ids).await?
+    {
+        return Ok(channel);
+    }
+
+    for recipient in &recipients {
+        if !accepts_direct_messages(app_state.clone(), recipient, initiator).await? {
+            return Err(DirectConversationError::NotAccepting(
+                recipient.username.clone(),
+            ));
+        }
+    }
+
+    let (channel, created) =
+        queries::create_direct_conversation(app_state.clone(), &member_ids).await?;
+
+    if created {
+        spawn_dispatch_event(
+            app_state,
+            event_types::CHANNEL_CREATED,
+            json!({ "channel": channel.to_resource(), "memberIds": member_ids }),
+        );
+    }
+
+    Ok(channel)
+}
+
+/// Conversation resources with participants and the unread state of `user_id`.
+pub async fn to_direct_conversation_resources(
+    app_state: Arc<AppState>,
+    conversations: &[DirectConversation],
+    user_id: &str,
+) -> sqlx::Result<Vec<DirectConversationResource>> {
+    let channel_ids: Vec<String> = conversations
+        .iter()
+        .map(|conversation| conversation.id.clone())
+        .collect();
+    let members = queries::get_channel_members(app_state.clone(), &channel_ids).await?;
+
+    let user_ids: Vec<String> = members
+        .iter()
+        .map(|member| member.user_id.clone())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let users: HashMap<String, User> = queries::get_users(app_state.clone(), Some(&user_ids))
+        .await?
+        .into_iter()
+        .map(|user| (user.id.clone(), user))
+        .collect();
+
+    let blocker_ids = queries::get_blocker_ids(app_state.clone(), user_id).await?;
+
+    let mut participants: HashMap<&str, Vec<UserResource>> = HashMap::new();
+    for member in &members {
+        if let Some(user) = users.get(&member.user_id) {
+            let mut user_resource = user.to_resource();
+            hide_custom_status(&mut user_resource, &blocker_ids);
+
+            participants
+                .entry(member.channel_id.as_str())
+                .or_default()
+                .push(user_resource);
+        }
+    }
+
+    let mut unread_counts: HashMap<String, ChannelReadStateResource> =
+        queries::get_channel_unread_counts(app_state, user_id, None)
+            .await?
+            .into_iter()
+            .map(|unread_count| (unread_count.channel_id.clone(), unread_count.to_resource()))
+            .collect();
+
+    Ok(conversations
+        .iter()
+        .map(|conversation| {
+            let read_state = unread_counts.remove(&conversation.id);
+
+            DirectConversationResource {
+                id: conversation.id.to_owned(),
+                participants: participants
+                    .remove(conversation.id.as_str())
+                    .unwrap_or_default(),
+                lastMessageAt: conversation.last_message_at,
+                lastReadMessageId: read_state
+                    .as_ref()
+                    .and_then(|read_state| read_state.lastReadMessageId.clone()),
+                unreadCount: read_state
+                    .as_ref()
+                    .map_or(0, |read_state| read_state.unreadCount),
+                mentionCount: read_state.map_or(0, |read_state| read_state.mentionCount),
+                createdAt: conversation.created_at,
+            }
+        })
+        .collect())
+}
+
+/// Members of `channel` if it is a direct conversation, `None` for text
+/// channels which everyone can see.
+async fn direct_member_ids(
+    app_state: Arc<AppState>,
+    channel: &Channel,
+) -> sqlx::Result<Option<Vec<String>>> {
+    if !channel.is_direct() {
+        return Ok(None);
+    }
+
+    let members = queries::get_channel_members(app_state, &[channel.id.clone()]).await?;
+
+    Ok(Some(
+        members.into_iter().map(|member| member.user_id).collect(),
+    ))
+}
+
+/// Emits `event` to everyone who may see `channel`: all sockets for text
+/// channels, only the participants' sockets for direct conversations.
+pub async fn emit_to_channel<T: Serialize + ?Sized>(
+    app_state: Arc<AppState>,
+    channel: &Channel,
+    event: &'static str,
+    data: &T,
+) {
+    emit_to_channel_except(app_state, channel, &[], event, data).await;
+}
+
+/// Like `emit_to_channel`, but skips all connections of `except_user_ids`.
+pub async fn emit_to_channel_except<T: Serialize + ?Sized>(
+    app_state: Arc<AppState>,
+    channel: &Channel,
+    except_user_ids: &[String],
+    event: &'static str,
+    data: &T,
+) {
+    let member_ids = match direct_member_ids(app_state.clone(), channel).await {
+        Ok(member_ids) => member_ids,
+        Err(e) => {
+            warn!("Failed to load members of channel {}: {}", channel.id, e);
+            return;
+        }
+    };
+
+    let result = match member_ids {
+        Some(member_ids) => {
+            let rooms: Vec<String> = member_ids
+                .iter()
+                .filter(|member_id| !except_user_ids.contains(member_id))
+                .map(|member_id| user_room(member_id))
+                .collect();
+
+            // An empty room list would broadcast to every socket
+            if rooms.is_empty() {
+                return;
+            }
+
+            app_state.io.to(rooms).emit(event, data).await
+        }
+        None => {
+            let rooms: Vec<String> = except_user_ids
+                .iter()
+                .map(|user_id| user_room(user_id))
+                .collect();
+
+            app_state.io.except(rooms).emit(event, data).await
+        }
+    };
+
+    if let Err(e) = result {
+        warn!("Failed to emit {} to channel {}: {}", event, channel.id, e);
+    }
+}
+
+/// Emits `event` only to those of `user_ids` who may see `channel`.
+pub async fn emit_to_channel_users<T: Serialize + ?Sized>(
+    app_state: Arc<AppState>,
+    channel: &Channel,
+    user_ids: &[String],
+    event: &'static str,
+    data: &T,
+) {
+    let member_ids = match direct_member_ids(app_state.clone(), channel).await {
+        Ok(member_ids) => member_ids,
+        Err(e) => {
+            warn!("Failed to load members of channel {}: {}", channel.id, e);
+            return;
+        }
+    };
+
+    let rooms: Vec<String> = user_ids
+        .iter()
+        .filter(|user_id| {
+            member_ids
+                .as_ref()
+                .map_or(true, |member_ids| member_ids.contains(user_id))
+        })
+        .map(|user_id| user_room(user_id))
+        .collect();
+
+    if rooms.is_empty() {
+        return;
+    }
+
+    if let Err(e) = app_state.io.to(rooms).emit(event, data).await {
+        warn!("Failed to emit {} to channel {}: {}", event, channel.id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::queries;
+    use crate::test_support::{create_test_user, test_app_state};
+    use sqlx::MySqlPool;
+
+    #[sqlx::test]
+    async fn concurrent_creations_share_one_conversation(pool: MySqlPool) {
+        let app_state = test_app_state(pool);
+        let alice = create_test_user(app_state.clone(), "alice").await;
+        let bob = create_test_user(app_state.clone(), "bob").await;
+        let member_ids = vec![alice.id, bob.id];
+        let reversed_ids: Vec<String> = member_ids.iter().rev().cloned().collect();
+
+        let (first, second) = tokio::join!(
+            queries::create_direct_conversation(app_state.clone(), &member_ids),
+            queries::create_direct_conversation(app_state.clone(), &reversed_ids),
+        );
+
+        let (first, first_created) = first.unwrap();
+        let (second, second_created) = second.unwrap();
+
+        assert_eq!(first.id, second.id);
+        assert_ne!(first_created, second_created);
+    }
+}
@@ -0,0 +1,330 @@
+use crate::errors::AppError;
+use crate::services::link_preview::UrlPreview;
+use crate::services::lru::LruCache;
+use crate::services::safe_fetch::{fetch, FetchError};
+use crate::AppState;
+use axum::body::Bytes;
+use hmac::{Hmac, Mac};
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageError, ImageFormat, ImageReader, Limits};
+use sha2::Sha256;
+use std::fmt;
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use url::form_urlencoded;
+
+pub const PROXY_PATH: &str = "/image-proxy";
+
+/// Remote images larger than this are refused without decoding.
+const MAX_SOURCE_BYTES: usize = 10 * 1024 * 1024;
+/// Larger images are downscaled to fit within this many pixels per side.
+const MAX_DIMENSION: u32 = 1024;
+/// Refuses images whose header claims a size that would be costly to decode.
+const MAX_SOURCE_DIMENSION: u32 = 8192;
+const MAX_DECODE_ALLOC: u64 = 256 * 1024 * 1024;
+const JPEG_QUALITY: u8 = 85;
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const CACHE_CAPACITY: usize = 256;
+/// Total size of the encoded images kept in memory.
+const CACHE_MAX_BYTES: usize = 64 * 1024 * 1024;
+
+const ALLOWED_FORMATS: [ImageFormat; 4] = [
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::Gif,
+    ImageFormat::WebP,
+];
+
+#[derive(Debug)]
+pub enum ImageProxyError {
+    InvalidSignature,
+    Fetch(FetchError),
+    /// The body is not a decodable image in one of the allowed formats.
+    NotAnImage,
+    TooLarge,
+}
+
+impl fmt::Display for ImageProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageProxyError::InvalidSignature => write!(f, "Invalid image signature"),
+            ImageProxyError::Fetch(e) => write!(f, "{}", e),
+            ImageProxyError::NotAnImage => write!(f, "URL does not point at a supported image"),
+            ImageProxyError::TooLarge => write!(f, "Image is too large"),
+        }
+    }
+}
+
+impl From<ImageProxyError> for AppError {
+    fn from(e: ImageProxyError) -> Self {
+        match e {
+            ImageProxyError::InvalidSignature => AppError::Forbidden(e.to_string()),
+            ImageProxyError::Fetch(e) if !e.is_rejected_url() => AppError::Upstream(e.to_string()),
+            e => AppError::Validation(e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ProxiedImage {
+    pub bytes: Bytes,
+    pub content_type: &'static str,
+    expires_at: Instant,
+}
+
+/// Images already fetched and re-encoded, by source URL.
+#[derive(Debug)]
+pub struct ImageProxyCache {
+    images: LruCache<Arc<ProxiedImage>>,
+}
+
+impl Default for ImageProxyCache {
+    fn default() -> Self {
+        Self {
+            images: LruCache::with_max_weight(CACHE_CAPACITY, CACHE_MAX_BYTES, |image| {
+                image.bytes.len()
+            }),
+        }
+    }
+}
+
+impl ImageProxyCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get(&self, url: &str) -> Option<Arc<ProxiedImage>> {
+        let image = self.images.get(url)?;
+        if image.expires_at <= Instant::now() {
+            self.images.remove(url);
+            return None;
+        }
+
+        Some(image)
+    }
+
+    /// Drops expired images.
+    pub fn prune(&self) {
+        let now = Instant::now();
+        self.images.retain(|image| image.expires_at > now);
+    }
+}
+
+fn new_mac(jwt_secret: &str) -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(format!("{}.image-proxy", jwt_secret).as_bytes())
+        .expect("HMAC accepts keys of any size")
+}
+
+/// Hex encoded HMAC-SHA256 of `url`, keyed from the JWT secret so only URLs
+/// this server handed out can be fetched through the proxy.
+pub fn sign_image_url(jwt_secret: &str, url: &str) -> String {
+    let mut mac = new_mac(jwt_secret);
+    mac.update(url.as_bytes());
+
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn verify_signature(jwt_secret: &str, url: &str, signature: &str) -> bool {
+    if signature.len() % 2 != 0 || !signature.is_ascii() {
+        return false;
+    }
+
+    let Ok(signature) = (0..signature.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&signature[i..i + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()
+    else {
+        return false;
+    };
+
+    let mut mac = new_mac(jwt_secret);
+    mac.update(url.as_bytes());
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// The proxy path serving `url`. Anything but an absolute http(s) URL is
+/// returned unchanged, including URLs that already point at the proxy.
+pub fn proxied_image_url(jwt_secret: &str, url: &str) -> String {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return url.to_string();
+    }
+
+    let query = form_urlencoded::Serializer::new(String::new())
+        .append_pair("url", url)
+        .append_pair("sig", &sign_image_url(jwt_secret, url))
+        .finish();
+
+    format!("{}?{}", PROXY_PATH, query)
+}
+
+/// Points the images of `preview` at the proxy so clients never contact
+/// the remote hosts themselves.
+pub fn proxy_preview_images(preview: &mut UrlPreview, jwt_secret: &str) {
+    let proxy = |url: &mut Option<String>| {
+        if let Some(url) = url {
+            *url = proxied_image_url(jwt_secret, url);
+        }
+    };
+
+    proxy(&mut preview.image);
+    proxy(&mut preview.favicon);
+    if let Some(oembed) = &mut preview.oembed {
+        proxy(&mut oembed.thumbnail_url);
+    }
+}
+
+/// Fetches `url` if `signature` matches it, checks that it is an image and
+/// re-encodes it within bounded dimensions. Results are cached in memory.
+pub async fn get_proxied_image(
+    app_state: Arc<AppState>,
+    url: &str,
+    signature: &str,
+) -> Result<Arc<ProxiedImage>, ImageProxyError> {
+    if !verify_signature(&app_state.config.jwt_secret, url, signature) {
+        return Err(ImageProxyError::InvalidSignature);
+    }
+
+    if let Some(image) = app_state.image_proxy_cache.get(url) {
+        return Ok(image);
+    }
+
+    // The content type is checked again by decoding, since servers lie
+    let response = fetch(url, MAX_SOURCE_BYTES, |content_type| {
+        content_type.starts_with("image/")
+    })
+    .await
+    .map_err(ImageProxyError::Fetch)?;
+
+    if !response.content_type().starts_with("image/") {
+        return Err(ImageProxyError::NotAnImage);
+    }
+    if response.truncated {
+        return Err(ImageProxyError::TooLarge);
+    }
+
+    let (bytes, content_type) = tokio::task::spawn_blocking(move || reencode(&response.body))
+        .await
+        .map_err(|_| ImageProxyError::NotAnImage)??;
+
+    let image = Arc::new(ProxiedImage {
+        bytes: Bytes::from(bytes),
+        content_type,
+        expires_at: Instant::now() + CACHE_TTL,
+    });
+    app_state
+        .image_proxy_cache
+        .images
+        .insert(url, image.clone());
+
+    Ok(image)
+}
+
+/// Decodes `body`, downscales it to fit within `MAX_DIMENSION` and encodes
+/// it again, as PNG if it has transparency and JPEG otherwise. Animated
+/// images keep only their first frame.
+pub fn reencode(body: &[u8]) -> Result<(Vec<u8>, &'static str), ImageProxyError> {
+    let mut reader = ImageReader::new(Cursor::new(body))
+        .with_guessed_format()
+        .map_err(|_| ImageProxyError::NotAnImage)?;
+
+    if !reader
+        .format()
+        .is_some_and(|format| ALLOWED_FORMATS.contains(&format))
+    {
+        return Err(ImageProxyError::NotAnImage);
+    }
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_SOURCE_DIMENSION);
+    limits.max_image_height = Some(MAX_SOURCE_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODE_ALLOC);
+    reader.limits(limits);
+
+    let image = reader.decode().map_err(|e| match e {
+        ImageError::Limits(_) => ImageProxyError::TooLarge,
+        _ => ImageProxyError::NotAnImage,
+    })?;
+
+    let image = if image.width() > MAX_DIMENSION || image.height() > MAX_DIMENSION {
+        image.thumbnail(MAX_DIMENSION, MAX_DIMENSION)
+    } else {
+        image
+    };
+
+    let mut encoded = Cursor::new(Vec::new());
+    let content_type = if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+            .write_to(&mut encoded, ImageFormat::Png)
+            .map_err(|_| ImageProxyError::NotAnImage)?;
+        "image/png"
+    } else {
+        let encoder = JpegEncoder::new_with_quality(&mut encoded, JPEG_QUALITY);
+        image
+            .to_rgb8()
+            .write_with_encoder(encoder)
+            .map_err(|_| ImageProxyError::NotAnImage)?;
+        "image/jpeg"
+    };
+
+    Ok((encoded.into_inner(), content_type))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, RgbImage};
+
+    #[test]
+    fn signatures_cover_the_url() {
+        let url = "https://example.com/a.png";
+        let signature = sign_image_url("secret", url);
+
+        assert!(verify_signature("secret", url, &signature));
+        assert!(!verify_signature(
+            "secret",
+            "https://example.com/b.png",
+            &signature
+        ));
+        assert!(!verify_signature("other", url, &signature));
+        assert!(!verify_signature("secret", url, "zz"));
+    }
+
+    #[test]
+    fn proxy_preview_images_rewrites_remote_urls() {
+        let mut preview = UrlPreview {
+            image: Some("https://example.com/a b.png".to_string()),
+            favicon: Some("data:image/png;base64,AAAA".to_string()),
+            ..Default::default()
+        };
+
+        proxy_preview_images(&mut preview, "secret");
+
+        let image = preview.image.unwrap();
+        assert!(image.starts_with("/image-proxy?url=https%3A%2F%2Fexample.com%2Fa+b.png&sig="));
+        assert!(image.ends_with(&sign_image_url("secret", "https://example.com/a b.png")));
+        assert_eq!(preview.favicon.unwrap(), "data:image/png;base64,AAAA");
+    }
+
+    #[test]
+    fn reencode_downscales_large_images() {
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(RgbImage::new(2048, 512))
+            .write_to(&mut png, ImageFormat::Png)
+            .unwrap();
+
+        let (bytes, content_type) = reencode(&png.into_inner()).unwrap();
+        let image = image::load_from_memory(&bytes).unwrap();
+
+        assert_eq!(content_type, "image/jpeg");
+        assert_eq!(image.dimensions(), (1024, 256));
+        assert!(matches!(
+            reencode(b"<html></html>"),
+            Err(ImageProxyError::NotAnImage)
+        ));
+    }
+}
@@ -1,96 +1,523 @@
-use reqwest::header::CONTENT_TYPE;
-use reqwest::Client;
+use crate::services::safe_fetch::{fetch, FetchError};
+use encoding_rs::{Encoding, UTF_8};
 use select::document::Document;
 use select::predicate::{Attr, Name, Or, Predicate};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
 use url::Url;
 
-#[derive(Debug, Default, Serialize)]
+/// Metadata lives in the head, so the rest of a large page is not needed.
+const MAX_HTML_BYTES: usize = 1024 * 1024;
+const MAX_OEMBED_BYTES: usize = 64 * 1024;
+/// How far into a page a `<meta charset>` is looked for, as browsers do.
+const CHARSET_SNIFF_BYTES: usize = 1024;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct UrlPreview {
     pub title: Option<String>,
     pub description: Option<String>,
     pub image: Option<String>,
     pub favicon: Option<String>,
     pub url: String,
+    #[serde(rename = "siteName")]
+    pub site_name: Option<String>,
+    /// `og:type`, e.g. "article" or "video.other".
+    #[serde(rename = "type")]
+    pub preview_type: Option<String>,
+    #[serde(rename = "imageWidth")]
+    pub image_width: Option<u32>,
+    #[serde(rename = "imageHeight")]
+    pub image_height: Option<u32>,
+    pub video: Option<PreviewMedia>,
+    pub audio: Option<PreviewMedia>,
+    #[serde(rename = "themeColor")]
+    pub theme_color: Option<String>,
+    pub author: Option<String>,
+    #[serde(rename = "publishedAt")]
+    pub published_at: Option<String>,
+    /// `twitter:card`, e.g. "summary_large_image" or "player".
+    #[serde(rename = "twitterCard")]
+    pub twitter_card: Option<String>,
+    pub oembed: Option<OEmbed>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PreviewMedia {
+    pub url: String,
+    #[serde(rename = "type")]
+    pub mime_type: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// The parts of an oEmbed response worth showing. Its `html` is dropped so
+/// clients never inject third-party markup.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct OEmbed {
+    #[serde(rename = "type")]
+    pub oembed_type: Option<String>,
+    #[serde(rename = "providerName", alias = "provider_name")]
+    pub provider_name: Option<String>,
+    #[serde(rename = "providerUrl", alias = "provider_url")]
+    pub provider_url: Option<String>,
+    pub title: Option<String>,
+    #[serde(rename = "authorName", alias = "author_name")]
+    pub author_name: Option<String>,
+    #[serde(rename = "authorUrl", alias = "author_url")]
+    pub author_url: Option<String>,
+    #[serde(rename = "thumbnailUrl", alias = "thumbnail_url")]
+    pub thumbnail_url: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_dimension")]
+    pub width: Option<u32>,
+    #[serde(default, deserialize_with = "deserialize_dimension")]
+    pub height: Option<u32>,
+}
+
+/// Providers send dimensions as numbers or strings, and sometimes null.
+fn deserialize_dimension<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Number(number) => number.as_u64().and_then(|n| u32::try_from(n).ok()),
+        Value::String(string) => string.trim().parse().ok(),
+        _ => None,
+    })
+}
+
+#[derive(Debug)]
+pub enum LinkPreviewError {
+    Fetch(FetchError),
+    NotHtml(String),
+}
+
+impl fmt::Display for LinkPreviewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkPreviewError::Fetch(e) => write!(f, "{}", e),
+            LinkPreviewError::NotHtml(content_type) => {
+                write!(f, "Not an HTML page: {}", content_type)
+            }
+        }
+    }
+}
+
+impl LinkPreviewError {
+    pub fn is_rejected_url(&self) -> bool {
+        matches!(self, LinkPreviewError::Fetch(e) if e.is_rejected_url())
+    }
+}
+
+impl From<FetchError> for LinkPreviewError {
+    fn from(e: FetchError) -> Self {
+        LinkPreviewError::Fetch(e)
+    }
 }
 
-pub async fn get_url_preview(input_url: &str) -> Result<UrlPreview, Box<dyn std::error::Error>> {
-    let parsed_url =
-        Url::parse(input_url).map_err(|e| format!("Invalid URL '{}': {}", input_url, e))?;
+fn is_html(content_type: &str) -> bool {
+    content_type.starts_with("text/html")
+}
+
+fn is_json(content_type: &str) -> bool {
+    content_type.contains("json")
+}
+
+pub async fn get_url_preview(input_url: &str) -> Result<UrlPreview, LinkPreviewError> {
+    let response = fetch(input_url, MAX_HTML_BYTES, is_html).await?;
+
+    if !is_html(response.content_type()) {
+        return Err(LinkPreviewError::NotHtml(
+            response.content_type().to_string(),
+        ));
+    }
 
-    if parsed_url.scheme() != "http" && parsed_url.scheme() != "https" {
-        return Err(format!("Unsupported URL scheme: {}", parsed_url.scheme()).into());
+    let html = decode_html(&response.body, response.content_type());
+    let (mut preview, oembed_url) = extract_preview(&html, &response.url);
+
+    // The page preview stands on its own if the oEmbed endpoint fails
+    if let Some(oembed_url) = oembed_url {
+        if let Ok(oembed_response) = fetch(oembed_url.as_str(), MAX_OEMBED_BYTES, is_json).await {
+            if let Ok(oembed) = serde_json::from_slice::<OEmbed>(&oembed_response.body) {
+                merge_oembed(&mut preview, oembed);
+            }
+        }
     }
 
-    let client = Client::builder()
-        .user_agent("Mozilla/5.0 (compatible; RustBot/1.0)")
-        .build()?;
+    Ok(preview)
+}
 
-    let response = client.get(input_url).send().await?;
+fn charset_regex() -> &'static regex::bytes::Regex {
+    static CHARSET_REGEX: OnceLock<regex::bytes::Regex> = OnceLock::new();
+    CHARSET_REGEX.get_or_init(|| {
+        regex::bytes::Regex::new(r#"(?i)<meta[^>]+charset\s*=\s*["']?\s*([a-z0-9_:.\-]+)"#)
+            .expect("charset pattern is valid")
+    })
+}
 
-    if !response.status().is_success() {
-        return Err(format!("Failed to fetch URL: HTTP {}", response.status()).into());
+/// Decodes a page the way browsers do: a byte order mark wins over the
+/// `Content-Type` charset, which wins over `<meta charset>` or its
+/// `http-equiv` form. Anything else is read as UTF-8.
+pub fn decode_html(body: &[u8], content_type: &str) -> String {
+    let from_header = content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .and_then(|(_, label)| Encoding::for_label(label.trim().trim_matches('"').as_bytes()));
+
+    let from_meta = || {
+        charset_regex()
+            .captures(&body[..body.len().min(CHARSET_SNIFF_BYTES)])
+            .and_then(|captures| Encoding::for_label(&captures[1]))
+    };
+
+    let encoding = Encoding::for_bom(body)
+        .map(|(encoding, _)| encoding)
+        .or(from_header)
+        .or_else(from_meta)
+        .unwrap_or(UTF_8);
+
+    let (text, _, _) = encoding.decode(body);
+    text.into_owned()
+}
+
+/// The metadata found in `<script type="application/ld+json">` blocks.
+#[derive(Debug, Default)]
+struct JsonLd {
+    headline: Option<String>,
+    description: Option<String>,
+    image: Option<String>,
+    author: Option<String>,
+    published_at: Option<String>,
+    publisher: Option<String>,
+}
+
+/// A string, an object with `key`, or the first of an array of either.
+fn json_ld_text(value: &Value, key: &str) -> Option<String> {
+    match value {
+        Value::String(string) => Some(string.clone()),
+        Value::Object(object) => object.get(key).and_then(|value| json_ld_text(value, key)),
+        Value::Array(values) => values.iter().find_map(|value| json_ld_text(value, key)),
+        _ => None,
+    }
+}
+
+/// The objects of a JSON-LD block, including those nested in `@graph`.
+fn json_ld_objects(value: &Value) -> Vec<&serde_json::Map<String, Value>> {
+    match value {
+        Value::Array(values) => values.iter().flat_map(json_ld_objects).collect(),
+        Value::Object(object) => {
+            let mut objects = vec![object];
+            if let Some(graph) = object.get("@graph") {
+                objects.extend(json_ld_objects(graph));
+            }
+            objects
+        }
+        _ => vec![],
     }
+}
+
+fn extract_json_ld(document: &Document) -> JsonLd {
+    let blocks: Vec<Value> = document
+        .find(Name("script").and(Attr("type", "application/ld+json")))
+        .filter_map(|node| serde_json::from_str(node.text().trim()).ok())
+        .collect();
 
-    let content_type = response
-        .headers()
-        .get(CONTENT_TYPE)
-        .and_then(|ct| ct.to_str().ok())
-        .unwrap_or("");
+    // The main entity is the object with a headline, or failing that a name
+    let objects: Vec<_> = blocks.iter().flat_map(json_ld_objects).collect();
+    let Some(main) = objects
+        .iter()
+        .find(|object| object.contains_key("headline"))
+        .or_else(|| objects.iter().find(|object| object.contains_key("name")))
+    else {
+        return JsonLd::default();
+    };
 
-    if !content_type.starts_with("text/html") {
-        return Err(format!("Not an HTML page: {}", content_type).into());
+    let text = |key: &str, inner_key: &str| main.get(key).and_then(|v| json_ld_text(v, inner_key));
+
+    JsonLd {
+        headline: text("headline", "").or_else(|| text("name", "")),
+        description: text("description", ""),
+        image: text("image", "url").or_else(|| text("thumbnailUrl", "url")),
+        author: text("author", "name"),
+        published_at: text("datePublished", ""),
+        publisher: text("publisher", "name"),
     }
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
 
-    let final_url = response.url().to_string();
-    let body = response.text().await?;
-    let document = Document::from(body.as_str());
+/// Extracts the preview of a page fetched from `page_url`, along with the
+/// oEmbed endpoint the page advertises. Open Graph wins over Twitter cards,
+/// which win over JSON-LD and finally the plain HTML.
+pub fn extract_preview(html: &str, page_url: &Url) -> (UrlPreview, Option<Url>) {
+    let document = Document::from(html);
 
+    // The first of repeated tags is the primary one, e.g. for `og:image`
     let mut meta_map = HashMap::new();
     for node in document.find(Name("meta")) {
-        if let Some(name) = node.attr("name").or_else(|| node.attr("property")) {
-            if let Some(content) = node.attr("content") {
-                meta_map.insert(name.to_lowercase(), content.to_string());
+        if let Some(name) = node.attr("property").or_else(|| node.attr("name")) {
+            if let Some(content) = node.attr("content").and_then(non_empty) {
+                meta_map.entry(name.to_lowercase()).or_insert(content);
             }
         }
     }
+    let meta = |keys: &[&str]| keys.iter().find_map(|key| meta_map.get(*key).cloned());
+    let dimension = |key: &str| meta(&[key]).and_then(|value| value.parse::<u32>().ok());
+    let resolve = |link: String| resolve_url(page_url, &link);
+
+    let json_ld = extract_json_ld(&document);
+
+    let title = meta(&["og:title", "twitter:title"])
+        .or(json_ld.headline)
+        .or_else(|| {
+            document
+                .find(Name("title"))
+                .next()
+                .and_then(|node| non_empty(&node.text()))
+        });
 
-    let title = document
-        .find(Name("title"))
-        .next()
-        .map(|n| n.text())
-        .or_else(|| meta_map.get("og:title").cloned());
+    let description =
+        meta(&["og:description", "twitter:description", "description"]).or(json_ld.description);
 
-    let description = meta_map
-        .get("description")
-        .or_else(|| meta_map.get("og:description"))
-        .cloned();
+    let image = meta(&[
+        "og:image:secure_url",
+        "og:image:url",
+        "og:image",
+        "twitter:image",
+        "twitter:image:src",
+    ])
+    .or(json_ld.image)
+    .map(resolve);
 
-    let image = meta_map
-        .get("og:image")
-        .or_else(|| meta_map.get("twitter:image"))
-        .cloned();
+    let video = meta(&["og:video:secure_url", "og:video:url", "og:video"])
+        .map(|url| PreviewMedia {
+            url: resolve(url),
+            mime_type: meta(&["og:video:type"]),
+            width: dimension("og:video:width"),
+            height: dimension("og:video:height"),
+        })
+        .or_else(|| {
+            meta(&["twitter:player"]).map(|url| PreviewMedia {
+                url: resolve(url),
+                mime_type: None,
+                width: dimension("twitter:player:width"),
+                height: dimension("twitter:player:height"),
+            })
+        });
+
+    let audio =
+        meta(&["og:audio:secure_url", "og:audio:url", "og:audio"]).map(|url| PreviewMedia {
+            url: resolve(url),
+            mime_type: meta(&["og:audio:type"]),
+            width: None,
+            height: None,
+        });
 
     let favicon = document
         .find(Or(Attr("rel", "icon"), Attr("rel", "shortcut icon")))
-        .filter_map(|n| n.attr("href"))
-        .map(|href| resolve_url(&final_url, href))
+        .filter_map(|node| node.attr("href"))
+        .map(|href| resolve_url(page_url, href))
         .next();
 
-    Ok(UrlPreview {
+    let oembed_url = document
+        .find(Name("link").and(Attr("type", "application/json+oembed")))
+        .filter_map(|node| node.attr("href"))
+        .find_map(|href| page_url.join(href.trim()).ok());
+
+    let preview = UrlPreview {
         title,
         description,
         image,
         favicon,
-        url: final_url,
-    })
+        url: page_url.to_string(),
+        site_name: meta(&["og:site_name", "application-name"]).or(json_ld.publisher),
+        preview_type: meta(&["og:type"]),
+        image_width: dimension("og:image:width"),
+        image_height: dimension("og:image:height"),
+        video,
+        audio,
+        theme_color: meta(&["theme-color"]),
+        author: meta(&["author", "article:author"]).or(json_ld.author),
+        published_at: meta(&["article:published_time"]).or(json_ld.published_at),
+        twitter_card: meta(&["twitter:card"]),
+        oembed: None,
+    };
+
+    (preview, oembed_url)
 }
 
-fn resolve_url(base: &str, link: &str) -> String {
-    Url::parse(base)
-        .ok()
-        .and_then(|base_url| base_url.join(link).ok())
-        .map(|u| u.to_string())
-        .unwrap_or_else(|| link.to_string())
+/// Fills gaps in `preview` from the page's oEmbed response.
+fn merge_oembed(preview: &mut UrlPreview, oembed: OEmbed) {
+    if preview.title.is_none() {
+        preview.title = oembed.title.clone();
+    }
+    if preview.site_name.is_none() {
+        preview.site_name = oembed.provider_name.clone();
+    }
+    if preview.author.is_none() {
+        preview.author = oembed.author_name.clone();
+    }
+    if preview.image.is_none() {
+        preview.image = oembed.thumbnail_url.clone();
+    }
+
+    preview.oembed = Some(oembed);
+}
+
+fn resolve_url(base: &Url, link: &str) -> String {
+    base.join(link.trim())
+        .map(|url| url.to_string())
+        .unwrap_or_else(|_| link.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! fixture {
+        ($name:literal) => {
+            include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/link_preview/",
+                $name
+            ))
+        };
+    }
+
+    fn extract(html: &[u8], content_type: &str) -> (UrlPreview, Option<Url>) {
+        let page_url = Url::parse("https://news.example.com/2024/story").unwrap();
+        extract_preview(&decode_html(html, content_type), &page_url)
+    }
+
+    #[test]
+    fn open_graph_wins_over_title_tag() {
+        let (preview, oembed_url) = extract(fixture!("article.html"), "text/html");
+
+        assert_eq!(preview.title.as_deref(), Some("Rust 2.0 released"));
+        assert_eq!(preview.description.as_deref(), Some("The OG description."));
+        assert_eq!(preview.site_name.as_deref(), Some("Example News"));
+        assert_eq!(preview.preview_type.as_deref(), Some("article"));
+        assert_eq!(
+            preview.image.as_deref(),
+            Some("https://news.example.com/images/hero.jpg")
+        );
+        assert_eq!(preview.image_width, Some(1200));
+        assert_eq!(preview.image_height, Some(630));
+        assert_eq!(preview.theme_color.as_deref(), Some("#ff6600"));
+        assert_eq!(preview.author.as_deref(), Some("Jane Doe"));
+        assert_eq!(
+            preview.published_at.as_deref(),
+            Some("2024-05-01T10:00:00Z")
+        );
+        assert_eq!(preview.twitter_card.as_deref(), Some("summary_large_image"));
+        assert_eq!(
+            preview.favicon.as_deref(),
+            Some("https://news.example.com/favicon.png")
+        );
+        assert_eq!(
+            oembed_url.map(|url| url.to_string()).as_deref(),
+            Some("https://news.example.com/oembed?url=https%3A%2F%2Fnews.example.com%2F2024%2Fstory&format=json")
+        );
+    }
+
+    #[test]
+    fn json_ld_fills_in_without_open_graph() {
+        let (preview, oembed_url) = extract(fixture!("json_ld.html"), "text/html");
+
+        assert_eq!(preview.title.as_deref(), Some("Markets rally on Friday"));
+        assert_eq!(
+            preview.description.as_deref(),
+            Some("Stocks closed higher.")
+        );
+        assert_eq!(
+            preview.image.as_deref(),
+            Some("https://cdn.example.com/rally.jpg")
+        );
+        assert_eq!(preview.author.as_deref(), Some("Alex Smith"));
+        assert_eq!(preview.site_name.as_deref(), Some("Daily Example"));
+        assert_eq!(preview.published_at.as_deref(), Some("2024-06-07"));
+        assert!(oembed_url.is_none());
+    }
+
+    #[test]
+    fn twitter_cards_and_media_are_extracted() {
+        let (preview, _) = extract(fixture!("video.html"), "text/html");
+
+        assert_eq!(preview.title.as_deref(), Some("Cat plays piano"));
+        assert_eq!(
+            preview.video,
+            Some(PreviewMedia {
+                url: "https://videos.example.com/cat.mp4".to_string(),
+                mime_type: Some("video/mp4".to_string()),
+                width: Some(1280),
+                height: Some(720),
+            })
+        );
+        assert_eq!(
+            preview.audio.map(|audio| audio.url).as_deref(),
+            Some("https://videos.example.com/cat.mp3")
+        );
+
+        let (preview, _) = extract(fixture!("twitter_player.html"), "text/html");
+
+        assert_eq!(preview.title.as_deref(), Some("Live stream"));
+        assert_eq!(preview.twitter_card.as_deref(), Some("player"));
+        assert_eq!(
+            preview.video,
+            Some(PreviewMedia {
+                url: "https://player.example.com/embed/42".to_string(),
+                mime_type: None,
+                width: Some(640),
+                height: Some(360),
+            })
+        );
+    }
+
+    #[test]
+    fn charset_comes_from_meta_tag_or_header() {
+        let (preview, _) = extract(fixture!("windows_1252.html"), "text/html");
+        assert_eq!(preview.title.as_deref(), Some("Café crème – menu"));
+
+        let (preview, _) = extract(fixture!("shift_jis.html"), "text/html; charset=Shift_JIS");
+        assert_eq!(preview.title.as_deref(), Some("日本語のページ"));
+
+        // The header wins over a conflicting meta tag
+        assert_eq!(
+            decode_html(
+                b"<meta charset=\"windows-1252\">caf\xc3\xa9",
+                "text/html; charset=utf-8"
+            ),
+            "<meta charset=\"windows-1252\">café"
+        );
+    }
+
+    #[test]
+    fn oembed_fills_gaps_without_its_html() {
+        let oembed: OEmbed = serde_json::from_str(
+            r#"{"type":"video","provider_name":"ExampleTube","author_name":"Chef","title":"Pasta","thumbnail_url":"https://i.example.com/t.jpg","width":"480","height":270,"html":"<iframe></iframe>"}"#,
+        )
+        .unwrap();
+
+        let mut preview = UrlPreview::default();
+        merge_oembed(&mut preview, oembed);
+
+        assert_eq!(preview.title.as_deref(), Some("Pasta"));
+        assert_eq!(preview.site_name.as_deref(), Some("ExampleTube"));
+        assert_eq!(
+            preview.image.as_deref(),
+            Some("https://i.example.com/t.jpg")
+        );
+
+        let oembed = serde_json::to_value(preview.oembed.unwrap()).unwrap();
+        assert_eq!(oembed["width"], 480);
+        assert_eq!(oembed["height"], 270);
+        assert!(oembed.get("html").is_none());
+    }
 }
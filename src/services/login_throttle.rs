@@ -0,0 +1,311 @@
+use dashmap::DashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Distinct IPs whose throttled attempts are counted individually between
+/// two summaries. Attempts from further IPs only add to the total.
+const MAX_THROTTLED_IPS: usize = 10_000;
+
+/// Back-off policy for one kind of key (account or IP).
+#[derive(Debug, Clone, Copy)]
+struct ThrottlePolicy {
+    /// Failures allowed before any delay is enforced.
+    free_attempts: u32,
+    /// Failures after which the key is locked out entirely.
+    lockout_threshold: u32,
+    lockout_duration: Duration,
+    max_backoff: Duration,
+    /// Counters are forgotten once no failure happened for this long.
+    reset_after: Duration,
+}
+
+const ACCOUNT_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 3,
+    lockout_threshold: 10,
+    lockout_duration: Duration::from_secs(15 * 60),
+    max_backoff: Duration::from_secs(5 * 60),
+    reset_after: Duration::from_secs(60 * 60),
+};
+
+const IP_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 10,
+    lockout_threshold: 50,
+    lockout_duration: Duration::from_secs(30 * 60),
+    max_backoff: Duration::from_secs(5 * 60),
+    reset_after: Duration::from_secs(60 * 60),
+};
+
+#[derive(Debug, Clone, Copy)]
+struct AttemptState {
+    failures: u32,
+    last_failure: Instant,
+}
+
+impl AttemptState {
+    fn blocked_until(&self, policy: &ThrottlePolicy) -> Option<Instant> {
+        if self.failures >= policy.lockout_threshold {
+            return Some(self.last_failure + policy.lockout_duration);
+        }
+
+        if self.failures < policy.free_attempts {
+            return None;
+        }
+
+        // 1s, 2s, 4s, ... capped at max_backoff
+        let exponent = (self.failures - policy.free_attempts).min(16);
+        let backoff = Duration::from_secs(1u64 << exponent).min(policy.max_backoff);
+
+        Some(self.last_failure + backoff)
+    }
+
+    fn is_stale(&self, policy: &ThrottlePolicy, now: Instant) -> bool {
+        now.duration_since(self.last_failure) > policy.reset_after
+    }
+}
+
+/// In-memory failed login counters per account and per client IP.
+#[derive(Debug, Default)]
+pub struct LoginThrottle {
+    accounts: DashMap<String, AttemptState>,
+    ips: DashMap<IpAddr, AttemptState>,
+    /// Attempts rejected while throttled, per IP, since the last
+    /// `take_throttled_summary`. Kept out of the database so a flood of
+    /// rejected attempts costs no writes.
+    throttled: DashMap<IpAddr, u64>,
+    /// Throttled attempts from IPs beyond `MAX_THROTTLED_IPS`.
+    throttled_overflow: AtomicU64,
+}
+
+/// Attempts rejected while throttled over some period.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ThrottledSummary {
+    pub attempts: u64,
+    /// Distinct IPs counted, at most `MAX_THROTTLED_IPS`.
+    pub ips: usize,
+    /// The IP with the most rejected attempts and their number.
+    pub busiest_ip: Option<(IpAddr, u64)>,
+}
+
+fn retry_after<K: Eq + Hash>(
+    map: &DashMap<K, AttemptState>,
+    key: &K,
+    policy: &ThrottlePolicy,
+    now: Instant,
+) -> Option<Duration> {
+    let state = map.get(key)?;
+
+    if state.is_stale(policy, now) {
+        return None;
+    }
+
+    state
+        .blocked_until(policy)
+        .filter(|until| *until > now)
+        .map(|until| until - now)
+}
+
+fn register_failure<K: Eq + Hash>(
+    map: &DashMap<K, AttemptState>,
+    key: K,
+    policy: &ThrottlePolicy,
+    now: Instant,
+) {
+    let mut state = map.entry(key).or_insert(AttemptState {
+        failures: 0,
+        last_failure: now,
+    });
+
+    if state.is_stale(policy, now) {
+        state.failures = 0;
+    }
+
+    state.failures = state.failures.saturating_add(1);
+    state.last_failure = now;
+}
+
+impl LoginThrottle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns how long the caller has to wait before another attempt for
+    /// this account / IP combination is accepted, if at all.
+    pub fn check(&self, account: &str, ip: IpAddr) -> Option<Duration> {
+        let now = Instant::now();
+        let account = account.to_lowercase();
+
+        let account_wait = retry_after(&self.accounts, &account, &ACCOUNT_POLICY, now);
+        let ip_wait = retry_after(&self.ips, &ip, &IP_POLICY, now);
+
+        account_wait.max(ip_wait)
+    }
+
+    pub fn record_failure(&self, account: &str, ip: IpAddr) {
+        let now = Instant::now();
+
+        register_failure(&self.accounts, account.to_lowercase(), &ACCOUNT_POLICY, now);
+        register_failure(&self.ips, ip, &IP_POLICY, now);
+    }
+
+    /// Counts an attempt that `check` rejected.
+    pub fn record_throttled(&self, ip: IpAddr) {
+        if let Some(mut attempts) = self.throttled.get_mut(&ip) {
+            *attempts += 1;
+            return;
+        }
+
+        if self.throttled.len() >= MAX_THROTTLED_IPS {
+            self.throttled_overflow.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        *self.throttled.entry(ip).or_insert(0) += 1;
+    }
+
+    /// Summarises and resets the throttled attempts, `None` if there were
+    /// none.
+    pub fn take_throttled_summary(&self) -> Option<ThrottledSummary> {
+        let ips: Vec<IpAddr> = self.throttled.iter().map(|entry| *entry.key()).collect();
+        let counts: Vec<(IpAddr, u64)> = ips
+            .into_iter()
+            .filter_map(|ip| self.throttled.remove(&ip))
+            .collect();
+        let overflow = self.throttled_overflow.swap(0, Ordering::Relaxed);
+
+        let attempts = counts.iter().map(|(_, attempts)| attempts).sum::<u64>() + overflow;
+        if attempts == 0 {
+            return None;
+        }
+
+        Some(ThrottledSummary {
+            attempts,
+            ips: counts.len(),
+            busiest_ip: counts.into_iter().max_by_key(|(_, attempts)| *attempts),
+        })
+    }
+
+    /// Clears the account counter. The IP counter is left untouched so a
+    /// client cannot reset it by logging into an account of its own.
+    pub fn record_success(&self, account: &str) {
+        self.accounts.remove(&account.to_lowercase());
+    }
+
+    /// Drops counters that have not seen a failure in a while.
+    pub fn prune(&self) {
+        let now = Instant::now();
+
+        self.accounts
+            .retain(|_, state| !state.is_stale(&ACCOUNT_POLICY, now));
+        self.ips.retain(|_, state| !state.is_stale(&IP_POLICY, now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn locks_out_after_the_threshold() {
+        let accounts = DashMap::new();
+        let start = Instant::now();
+
+        for _ in 0..ACCOUNT_POLICY.free_attempts {
+            register_failure(&accounts, "alice", &ACCOUNT_POLICY, start);
+        }
+        assert_eq!(
+            retry_after(&accounts, &"alice", &ACCOUNT_POLICY, start),
+            Some(Duration::from_secs(1))
+        );
+
+        for _ in ACCOUNT_POLICY.free_attempts..ACCOUNT_POLICY.lockout_threshold {
+            register_failure(&accounts, "alice", &ACCOUNT_POLICY, start);
+        }
+        assert_eq!(
+            retry_after(&accounts, &"alice", &ACCOUNT_POLICY, start),
+            Some(ACCOUNT_POLICY.lockout_duration)
+        );
+
+        let after_lockout = start + ACCOUNT_POLICY.lockout_duration + Duration::from_secs(1);
+        assert_eq!(
+            retry_after(&accounts, &"alice", &ACCOUNT_POLICY, after_lockout),
+            None
+        );
+    }
+
+    #[test]
+    fn forgets_failures_after_the_reset_window() {
+        let accounts = DashMap::new();
+        let start = Instant::now();
+
+        for _ in 0..ACCOUNT_POLICY.lockout_threshold {
+            register_failure(&accounts, "alice", &ACCOUNT_POLICY, start);
+        }
+
+        let later = start + ACCOUNT_POLICY.reset_after + Duration::from_secs(1);
+        register_failure(&accounts, "alice", &ACCOUNT_POLICY, later);
+
+        assert_eq!(accounts.get("alice").unwrap().failures, 1);
+        assert_eq!(
+            retry_after(&accounts, &"alice", &ACCOUNT_POLICY, later),
+            None
+        );
+    }
+
+    #[test]
+    fn success_resets_the_account_but_not_the_ip() {
+        let throttle = LoginThrottle::new();
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        for _ in 0..ACCOUNT_POLICY.lockout_threshold {
+            throttle.record_failure("Alice", ip);
+        }
+        assert!(throttle.check("alice", ip).is_some());
+
+        throttle.record_success("alice");
+
+        assert!(throttle.accounts.is_empty());
+        assert_eq!(
+            throttle.ips.get(&ip).unwrap().failures,
+            ACCOUNT_POLICY.lockout_threshold
+        );
+    }
+
+    #[test]
+    fn throttled_attempts_are_summarised_per_ip() {
+        let throttle = LoginThrottle::new();
+        let first = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let second = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+        throttle.record_throttled(first);
+        throttle.record_throttled(second);
+        throttle.record_throttled(second);
+
+        assert_eq!(
+            throttle.take_throttled_summary(),
+            Some(ThrottledSummary {
+                attempts: 3,
+                ips: 2,
+                busiest_ip: Some((second, 2)),
+            })
+        );
+        assert_eq!(throttle.take_throttled_summary(), None);
+    }
+
+    #[test]
+    fn throttled_ips_are_capped() {
+        let throttle = LoginThrottle::new();
+
+        for i in 0..MAX_THROTTLED_IPS as u32 + 5 {
+            throttle.record_throttled(IpAddr::V4(Ipv4Addr::from(i)));
+        }
+
+        assert_eq!(throttle.throttled.len(), MAX_THROTTLED_IPS);
+
+        let summary = throttle.take_throttled_summary().unwrap();
+        assert_eq!(summary.attempts, MAX_THROTTLED_IPS as u64 + 5);
+        assert_eq!(summary.ips, MAX_THROTTLED_IPS);
+    }
+}
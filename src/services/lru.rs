@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// A small thread-safe least recently used cache. Evicting scans for the
+/// oldest entry, which is cheap at the capacities it is used with.
+///
+/// Besides the entry count, the cache can be bounded by the total weight of
+/// its values, e.g. their size in bytes.
+#[derive(Debug)]
+pub struct LruCache<V> {
+    capacity: usize,
+    max_weight: usize,
+    weigh: fn(&V) -> usize,
+    inner: Mutex<LruInner<V>>,
+}
+
+#[derive(Debug)]
+struct LruInner<V> {
+    /// Values with the tick they were last used at.
+    entries: HashMap<String, (V, u64)>,
+    tick: u64,
+    weight: usize,
+}
+
+impl<V> LruInner<V> {
+    fn evict_oldest(&mut self, weigh: fn(&V) -> usize) -> bool {
+        let oldest = self
+            .entries
+            .iter()
+            .min_by_key(|(_, (_, last_used))| *last_used)
+            .map(|(key, _)| key.clone());
+
+        match oldest.and_then(|oldest| self.entries.remove(&oldest)) {
+            Some((value, _)) => {
+                self.weight -= weigh(&value);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<V: Clone> LruCache<V> {
+    pub fn new(capacity: usize) -> Self {
+        Self::with_max_weight(capacity, usize::MAX, |_| 0)
+    }
+
+    /// A cache that also evicts until the values `weigh` at most
+    /// `max_weight` in total. Values heavier than that are never stored.
+    pub fn with_max_weight(capacity: usize, max_weight: usize, weigh: fn(&V) -> usize) -> Self {
+        Self {
+            capacity,
+            max_weight,
+            weigh,
+            inner: Mutex::new(LruInner {
+                entries: HashMap::new(),
+                tick: 0,
+                weight: 0,
+            }),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+
+        let (value, last_used) = inner.entries.get_mut(key)?;
+        *last_used = tick;
+
+        Some(value.clone())
+    }
+
+    pub fn insert(&self, key: &str, value: V) {
+        let weight = (self.weigh)(&value);
+        if weight > self.max_weight {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some((previous, _)) = inner.entries.remove(key) {
+            inner.weight -= (self.weigh)(&previous);
+        }
+
+        while inner.entries.len() >= self.capacity || inner.weight + weight > self.max_weight {
+            if !inner.evict_oldest(self.weigh) {
+                break;
+            }
+        }
+
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.weight += weight;
+        inner.entries.insert(key.to_string(), (value, tick));
+    }
+
+    pub fn remove(&self, key: &str) {
+        let mut inner = self.inner.lock().unwrap();
+
+        if let Some((value, _)) = inner.entries.remove(key) {
+            inner.weight -= (self.weigh)(&value);
+        }
+    }
+
+    pub fn clear(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.entries.clear();
+        inner.weight = 0;
+    }
+
+    /// Keeps only the values `keep` returns true for.
+    pub fn retain(&self, mut keep: impl FnMut(&V) -> bool) {
+        let mut inner = self.inner.lock().unwrap();
+        let mut removed = 0;
+
+        inner.entries.retain(|_, (value, _)| {
+            let kept = keep(value);
+            if !kept {
+                removed += (self.weigh)(value);
+            }
+            kept
+        });
+        inner.weight -= removed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get("a"), Some(1));
+
+        cache.insert("c", 3);
+
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("c"), Some(3));
+    }
+
+    #[test]
+    fn evicts_until_within_max_weight() {
+        let cache: LruCache<Vec<u8>> = LruCache::with_max_weight(10, 10, |value| value.len());
+        cache.insert("a", vec![0; 4]);
+        cache.insert("b", vec![0; 4]);
+        assert!(cache.get("a").is_some());
+
+        cache.insert("c", vec![0; 5]);
+
+        assert!(cache.get("a").is_some());
+        assert_eq!(cache.get("b"), None);
+        assert!(cache.get("c").is_some());
+
+        cache.insert("d", vec![0; 11]);
+        assert_eq!(cache.get("d"), None);
+        assert!(cache.get("c").is_some());
+
+        cache.insert("a", vec![0; 6]);
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_none());
+    }
+}
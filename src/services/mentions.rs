@@ -0,0 +1,235 @@
+use crate::models::{
+    Channel, Message, MessageMention, User, MENTION_TYPE_EVERYONE, MENTION_TYPE_ROLE,
+    MENTION_TYPE_USER,
+};
+use crate::queries;
+use crate::responses::MessageResource;
+use crate::socket::events::socket_publish_events;
+use crate::AppState;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::warn;
+use uuid::Uuid;
+
+const EVERYONE: &str = "everyone";
+const MAX_MENTIONS_PER_MESSAGE: usize = 50;
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.')
+}
+
+/// Extracts the names following `@` in `content`, lowercased and without
+/// duplicates, in order of appearance. An `@` preceded by a name character,
+/// as in e-mail addresses, does not start a mention.
+pub fn parse_mentions(content: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut previous: Option<char> = None;
+
+    for (index, c) in content.char_indices() {
+        if c == '@' && !previous.is_some_and(is_name_char) {
+            let name = content[index + 1..]
+                .chars()
+                .take_while(|c| is_name_char(*c))
+                .collect::<String>();
+            // Punctuation at the end of a sentence is not part of the name
+            let name = name.trim_end_matches(['.', '-']).to_lowercase();
+
+            if !name.is_empty() && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+
+        previous = Some(c);
+    }
+
+    names.truncate(MAX_MENTIONS_PER_MESSAGE);
+    names
+}
+
+/// Resolves and stores the mentions in `message`. Names are matched against
+/// usernames first, then role names. `@everyone` is dropped unless the
+/// author has the `mention_everyone` permission.
+pub async fn record_mentions(
+    app_state: Arc<AppState>,
+    message: &Message,
+    author: &User,
+) -> sqlx::Result<Vec<MessageMention>> {
+    let names = parse_mentions(message.content.as_deref().unwrap_or(""));
+    if names.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let users: HashMap<String, User> = queries::get_users_by_usernames(app_state.clone(), &names)
+        .await?
+        .into_iter()
+        .map(|user| (user.username.to_lowercase(), user))
+        .collect();
+
+    let role_names = names
+        .iter()
+        .filter(|name| !users.contains_key(*name) && name.as_str() != EVERYONE)
+        .cloned()
+        .collect::<Vec<_>>();
+    let roles: HashMap<String, String> =
+        queries::get_roles_by_names(app_state.clone(), &role_names)
+            .await?
+            .into_iter()
+            .map(|role| (role.name.to_lowercase(), role.id))
+            .collect();
+
+    let mut mentions = Vec::new();
+    for name in &names {
+        let (mention_type, user_id, role_id) = if let Some(user) = users.get(name) {
+            (MENTION_TYPE_USER, Some(user.id.clone()), None)
+        } else if let Some(role_id) = roles.get(name) {
+            (MENTION_TYPE_ROLE, None, Some(role_id.clone()))
+        } else if name == EVERYONE {
+            let is_allowed =
+                queries::user_has_permission(app_state.clone(), &author.id, "mention_everyone")
+                    .await?;
+            if !is_allowed {
+                continue;
+            }
+
+            (MENTION_TYPE_EVERYONE, None, None)
+        } else {
+            continue;
+        };
+
+        mentions.push(MessageMention {
+            id: Uuid::new_v4().to_string(),
+            message_id: message.id.clone(),
+            mention_type: mention_type.to_string(),
+            user_id,
+            role_id,
+            name: Some(name.clone()),
+        });
+    }
+
+    if !mentions.is_empty() {
+        queries::create_message_mentions(app_state, &mentions).await?;
+    }
+
+    Ok(mentions)
+}
+
+/// Pushes `receiveMention` to every online user the message mentions,
+/// except its author. Users mentioned in several ways are notified once
+/// with the most specific mention type. In direct conversations only the
+/// participants are notified, and users who blocked the author never are.
+pub async fn notify_mentions(
+    app_state: Arc<AppState>,
+    channel: &Channel,
+    message: &MessageResource,
+    mentions: &[MessageMention],
+) {
+    let mut recipients: HashMap<String, &'static str> = HashMap::new();
+
+    if mentions
+        .iter()
+        .any(|mention| mention.mention_type == MENTION_TYPE_EVERYONE)
+    {
+        for connection in app_state.connected_users.iter() {
+            recipients.insert(connection.key().clone(), MENTION_TYPE_EVERYONE);
+        }
+    }
+
+    let role_ids = mentions
+        .iter()
+        .filter_map(|mention| mention.role_id.clone())
+        .collect::<Vec<_>>();
+    match queries::get_role_member_ids(app_state.clone(), &role_ids).await {
+        Ok(member_ids) => {
+            for member_id in member_ids {
+                recipients.insert(member_id, MENTION_TYPE_ROLE);
+            }
+        }
+        Err(e) => warn!("Failed to load members of mentioned roles: {}", e),
+    }
+
+    for user_id in mentions
+        .iter()
+        .filter_map(|mention| mention.user_id.clone())
+    {
+        recipients.insert(user_id, MENTION_TYPE_USER);
+    }
+
+    recipients.remove(&message.userId);
+
+    match queries::get_blocker_ids(app_state.clone(), &message.userId).await {
+        Ok(blocker_ids) => recipients.retain(|user_id, _| !blocker_ids.contains(user_id)),
+        Err(e) => {
+            warn!("Failed to load blocks of user {}: {}", message.userId, e);
+            return;
+        }
+    }
+
+    if channel.is_direct() {
+        match queries::get_channel_members(app_state.clone(), &[channel.id.clone()]).await {
+            Ok(members) => recipients
+                .retain(|user_id, _| members.iter().any(|member| member.user_id == *user_id)),
+            Err(e) => {
+                warn!("Failed to load members of channel {}: {}", channel.id, e);
+                return;
+            }
+        }
+    }
+
+    for (user_id, mention_type) in recipients {
+        if let Some(connection) = app_state.connected_users.get(&user_id) {
+            connection
+                .socket
+                .emit(
+                    socket_publish_events::RECEIVE_MENTION,
+                    &json!({ "message": message, "mentionType": mention_type }),
+                )
+                .ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_at_punctuation() {
+        assert_eq!(
+            parse_mentions("thanks @alice, (@bob) and @carol. @dave! @erin-"),
+            vec!["alice", "bob", "carol", "dave", "erin"]
+        );
+        assert_eq!(parse_mentions("@first.last: hi"), vec!["first.last"]);
+        assert!(parse_mentions("@ @. @!").is_empty());
+    }
+
+    #[test]
+    fn ignores_email_addresses() {
+        assert!(parse_mentions("mail alice@example.com or bob.smith@example.org").is_empty());
+        assert_eq!(parse_mentions("ask @carol, not carol@host"), vec!["carol"]);
+    }
+
+    #[test]
+    fn drops_duplicates_case_insensitively() {
+        assert_eq!(
+            parse_mentions("@Alice @alice @ALICE @everyone @everyone"),
+            vec!["alice", "everyone"]
+        );
+    }
+
+    #[test]
+    fn matches_whole_names_only() {
+        assert_eq!(parse_mentions("@bob @bobby"), vec!["bob", "bobby"]);
+        assert_eq!(parse_mentions("@bobby"), vec!["bobby"]);
+    }
+
+    #[test]
+    fn caps_the_number_of_mentions() {
+        let content = (0..MAX_MENTIONS_PER_MESSAGE + 10)
+            .map(|i| format!("@user{}", i))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        assert_eq!(parse_mentions(&content).len(), MAX_MENTIONS_PER_MESSAGE);
+    }
+}
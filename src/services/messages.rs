@@ -0,0 +1,222 @@
+use crate::errors::AppError;
+use crate::models::{Message, User};
+use crate::queries;
+use crate::responses::{
+    EmbedResource, MentionResource, MessageResource, ReactionResource, ThreadSummaryResource,
+};
+use crate::services::blocks::apply_viewer_blocks;
+use crate::services::image_proxy::proxy_preview_images;
+use crate::services::link_preview::UrlPreview;
+use crate::AppState;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use tracing::warn;
+
+#[derive(Debug)]
+pub enum MessageReferenceError {
+    ReplyTargetNotFound,
+    ThreadNotFound,
+    NestedThread,
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for MessageReferenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageReferenceError::ReplyTargetNotFound => {
+                write!(f, "Quoted message not found in this channel")
+            }
+            MessageReferenceError::ThreadNotFound => {
+                write!(f, "Thread parent message not found in this channel")
+            }
+            MessageReferenceError::NestedThread => {
+                write!(f, "Thread replies cannot start a thread of their own")
+            }
+            MessageReferenceError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<MessageReferenceError> for AppError {
+    fn from(e: MessageReferenceError) -> Self {
+        match e {
+            MessageReferenceError::Database(e) => AppError::from(e),
+            e => AppError::Validation(e.to_string()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for MessageReferenceError {
+    fn from(e: sqlx::Error) -> Self {
+        MessageReferenceError::Database(e)
+    }
+}
+
+/// Checks that a quoted message and a thread parent exist in `channel_id`.
+/// Threads are one level deep, so a thread reply cannot be a parent.
+pub async fn validate_message_references(
+    app_state: Arc<AppState>,
+    channel_id: &str,
+    reply_to_message_id: Option<&str>,
+    thread_id: Option<&str>,
+) -> Result<(), MessageReferenceError> {
+    if let Some(reply_to_message_id) = reply_to_message_id {
+        queries::get_message_by_id(app_state.clone(), reply_to_message_id)
+            .await?
+            .filter(|message| message.channel_id == channel_id)
+            .ok_or(MessageReferenceError::ReplyTargetNotFound)?;
+    }
+
+    if let Some(thread_id) = thread_id {
+        let parent = queries::get_message_by_id(app_state.clone(), thread_id)
+            .await?
+            .filter(|message| message.channel_id == channel_id)
+            .ok_or(MessageReferenceError::ThreadNotFound)?;
+
+        if parent.thread_id.is_some() {
+            return Err(MessageReferenceError::NestedThread);
+        }
+    }
+
+    Ok(())
+}
+
+/// Current reply count and last reply time of the thread `message` was
+/// posted to, if any.
+pub async fn thread_summary_for(
+    app_state: Arc<AppState>,
+    message: &Message,
+) -> sqlx::Result<Option<ThreadSummaryResource>> {
+    let Some(thread_id) = &message.thread_id else {
+        return Ok(None);
+    };
+
+    Ok(queries::get_message_by_id(app_state, thread_id)
+        .await?
+        .map(|parent| parent.to_thread_summary_resource()))
+}
+
+/// Fills in `replyTo` for messages quoting another one.
+pub async fn attach_reply_references(
+    app_state: Arc<AppState>,
+    resources: &mut [MessageResource],
+) -> sqlx::Result<()> {
+    let reply_to_ids: Vec<String> = resources
+        .iter()
+        .filter_map(|resource| resource.replyToMessageId.clone())
+        .collect::<HashSet<String>>()
+        .into_iter()
+        .collect();
+
+    if reply_to_ids.is_empty() {
+        return Ok(());
+    }
+
+    let referenced: HashMap<String, Message> =
+        queries::get_messages_by_ids(app_state, &reply_to_ids)
+            .await?
+            .into_iter()
+            .map(|message| (message.id.clone(), message))
+            .collect();
+
+    for resource in resources.iter_mut() {
+        resource.replyTo = resource
+            .replyToMessageId
+            .as_ref()
+            .and_then(|id| referenced.get(id))
+            .map(|message| message.to_reference_resource());
+    }
+
+    Ok(())
+}
+
+/// Builds full message resources with authors, reactions, mentions, embeds
+/// and quoted messages. Reactions only carry `me` for a given viewer; broadcasts
+/// pass `None` and leave it to every client.
+pub async fn to_message_resources(
+    app_state: Arc<AppState>,
+    messages: Vec<Message>,
+    viewer_user_id: Option<&str>,
+) -> sqlx::Result<Vec<MessageResource>> {
+    // Fetch only the needed users
+    let unique_user_ids: Vec<String> = messages
+        .iter()
+        .map(|message| message.user_id.clone())
+        .collect::<HashSet<String>>()
+        .into_iter()
+        .collect();
+
+    let users = queries::get_users(app_state.clone(), Some(&unique_user_ids)).await?;
+    let user_map: HashMap<String, User> = users.into_iter().map(|u| (u.id.clone(), u)).collect();
+
+    // Aggregate reactions per message
+    let message_ids: Vec<String> = messages.iter().map(|message| message.id.clone()).collect();
+    // No user has an empty id, so `me` stays false without a viewer
+    let reaction_counts = queries::get_message_reaction_counts(
+        app_state.clone(),
+        &message_ids,
+        viewer_user_id.unwrap_or_default(),
+    )
+    .await?;
+
+    let mut reaction_map: HashMap<String, Vec<ReactionResource>> = HashMap::new();
+    for reaction_count in reaction_counts {
+        reaction_map
+            .entry(reaction_count.message_id.clone())
+            .or_default()
+            .push(reaction_count.to_resource());
+    }
+
+    let mut mention_map: HashMap<String, Vec<MentionResource>> = HashMap::new();
+    for mention in queries::get_message_mentions(app_state.clone(), &message_ids).await? {
+        mention_map
+            .entry(mention.message_id.clone())
+            .or_default()
+            .push(mention.to_resource());
+    }
+
+    let mut embed_map: HashMap<String, Vec<EmbedResource>> = HashMap::new();
+    for embed in queries::get_message_embeds(app_state.clone(), &message_ids).await? {
+        // Images are proxied when served rather than when stored, so embeds
+        // survive a change of the JWT secret
+        let mut resource = embed.to_resource();
+        if let Ok(mut preview) = serde_json::from_str::<UrlPreview>(&embed.preview) {
+            proxy_preview_images(&mut preview, &app_state.config.jwt_secret);
+            resource.preview = serde_json::json!(preview);
+        }
+
+        embed_map
+            .entry(embed.message_id.clone())
+            .or_default()
+            .push(resource);
+    }
+
+    let mut resources = messages
+        .into_iter()
+        .filter_map(|message| {
+            let Some(user) = user_map.get(&message.user_id) else {
+                warn!(
+                    "Author {} of message {} not found",
+                    message.user_id, message.id
+                );
+                return None;
+            };
+
+            let mut resource = message.to_resource(user.to_resource());
+            resource.reactions = reaction_map.remove(&message.id).unwrap_or_default();
+            resource.mentions = mention_map.remove(&message.id).unwrap_or_default();
+            resource.embeds = embed_map.remove(&message.id).unwrap_or_default();
+
+            Some(resource)
+        })
+        .collect::<Vec<_>>();
+
+    attach_reply_references(app_state.clone(), &mut resources).await?;
+
+    if let Some(viewer_user_id) = viewer_user_id {
+        apply_viewer_blocks(app_state, &mut resources, viewer_user_id).await?;
+    }
+
+    Ok(resources)
+}
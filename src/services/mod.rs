@@ -1 +1,22 @@
+pub mod audit_log;
+pub mod automod;
+pub mod blocks;
+pub mod direct_messages;
+pub mod image_proxy;
 pub mod link_preview;
+pub mod login_throttle;
+pub mod lru;
+pub mod mentions;
+pub mod messages;
+pub mod moderation;
+pub mod outgoing_webhooks;
+pub mod pokes;
+pub mod preview_cache;
+pub mod rate_limit;
+pub mod reactions;
+pub mod read_state;
+pub mod reports;
+pub mod safe_fetch;
+pub mod search;
+pub mod two_factor;
+pub mod unfurl;
@@ -0,0 +1,130 @@
+use crate::errors::AppError;
+use crate::models::{User, UserBan, UserTimeout};
+use crate::socket::connection::user_room;
+use crate::socket::events::socket_publish_events;
+use crate::AppState;
+use serde_json::json;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Longest timeout moderators can hand out.
+pub const MAX_TIMEOUT: chrono::Duration = chrono::Duration::days(28);
+pub const MAX_REASON_LENGTH: usize = 512;
+
+/// Ack error and REST message for users who are timed out.
+pub fn timed_out_message(timeout: &UserTimeout) -> String {
+    format!(
+        "You are timed out until {}",
+        timeout.expires_at.to_rfc3339()
+    )
+}
+
+/// Timed out users may mute their microphone but not unmute it.
+pub fn check_microphone_change(
+    timeout: Option<&UserTimeout>,
+    is_microphone_muted: bool,
+) -> Result<(), AppError> {
+    match timeout {
+        Some(timeout) if !is_microphone_muted => Err(AppError::TimedOut {
+            message: timed_out_message(timeout),
+            expires_at: timeout.expires_at,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Mutes the microphone of a connected user and tells everyone about it.
+pub async fn force_mute_microphone(app_state: Arc<AppState>, user_id: &str) {
+    match app_state.connected_users.get_mut(user_id) {
+        Some(mut connection) if !connection.is_mic_muted => connection.is_mic_muted = true,
+        _ => return,
+    }
+
+    if let Err(e) = app_state
+        .io
+        .emit(
+            socket_publish_events::RECEIVE_USER_MICROPHONE_STATUS_CHANGED,
+            &json!({ "userId": user_id, "isMicrophoneMuted": true }),
+        )
+        .await
+    {
+        warn!("Failed to announce muting of user {}: {}", user_id, e);
+    }
+}
+
+/// Tells every connection of the banned user about the ban, then drops
+/// them. Reconnecting is refused while the ban is active.
+pub async fn announce_ban(app_state: Arc<AppState>, ban: &UserBan, moderator: &User) {
+    let room = user_room(&ban.user_id);
+
+    if let Err(e) = app_state
+        .io
+        .to(room.clone())
+        .emit(
+            socket_publish_events::RECEIVE_BAN,
+            &json!({ "ban": ban.to_resource(), "user": moderator.to_resource() }),
+        )
+        .await
+    {
+        warn!("Failed to notify user {} of ban: {}", ban.user_id, e);
+    }
+
+    if let Err(e) = app_state.io.to(room).disconnect().await {
+        warn!("Failed to disconnect banned user {}: {}", ban.user_id, e);
+    }
+
+    app_state.connected_users.remove(&ban.user_id);
+}
+
+/// Tells the user about the timeout and mutes their microphone, since
+/// timeouts also block speaking.
+pub async fn announce_timeout(app_state: Arc<AppState>, timeout: &UserTimeout, moderator: &User) {
+    force_mute_microphone(app_state.clone(), &timeout.user_id).await;
+
+    if let Err(e) = app_state
+        .io
+        .to(user_room(&timeout.user_id))
+        .emit(
+            socket_publish_events::RECEIVE_TIMEOUT,
+            &json!({ "timeout": timeout.to_resource(), "user": moderator.to_resource() }),
+        )
+        .await
+    {
+        warn!(
+            "Failed to notify user {} of timeout: {}",
+            timeout.user_id, e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timeout() -> UserTimeout {
+        let now = chrono::Utc::now();
+
+        UserTimeout {
+            id: "timeout-1".to_string(),
+            user_id: "user-1".to_string(),
+            reason: None,
+            created_by_user_id: None,
+            expires_at: now + chrono::Duration::minutes(10),
+            lifted_at: None,
+            lifted_by_user_id: None,
+            created_at: now,
+        }
+    }
+
+    #[test]
+    fn timed_out_users_cannot_unmute() {
+        let timeout = timeout();
+
+        assert!(matches!(
+            check_microphone_change(Some(&timeout), false),
+            Err(AppError::TimedOut { expires_at, .. }) if expires_at == timeout.expires_at
+        ));
+        assert!(check_microphone_change(Some(&timeout), true).is_ok());
+        assert!(check_microphone_change(None, false).is_ok());
+    }
+}
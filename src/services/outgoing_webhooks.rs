@@ -0,0 +1,345 @@
+use crate::{queries, AppState};
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Client;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+use uuid::Uuid;
+
+pub mod event_types {
+    pub const MESSAGE_CREATED: &str = "message.created";
+    pub const USER_JOINED: &str = "user.joined";
+    pub const USER_KICKED: &str = "user.kicked";
+    pub const CHANNEL_CREATED: &str = "channel.created";
+
+    pub const ALL: [&str; 4] = [MESSAGE_CREATED, USER_JOINED, USER_KICKED, CHANNEL_CREATED];
+}
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Webhook-Timestamp";
+pub const EVENT_HEADER: &str = "X-Webhook-Event";
+pub const DELIVERY_HEADER: &str = "X-Webhook-Delivery";
+
+const MAX_ATTEMPTS: u32 = 8;
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(6 * 60 * 60);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const BATCH_SIZE: i64 = 20;
+
+/// Signs `"{timestamp}.{body}"` with HMAC-SHA256 and returns the header
+/// value, `sha256=<hex digest>`. Receivers recompute it with their secret.
+pub fn sign_payload(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+
+    let digest = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+
+    format!("sha256={}", digest)
+}
+
+/// Delay before the next attempt after `attempts` failed ones, or `None`
+/// once the delivery should be given up.
+pub fn next_attempt_delay(attempts: u32) -> Option<Duration> {
+    if attempts >= MAX_ATTEMPTS {
+        return None;
+    }
+
+    let factor = 1u32 << attempts.saturating_sub(1).min(16);
+    Some((BASE_RETRY_DELAY * factor).min(MAX_RETRY_DELAY))
+}
+
+/// Outcome of a single HTTP delivery attempt.
+#[derive(Debug)]
+pub struct DeliveryOutcome {
+    pub response_status: Option<u16>,
+    pub error: Option<String>,
+}
+
+impl DeliveryOutcome {
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// POSTs a signed payload to `url`. Any non-2xx response counts as failure.
+pub async fn deliver(
+    client: &Client,
+    url: &str,
+    secret: &str,
+    delivery_id: &str,
+    event_type: &str,
+    body: &str,
+) -> DeliveryOutcome {
+    let timestamp = chrono::Utc::now().timestamp();
+
+    let response = client
+        .post(url)
+        .timeout(DELIVERY_TIMEOUT)
+        .header(CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, event_type)
+        .header(DELIVERY_HEADER, delivery_id)
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(SIGNATURE_HEADER, sign_payload(secret, timestamp, body))
+        .body(body.to_string())
+        .send()
+        .await;
+
+    match response {
+        Ok(response) if response.status().is_success() => DeliveryOutcome {
+            response_status: Some(response.status().as_u16()),
+            error: None,
+        },
+        Ok(response) => DeliveryOutcome {
+            response_status: Some(response.status().as_u16()),
+            error: Some(format!("Unexpected response: HTTP {}", response.status())),
+        },
+        Err(e) => DeliveryOutcome {
+            response_status: None,
+            error: Some(e.to_string()),
+        },
+    }
+}
+
+/// Queues a delivery of `event_type` to every subscription listening for
+/// it. Errors are logged; dispatching must never fail the caller.
+pub async fn dispatch_event(app_state: Arc<AppState>, event_type: &'static str, data: Value) {
+    let subscriptions =
+        match queries::get_webhook_subscriptions_for_event(app_state.clone(), event_type).await {
+            Ok(subscriptions) => subscriptions,
+            Err(e) => {
+                warn!("Failed to load webhook subscriptions: {}", e);
+                return;
+            }
+        };
+
+    for subscription in subscriptions {
+        let delivery_id = Uuid::new_v4().to_string();
+        let payload = json!({
+            "id": delivery_id,
+            "event": event_type,
+            "createdAt": chrono::Utc::now(),
+            "data": data,
+        });
+
+        if let Err(e) = queries::create_webhook_delivery(
+            app_state.clone(),
+            &delivery_id,
+            &subscription.id,
+            event_type,
+            &payload.to_string(),
+        )
+        .await
+        {
+            warn!(
+                "Failed to queue {} delivery for subscription {}: {}",
+                event_type, subscription.id, e
+            );
+        }
+    }
+}
+
+/// Fire-and-forget variant of `dispatch_event` for request handlers.
+pub fn spawn_dispatch_event(app_state: Arc<AppState>, event_type: &'static str, data: Value) {
+    tokio::spawn(dispatch_event(app_state, event_type, data));
+}
+
+async fn process_due_deliveries(app_state: Arc<AppState>, client: &Client) {
+    let deliveries = match queries::get_due_webhook_deliveries(app_state.clone(), BATCH_SIZE).await
+    {
+        Ok(deliveries) => deliveries,
+        Err(e) => {
+            warn!("Failed to load due webhook deliveries: {}", e);
+            return;
+        }
+    };
+
+    for delivery in deliveries {
+        let outcome = deliver(
+            client,
+            &delivery.url,
+            &delivery.secret,
+            &delivery.id,
+            &delivery.event_type,
+            &delivery.payload,
+        )
+        .await;
+
+        let attempts = delivery.attempts.max(0) as u32 + 1;
+        let (status, next_attempt_at) = if outcome.is_success() {
+            ("succeeded", None)
+        } else {
+            match next_attempt_delay(attempts) {
+                Some(delay) => (
+                    "pending",
+                    Some(chrono::Utc::now() + chrono::Duration::seconds(delay.as_secs() as i64)),
+                ),
+                None => ("failed", None),
+            }
+        };
+
+        if let Err(e) = queries::record_webhook_delivery_attempt(
+            app_state.clone(),
+            &delivery.id,
+            status,
+            attempts as i32,
+            next_attempt_at,
+            outcome.response_status.map(i32::from),
+            outcome.error.as_deref(),
+        )
+        .await
+        {
+            warn!("Failed to record webhook delivery {}: {}", delivery.id, e);
+        }
+    }
+}
+
+/// Background worker draining the persistent delivery queue. Deliveries
+/// survive restarts since their state lives in `webhook_deliveries`.
+pub async fn run_delivery_worker(app_state: Arc<AppState>) {
+    let client = Client::builder()
+        .user_agent("rsblubber-webhooks/1.0")
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("failed to build webhook HTTP client");
+
+    let mut interval = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        process_due_deliveries(app_state.clone(), &client).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    struct CapturedRequest {
+        headers: HashMap<String, String>,
+        body: String,
+    }
+
+    /// Minimal local HTTP stand-in: accepts one request, answers with
+    /// `status` and hands the captured request back to the test.
+    async fn spawn_stand_in(status: u16) -> (String, tokio::task::JoinHandle<CapturedRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = Vec::new();
+            let mut chunk = [0u8; 1024];
+
+            let (head, body_start, content_length) = loop {
+                let read = stream.read(&mut chunk).await.unwrap();
+                buffer.extend_from_slice(&chunk[..read]);
+
+                if let Some(pos) = buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+                    let head = String::from_utf8_lossy(&buffer[..pos]).to_string();
+                    let content_length = head
+                        .lines()
+                        .filter_map(|line| line.split_once(':'))
+                        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                        .map(|(_, value)| value.trim().parse::<usize>().unwrap())
+                        .unwrap_or(0);
+                    break (head, pos + 4, content_length);
+                }
+            };
+
+            while buffer.len() < body_start + content_length {
+                let read = stream.read(&mut chunk).await.unwrap();
+                buffer.extend_from_slice(&chunk[..read]);
+            }
+
+            let headers = head
+                .lines()
+                .skip(1)
+                .filter_map(|line| line.split_once(':'))
+                .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+                .collect();
+            let body = String::from_utf8_lossy(&buffer[body_start..body_start + content_length])
+                .to_string();
+
+            let response = format!(
+                "HTTP/1.1 {} Stand-In\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                status
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+
+            CapturedRequest { headers, body }
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn signs_payload_with_hmac_sha256() {
+        assert_eq!(
+            sign_payload("secret", 1700000000, r#"{"event":"ping"}"#),
+            "sha256=4d39bd2442f073b6bc62e95d0297ce25475582a17389ab860abdc778fe1d9f77"
+        );
+    }
+
+    #[test]
+    fn backs_off_exponentially_and_gives_up() {
+        assert_eq!(next_attempt_delay(1), Some(Duration::from_secs(30)));
+        assert_eq!(next_attempt_delay(2), Some(Duration::from_secs(60)));
+        assert_eq!(next_attempt_delay(3), Some(Duration::from_secs(120)));
+        assert_eq!(next_attempt_delay(7), Some(Duration::from_secs(30 * 64)));
+        assert_eq!(next_attempt_delay(MAX_ATTEMPTS), None);
+    }
+
+    #[tokio::test]
+    async fn delivers_signed_payload_to_stand_in() {
+        let (url, stand_in) = spawn_stand_in(200).await;
+        let body = r#"{"event":"message.created"}"#;
+
+        let outcome = deliver(
+            &Client::new(),
+            &url,
+            "topsecret",
+            "delivery-1",
+            event_types::MESSAGE_CREATED,
+            body,
+        )
+        .await;
+        let request = stand_in.await.unwrap();
+
+        assert!(outcome.is_success());
+        assert_eq!(outcome.response_status, Some(200));
+        assert_eq!(request.body, body);
+        assert_eq!(request.headers["x-webhook-event"], "message.created");
+        assert_eq!(request.headers["x-webhook-delivery"], "delivery-1");
+
+        let timestamp = request.headers["x-webhook-timestamp"]
+            .parse::<i64>()
+            .unwrap();
+        assert_eq!(
+            request.headers["x-webhook-signature"],
+            sign_payload("topsecret", timestamp, body)
+        );
+    }
+
+    #[tokio::test]
+    async fn treats_error_status_as_failed_delivery() {
+        let (url, stand_in) = spawn_stand_in(500).await;
+
+        let outcome = deliver(&Client::new(), &url, "s", "d", "user.joined", "{}").await;
+        stand_in.await.unwrap();
+
+        assert!(!outcome.is_success());
+        assert_eq!(outcome.response_status, Some(500));
+    }
+}
@@ -0,0 +1,188 @@
+use crate::errors::AppError;
+use crate::models::User;
+use crate::queries;
+use crate::services::blocks::{hide_custom_status, is_blocked_by_any};
+use crate::socket::events::socket_publish_events;
+use crate::AppState;
+use serde_json::json;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
+
+pub const MAX_POKE_MESSAGE_LENGTH: usize = 256;
+
+/// Pokes a single user may send per `SENDER_PERIOD`.
+const SENDER_CAPACITY: u32 = 5;
+const SENDER_PERIOD: Duration = Duration::from_secs(60);
+/// Pokes a single user may receive per `RECIPIENT_PERIOD`, from anyone.
+const RECIPIENT_CAPACITY: u32 = 10;
+const RECIPIENT_PERIOD: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub enum PokeError {
+    MissingRecipient,
+    MessageTooLong,
+    RecipientNotFound,
+    Blocked,
+    DoNotDisturb,
+    NotConnected,
+    SenderRateLimited(Duration),
+    RecipientRateLimited(Duration),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for PokeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PokeError::MissingRecipient => write!(f, "No userId provided in payload"),
+            PokeError::MessageTooLong => write!(
+                f,
+                "Poke message must be at most {} characters",
+                MAX_POKE_MESSAGE_LENGTH
+            ),
+            PokeError::RecipientNotFound => write!(f, "User not found"),
+            PokeError::Blocked => write!(f, "You cannot poke this user"),
+            PokeError::DoNotDisturb => write!(f, "User does not want to be disturbed"),
+            PokeError::NotConnected => write!(f, "User is currently not connected"),
+            PokeError::SenderRateLimited(_) => {
+                write!(f, "You are poking too often, please try again later")
+            }
+            PokeError::RecipientRateLimited(_) => {
+                write!(f, "User has been poked too often, please try again later")
+            }
+            // Details stay in the server log
+            PokeError::Database(_) => write!(f, "Internal error"),
+        }
+    }
+}
+
+impl From<PokeError> for AppError {
+    fn from(e: PokeError) -> Self {
+        match e {
+            PokeError::MissingRecipient => AppError::BadRequest(e.to_string()),
+            PokeError::MessageTooLong => AppError::Validation(e.to_string()),
+            PokeError::RecipientNotFound => AppError::NotFound(e.to_string()),
+            PokeError::Blocked => AppError::Blocked(e.to_string()),
+            PokeError::DoNotDisturb => AppError::DoNotDisturb(e.to_string()),
+            PokeError::NotConnected => AppError::NotConnected(e.to_string()),
+            PokeError::SenderRateLimited(retry_after) => AppError::RateLimited {
+                message: e.to_string(),
+                retry_after,
+            },
+            PokeError::RecipientRateLimited(retry_after) => AppError::RecipientRateLimited {
+                message: e.to_string(),
+                retry_after,
+            },
+            PokeError::Database(e) => AppError::from(e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for PokeError {
+    fn from(e: sqlx::Error) -> Self {
+        PokeError::Database(e)
+    }
+}
+
+/// Delivers a poke from `sender` to the connected user `recipient_id`.
+/// The timestamp is always taken on the server.
+pub async fn send_poke(
+    app_state: Arc<AppState>,
+    sender: &User,
+    recipient_id: &str,
+    message: &str,
+) -> Result<(), PokeError> {
+    if recipient_id.is_empty() {
+        return Err(PokeError::MissingRecipient);
+    }
+    if message.chars().count() > MAX_POKE_MESSAGE_LENGTH {
+        return Err(PokeError::MessageTooLong);
+    }
+
+    app_state
+        .poke_rate_limiter
+        .try_acquire(
+            &format!("sender:{}", sender.id),
+            SENDER_CAPACITY,
+            SENDER_PERIOD,
+        )
+        .map_err(PokeError::SenderRateLimited)?;
+
+    // The connection holds a snapshot from connect time, so the settings
+    // are read from the database
+    let recipient = queries::get_users(app_state.clone(), Some(&[recipient_id.to_string()][..]))
+        .await?
+        .pop()
+        .ok_or(PokeError::RecipientNotFound)?;
+
+    if is_blocked_by_any(app_state.clone(), &sender.id, &[recipient.id.clone()]).await? {
+        return Err(PokeError::Blocked);
+    }
+    if recipient.do_not_disturb == 1 {
+        return Err(PokeError::DoNotDisturb);
+    }
+
+    let mut sender_resource = sender.to_resource();
+    let recipient_blocker_ids = queries::get_blocker_ids(app_state.clone(), &recipient.id).await?;
+    hide_custom_status(&mut sender_resource, &recipient_blocker_ids);
+
+    let Some(connection) = app_state.connected_users.get(&recipient.id) else {
+        return Err(PokeError::NotConnected);
+    };
+
+    app_state
+        .poke_rate_limiter
+        .try_acquire(
+            &format!("recipient:{}", recipient.id),
+            RECIPIENT_CAPACITY,
+            RECIPIENT_PERIOD,
+        )
+        .map_err(PokeError::RecipientRateLimited)?;
+
+    let poke_payload = json!({
+        "user": sender_resource,
+        "message": message,
+        "createdAt": chrono::Utc::now(),
+    });
+
+    if let Err(e) = connection
+        .socket
+        .emit(socket_publish_events::RECEIVE_POKE, &poke_payload)
+    {
+        warn!("Failed to deliver poke to user {}: {}", recipient.id, e);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn every_error_keeps_its_own_code() {
+        let errors = vec![
+            PokeError::MissingRecipient,
+            PokeError::MessageTooLong,
+            PokeError::RecipientNotFound,
+            PokeError::Blocked,
+            PokeError::DoNotDisturb,
+            PokeError::NotConnected,
+            PokeError::SenderRateLimited(Duration::from_secs(5)),
+            PokeError::RecipientRateLimited(Duration::from_secs(5)),
+            PokeError::Database(sqlx::Error::PoolTimedOut),
+        ];
+        let count = errors.len();
+
+        let codes: HashSet<&str> = errors
+            .into_iter()
+            .map(|e| AppError::from(e).code().as_str())
+            .collect();
+
+        assert_eq!(codes.len(), count);
+        assert!(codes.contains("do_not_disturb"));
+        assert!(codes.contains("recipient_rate_limited"));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::queries;
+use crate::services::link_preview::{get_url_preview, UrlPreview};
+use crate::services::lru::LruCache;
+use crate::services::safe_fetch::check_url;
+use crate::AppState;
+use dashmap::DashMap;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use tracing::warn;
+use url::Url;
+
+const SUCCESS_TTL: chrono::Duration = chrono::Duration::hours(24);
+/// Short enough that a page which was down is retried soon.
+const FAILURE_TTL: chrono::Duration = chrono::Duration::minutes(10);
+/// Previews kept in memory in front of the `link_previews` table.
+const MEMORY_CAPACITY: usize = 1000;
+
+/// Query parameters that only track where a link was shared.
+const TRACKING_PARAMS: [&str; 4] = ["fbclid", "gclid", "mc_eid", "igshid"];
+
+#[derive(Debug, Clone)]
+pub struct PreviewFailure {
+    pub message: String,
+    /// The URL itself was refused rather than the remote failing.
+    pub is_rejected_url: bool,
+}
+
+pub type PreviewResult = Result<Arc<UrlPreview>, PreviewFailure>;
+
+#[derive(Debug, Clone)]
+struct CacheEntry {
+    result: PreviewResult,
+    expires_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// In-memory least recently used previews plus the fetches in progress.
+#[derive(Debug)]
+pub struct PreviewCache {
+    entries: LruCache<CacheEntry>,
+    in_flight: DashMap<String, Arc<OnceCell<PreviewResult>>>,
+}
+
+impl Default for PreviewCache {
+    fn default() -> Self {
+        Self {
+            entries: LruCache::new(MEMORY_CAPACITY),
+            in_flight: DashMap::new(),
+        }
+    }
+}
+
+impl PreviewCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn get(&self, url: &str) -> Option<PreviewResult> {
+        let entry = self.entries.get(url)?;
+        if entry.expires_at <= chrono::Utc::now() {
+            self.entries.remove(url);
+            return None;
+        }
+
+        Some(entry.result)
+    }
+
+    fn insert(&self, url: &str, entry: CacheEntry) {
+        self.entries.insert(url, entry);
+    }
+
+    /// Forgets one URL, or every URL if `url` is `None`.
+    fn remove(&self, url: Option<&str>) {
+        match url {
+            Some(url) => self.entries.remove(url),
+            None => self.entries.clear(),
+        }
+    }
+
+    /// Drops expired entries.
+    pub fn prune(&self) {
+        let now = chrono::Utc::now();
+        self.entries.retain(|entry| entry.expires_at > now);
+    }
+}
+
+/// The cache key of `input`: fragments and tracking parameters are dropped,
+/// and the host and default port are normalised by the parser.
+pub fn normalize_url(input: &str) -> Option<Url> {
+    let mut url = Url::parse(input.trim()).ok()?;
+    url.set_fragment(None);
+
+    let is_tracking = |key: &str| key.starts_with("utm_") || TRACKING_PARAMS.contains(&key);
+    if url.query_pairs().any(|(key, _)| is_tracking(&key)) {
+        let kept: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(key, _)| !is_tracking(key))
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+
+        if kept.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(kept);
+        }
+    }
+
+    Some(url)
+}
+
+pub fn url_hash(url: &str) -> String {
+    format!("{:x}", Sha256::digest(url.as_bytes()))
+}
+
+/// The preview of `input_url`, served from memory or the `link_previews`
+/// table when possible. Concurrent requests for the same URL share a single
+/// fetch, and failures are cached for a shorter time than previews.
+pub async fn get_cached_preview(app_state: Arc<AppState>, input_url: &str) -> PreviewResult {
+    let Some(url) = normalize_url(input_url) else {
+        return Err(PreviewFailure {
+            message: "Malformed url".to_string(),
+            is_rejected_url: true,
+        });
+    };
+
+    // Refused URLs are cheap to detect and not worth caching
+    if let Err(e) = check_url(&url) {
+        return Err(PreviewFailure {
+            message: e.to_string(),
+            is_rejected_url: true,
+        });
+    }
+
+    let url = url.to_string();
+    let cache = &app_state.link_preview_cache;
+    if let Some(result) = cache.get(&url) {
+        return result;
+    }
+
+    let cell = cache.in_flight.entry(url.clone()).or_default().clone();
+    let result = cell
+        .get_or_init(|| load_preview(app_state.clone(), &url))
+        .await
+        .clone();
+    cache
+        .in_flight
+        .remove_if(&url, |_, current| Arc::ptr_eq(current, &cell));
+
+    result
+}
+
+async fn load_preview(app_state: Arc<AppState>, url: &str) -> PreviewResult {
+    let cache = &app_state.link_preview_cache;
+    if let Some(result) = cache.get(url) {
+        return result;
+    }
+
+    let hash = url_hash(url);
+    match queries::get_link_preview(app_state.clone(), &hash).await {
+        Ok(Some(stored)) => {
+            let result = match (&stored.preview, &stored.error) {
+                (Some(preview), _) => {
+                    serde_json::from_str(preview)
+                        .map(Arc::new)
+                        .map_err(|e| PreviewFailure {
+                            message: e.to_string(),
+                            is_rejected_url: false,
+                        })
+                }
+                (None, error) => Err(PreviewFailure {
+                    message: error.clone().unwrap_or_default(),
+                    is_rejected_url: stored.is_rejected_url != 0,
+                }),
+            };
+
+            // A preview stored by an older version may no longer parse
+            if result.is_ok() || stored.error.is_some() {
+                cache.insert(
+                    url,
+                    CacheEntry {
+                        result: result.clone(),
+                        expires_at: stored.expires_at,
+                    },
+                );
+                return result;
+            }
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Failed to load cached preview of {}: {}", url, e),
+    }
+
+    let result = get_url_preview(url)
+        .await
+        .map(Arc::new)
+        .map_err(|e| PreviewFailure {
+            message: e.to_string(),
+            is_rejected_url: e.is_rejected_url(),
+        });
+
+    let (preview, error, expires_at) = match &result {
+        Ok(preview) => (
+            serde_json::to_string(preview.as_ref()).ok(),
+            None,
+            chrono::Utc::now() + SUCCESS_TTL,
+        ),
+        Err(failure) => (
+            None,
+            Some(failure.message.clone()),
+            chrono::Utc::now() + FAILURE_TTL,
+        ),
+    };
+
+    if let Err(e) = queries::upsert_link_preview(
+        app_state.clone(),
+        &hash,
+        url,
+        preview.as_deref(),
+        error.as_deref(),
+        result
+            .as_ref()
+            .is_err_and(|failure| failure.is_rejected_url),
+        expires_at,
+    )
+    .await
+    {
+        warn!("Failed to store preview of {}: {}", url, e);
+    }
+
+    cache.insert(
+        url,
+        CacheEntry {
+            result: result.clone(),
+            expires_at,
+        },
+    );
+
+    result
+}
+
+/// Drops the cached preview of `url`, or every cached preview if `url` is
+/// `None`, so the next request fetches it again. Returns how many stored
+/// previews were deleted.
+pub async fn purge_previews(app_state: Arc<AppState>, url: Option<&Url>) -> sqlx::Result<u64> {
+    let url = url.map(|url| url.to_string());
+    let hash = url.as_deref().map(url_hash);
+
+    app_state.link_preview_cache.remove(url.as_deref());
+
+    queries::delete_link_previews(app_state, hash.as_deref()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_url_drops_fragments_and_tracking_parameters() {
+        let normalize = |url: &str| normalize_url(url).unwrap().to_string();
+
+        assert_eq!(
+            normalize("HTTPS://Example.COM:443/a?utm_source=x&id=1#top"),
+            "https://example.com/a?id=1"
+        );
+        assert_eq!(
+            normalize("https://example.com/a?fbclid=abc"),
+            "https://example.com/a"
+        );
+        // Untouched queries keep their original encoding
+        assert_eq!(
+            normalize("https://example.com/search?q=a+b"),
+            "https://example.com/search?q=a+b"
+        );
+        assert!(normalize_url("not a url").is_none());
+    }
+}
@@ -0,0 +1,105 @@
+use dashmap::DashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Keyed token bucket rate limiter. Each key gets a bucket holding up to
+/// `capacity` tokens that refills completely over `period`.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: DashMap<String, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes one token from the bucket for `key`. Returns how long to wait
+    /// until a token becomes available if the bucket is empty.
+    pub fn try_acquire(&self, key: &str, capacity: u32, period: Duration) -> Result<(), Duration> {
+        let now = Instant::now();
+        let capacity = capacity.max(1) as f64;
+        let refill_per_sec = capacity / period.as_secs_f64().max(f64::EPSILON);
+
+        let mut bucket = self.buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            last_refill: now,
+        });
+
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill_per_sec).min(capacity);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        Err(Duration::from_secs_f64(
+            (1.0 - bucket.tokens) / refill_per_sec,
+        ))
+    }
+
+    /// Drops buckets that have not been touched for `idle`; an idle bucket
+    /// would have refilled completely anyway.
+    pub fn prune(&self, idle: Duration) {
+        let now = Instant::now();
+
+        self.buckets
+            .retain(|_, bucket| now.duration_since(bucket.last_refill) < idle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_bursts_up_to_capacity() {
+        let limiter = RateLimiter::new();
+        let period = Duration::from_secs(60);
+
+        for _ in 0..3 {
+            assert!(limiter.try_acquire("a", 3, period).is_ok());
+        }
+
+        let retry_after = limiter.try_acquire("a", 3, period).unwrap_err();
+        assert!(retry_after > Duration::from_secs(19));
+        assert!(retry_after <= Duration::from_secs(20));
+
+        // Buckets are independent per key
+        assert!(limiter.try_acquire("b", 3, period).is_ok());
+    }
+
+    #[test]
+    fn refills_over_the_period() {
+        let limiter = RateLimiter::new();
+        let period = Duration::from_millis(50);
+
+        assert!(limiter.try_acquire("a", 1, period).is_ok());
+        assert!(limiter.try_acquire("a", 1, period).is_err());
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(limiter.try_acquire("a", 1, period).is_ok());
+    }
+
+    #[test]
+    fn prune_drops_idle_buckets() {
+        let limiter = RateLimiter::new();
+        limiter
+            .try_acquire("a", 1, Duration::from_secs(60))
+            .unwrap();
+
+        limiter.prune(Duration::from_secs(60));
+        assert_eq!(limiter.buckets.len(), 1);
+
+        limiter.prune(Duration::ZERO);
+        assert!(limiter.buckets.is_empty());
+        assert!(limiter.try_acquire("a", 1, Duration::from_secs(60)).is_ok());
+    }
+}
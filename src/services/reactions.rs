@@ -0,0 +1,204 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Longest accepted reaction in characters. Long enough for ZWJ sequences
+/// such as family or flag emoji.
+const MAX_EMOJI_CHARS: usize = 16;
+const MAX_CUSTOM_EMOJI_NAME_LENGTH: usize = 32;
+
+fn is_custom_emoji(emoji: &str) -> bool {
+    let Some(name) = emoji
+        .strip_prefix(':')
+        .and_then(|emoji| emoji.strip_suffix(':'))
+    else {
+        return false;
+    };
+
+    !name.is_empty()
+        && name.len() <= MAX_CUSTOM_EMOJI_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+const VARIATION_SELECTOR: char = '\u{FE0F}';
+const COMBINING_KEYCAP: char = '\u{20E3}';
+const CANCEL_TAG: char = '\u{E007F}';
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c, '\u{1F1E6}'..='\u{1F1FF}')
+}
+
+fn is_skin_tone(c: char) -> bool {
+    matches!(c, '\u{1F3FB}'..='\u{1F3FF}')
+}
+
+fn is_tag(c: char) -> bool {
+    matches!(c, '\u{E0020}'..='\u{E007E}')
+}
+
+/// Characters that render as an emoji on their own, roughly Unicode's
+/// Extended_Pictographic property.
+fn is_pictographic(c: char) -> bool {
+    if is_regional_indicator(c) || is_skin_tone(c) {
+        return false;
+    }
+
+    matches!(
+        c,
+        '\u{00A9}'
+            | '\u{00AE}'
+            | '\u{203C}'
+            | '\u{2049}'
+            | '\u{2122}'
+            | '\u{2139}'
+            | '\u{2194}'..='\u{2199}'
+            | '\u{21A9}'..='\u{21AA}'
+            | '\u{231A}'..='\u{231B}'
+            | '\u{2328}'
+            | '\u{23CF}'
+            | '\u{23E9}'..='\u{23F3}'
+            | '\u{23F8}'..='\u{23FA}'
+            | '\u{24C2}'
+            | '\u{25AA}'..='\u{25AB}'
+            | '\u{25B6}'
+            | '\u{25C0}'
+            | '\u{25FB}'..='\u{25FE}'
+            | '\u{2600}'..='\u{27BF}'
+            | '\u{2934}'..='\u{2935}'
+            | '\u{2B05}'..='\u{2B07}'
+            | '\u{2B1B}'..='\u{2B1C}'
+            | '\u{2B50}'
+            | '\u{2B55}'
+            | '\u{3030}'
+            | '\u{303D}'
+            | '\u{3297}'
+            | '\u{3299}'
+            | '\u{1F000}'..='\u{1FAFF}'
+    )
+}
+
+/// Consumes a single emoji from `chars`: a flag, a keycap, or a pictograph
+/// with an optional variation selector, skin tone and tag sequence.
+fn take_emoji(chars: &mut Peekable<Chars>) -> bool {
+    let Some(base) = chars.next() else {
+        return false;
+    };
+
+    if is_regional_indicator(base) {
+        return chars.next_if(|c| is_regional_indicator(*c)).is_some();
+    }
+
+    // ASCII is only allowed as the base of keycap sequences like "1️⃣"
+    if base.is_ascii_digit() || base == '#' || base == '*' {
+        chars.next_if_eq(&VARIATION_SELECTOR);
+        return chars.next_if_eq(&COMBINING_KEYCAP).is_some();
+    }
+
+    if !is_pictographic(base) {
+        return false;
+    }
+
+    chars.next_if_eq(&VARIATION_SELECTOR);
+    chars.next_if(|c| is_skin_tone(*c));
+
+    // Subdivision flags such as England end their tags with a cancel tag
+    if chars.next_if(|c| is_tag(*c)).is_some() {
+        while chars.next_if(|c| is_tag(*c)).is_some() {}
+        return chars.next_if_eq(&CANCEL_TAG).is_some();
+    }
+
+    true
+}
+
+/// Whether `emoji` is exactly one emoji, possibly several joined by ZWJ.
+fn is_unicode_emoji(emoji: &str) -> bool {
+    if emoji.chars().count() > MAX_EMOJI_CHARS {
+        return false;
+    }
+
+    let mut chars = emoji.chars().peekable();
+
+    loop {
+        if !take_emoji(&mut chars) {
+            return false;
+        }
+
+        match chars.next() {
+            None => return true,
+            Some(ZERO_WIDTH_JOINER) => continue,
+            Some(_) => return false,
+        }
+    }
+}
+
+/// Validates a reaction, which is either a unicode emoji or a custom emoji
+/// shortcode such as `:party-parrot:`. Returns the value to store.
+pub fn normalize_emoji(emoji: &str) -> Option<String> {
+    let emoji = emoji.trim();
+
+    if is_custom_emoji(emoji) {
+        return Some(emoji.to_lowercase());
+    }
+
+    if is_unicode_emoji(emoji) {
+        return Some(emoji.to_string());
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_unicode_emoji_sequences() {
+        for emoji in [
+            "😀",
+            "❤️",
+            "👍🏽",
+            "👨‍👩‍👧‍👦",
+            "🧑🏿‍🚀",
+            "🇳🇱",
+            "1️⃣",
+            "#⃣",
+            "🏴\u{E0067}\u{E0062}\u{E0065}\u{E006E}\u{E0067}\u{E007F}",
+            "©️",
+        ] {
+            assert_eq!(normalize_emoji(emoji).as_deref(), Some(emoji), "{}", emoji);
+        }
+    }
+
+    #[test]
+    fn rejects_text_that_is_not_a_single_emoji() {
+        for text in [
+            "",
+            "a",
+            "1",
+            "é",
+            "中文",
+            "😀😀",
+            "😀 ",
+            "😀a",
+            "🇳",
+            "👍\u{200D}",
+            "\u{200D}👍",
+            "🏽",
+            "🏴\u{E0067}\u{E0062}",
+        ] {
+            assert!(!is_unicode_emoji(text), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn normalizes_custom_emoji_shortcodes() {
+        assert_eq!(
+            normalize_emoji(" :Party-Parrot: ").as_deref(),
+            Some(":party-parrot:")
+        );
+        assert_eq!(normalize_emoji("::"), None);
+        assert_eq!(normalize_emoji(":no spaces:"), None);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::errors::AppError;
+use crate::models::Channel;
+use crate::queries;
+use crate::responses::{ChannelReadStateResource, ChannelResource};
+use crate::socket::connection::user_room;
+use crate::socket::events::socket_publish_events;
+use crate::AppState;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use tracing::warn;
+
+#[derive(Debug)]
+pub enum MarkReadError {
+    ChannelNotFound,
+    MessageNotFound,
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for MarkReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarkReadError::ChannelNotFound => write!(f, "Channel not found"),
+            MarkReadError::MessageNotFound => write!(f, "Message not found in this channel"),
+            MarkReadError::Database(e) => write!(f, "Database error: {}", e),
+        }
+    }
+}
+
+impl From<MarkReadError> for AppError {
+    fn from(e: MarkReadError) -> Self {
+        match e {
+            MarkReadError::Database(e) => AppError::from(e),
+            e => AppError::NotFound(e.to_string()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for MarkReadError {
+    fn from(e: sqlx::Error) -> Self {
+        MarkReadError::Database(e)
+    }
+}
+
+/// Moves the read marker of `user_id` in `channel_id` forward to
+/// `message_id` and pushes the resulting state to all connections of that
+/// user. Marking an older message leaves the marker where it is.
+pub async fn mark_channel_read(
+    app_state: Arc<AppState>,
+    user_id: &str,
+    channel_id: &str,
+    message_id: &str,
+) -> Result<ChannelReadStateResource, MarkReadError> {
+    queries::get_accessible_channel(app_state.clone(), channel_id, user_id)
+        .await?
+        .ok_or(MarkReadError::ChannelNotFound)?;
+
+    let message = queries::get_message_by_id(app_state.clone(), message_id)
+        .await?
+        .filter(|message| message.channel_id == channel_id)
+        .ok_or(MarkReadError::MessageNotFound)?;
+
+    queries::upsert_channel_read_state(app_state.clone(), user_id, channel_id, &message).await?;
+
+    let read_state =
+        queries::get_channel_unread_counts(app_state.clone(), user_id, Some(channel_id))
+            .await?
+            .pop()
+            .map(|unread_count| unread_count.to_resource())
+            .ok_or(MarkReadError::MessageNotFound)?;
+
+    if let Err(e) = app_state
+        .io
+        .to(user_room(user_id))
+        .emit(socket_publish_events::UPDATE_READ_STATE, &read_state)
+        .await
+    {
+        warn!("Failed to sync read state of user {}: {}", user_id, e);
+    }
+
+    Ok(read_state)
+}
+
+/// Channel resources carrying the read marker and unread counts of `user_id`.
+pub async fn to_channel_resources(
+    app_state: Arc<AppState>,
+    channels: &[Channel],
+    user_id: &str,
+) -> sqlx::Result<Vec<ChannelResource>> {
+    let mut unread_counts: HashMap<String, ChannelReadStateResource> =
+        queries::get_channel_unread_counts(app_state, user_id, None)
+            .await?
+            .into_iter()
+            .map(|unread_count| (unread_count.channel_id.clone(), unread_count.to_resource()))
+            .collect();
+
+    Ok(channels
+        .iter()
+        .map(|channel| {
+            let mut resource = channel.to_resource();
+
+            if let Some(read_state) = unread_counts.remove(&channel.id) {
+                resource.lastReadMessageId = read_state.lastReadMessageId;
+                resource.unreadCount = read_state.unreadCount;
+                resource.mentionCount = read_state.mentionCount;
+            }
+
+            resource
+        })
+        .collect())
+}
@@ -0,0 +1,210 @@
+use crate::errors::AppError;
+use crate::models::{Report, User};
+use crate::queries;
+use crate::services::messages::to_message_resources;
+use crate::services::moderation::MAX_REASON_LENGTH;
+use crate::socket::connection::user_room;
+use crate::socket::events::socket_publish_events;
+use crate::AppState;
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Permission of the users who work the report queue.
+pub const MODERATE_REPORTS_PERMISSION: &str = "moderate_reports";
+
+#[derive(Debug)]
+pub enum ReportError {
+    MissingTarget,
+    ReasonTooLong,
+    MessageNotFound,
+    UserNotFound,
+    OwnContent,
+    AlreadyReported,
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportError::MissingTarget => write!(f, "Nothing to report provided in payload"),
+            ReportError::ReasonTooLong => {
+                write!(f, "Reason must be at most {} characters", MAX_REASON_LENGTH)
+            }
+            ReportError::MessageNotFound => write!(f, "Message not found"),
+            ReportError::UserNotFound => write!(f, "User not found"),
+            ReportError::OwnContent => write!(f, "You cannot report yourself"),
+            ReportError::AlreadyReported => write!(f, "You have already reported this"),
+            // Details stay in the server log
+            ReportError::Database(_) => write!(f, "Internal error"),
+        }
+    }
+}
+
+impl From<ReportError> for AppError {
+    fn from(e: ReportError) -> Self {
+        match e {
+            ReportError::MessageNotFound | ReportError::UserNotFound => {
+                AppError::NotFound(e.to_string())
+            }
+            ReportError::AlreadyReported => AppError::Conflict(e.to_string()),
+            ReportError::Database(e) => AppError::from(e),
+            e => AppError::Validation(e.to_string()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for ReportError {
+    fn from(e: sqlx::Error) -> Self {
+        ReportError::Database(e)
+    }
+}
+
+/// Reports a message the reporter can see. The message is snapshotted so
+/// moderators can still review it after it is deleted.
+pub async fn report_message(
+    app_state: Arc<AppState>,
+    reporter: &User,
+    message_id: &str,
+    reason: Option<&str>,
+) -> Result<Report, ReportError> {
+    if message_id.is_empty() {
+        return Err(ReportError::MissingTarget);
+    }
+
+    let message = queries::get_message_by_id(app_state.clone(), message_id)
+        .await?
+        .filter(|message| message.deleted_at.is_none())
+        .ok_or(ReportError::MessageNotFound)?;
+
+    queries::get_accessible_channel(app_state.clone(), &message.channel_id, &reporter.id)
+        .await?
+        .ok_or(ReportError::MessageNotFound)?;
+
+    if message.user_id == reporter.id {
+        return Err(ReportError::OwnContent);
+    }
+
+    let reported_user_id = message.user_id.clone();
+    let channel_id = message.channel_id.clone();
+    let snapshot = to_message_resources(app_state.clone(), vec![message], None)
+        .await?
+        .pop()
+        .ok_or(ReportError::MessageNotFound)?;
+
+    file_report(
+        app_state,
+        reporter,
+        &reported_user_id,
+        Some(message_id),
+        Some(&channel_id),
+        reason,
+        json!(snapshot),
+    )
+    .await
+}
+
+/// Reports a user, snapshotting their profile as it is now.
+pub async fn report_user(
+    app_state: Arc<AppState>,
+    reporter: &User,
+    user_id: &str,
+    reason: Option<&str>,
+) -> Result<Report, ReportError> {
+    if user_id.is_empty() {
+        return Err(ReportError::MissingTarget);
+    }
+    if user_id == reporter.id {
+        return Err(ReportError::OwnContent);
+    }
+
+    let user = queries::get_users(app_state.clone(), Some(&[user_id.to_string()][..]))
+        .await?
+        .pop()
+        .ok_or(ReportError::UserNotFound)?;
+
+    file_report(
+        app_state,
+        reporter,
+        &user.id,
+        None,
+        None,
+        reason,
+        json!(user.to_resource()),
+    )
+    .await
+}
+
+async fn file_report(
+    app_state: Arc<AppState>,
+    reporter: &User,
+    reported_user_id: &str,
+    message_id: Option<&str>,
+    channel_id: Option<&str>,
+    reason: Option<&str>,
+    snapshot: Value,
+) -> Result<Report, ReportError> {
+    let reason = reason.map(str::trim).filter(|reason| !reason.is_empty());
+    if reason.is_some_and(|reason| reason.chars().count() > MAX_REASON_LENGTH) {
+        return Err(ReportError::ReasonTooLong);
+    }
+
+    if queries::has_pending_report(
+        app_state.clone(),
+        &reporter.id,
+        reported_user_id,
+        message_id,
+    )
+    .await?
+    {
+        return Err(ReportError::AlreadyReported);
+    }
+
+    let report = queries::create_report(
+        app_state.clone(),
+        &reporter.id,
+        reported_user_id,
+        message_id,
+        channel_id,
+        reason,
+        &snapshot.to_string(),
+    )
+    .await?;
+
+    notify_moderators(app_state, socket_publish_events::RECEIVE_REPORT, &report).await;
+
+    Ok(report)
+}
+
+/// Pushes `report` to every connection of users who moderate reports.
+pub async fn notify_moderators(app_state: Arc<AppState>, event: &'static str, report: &Report) {
+    let moderator_ids =
+        match queries::get_user_ids_with_permission(app_state.clone(), MODERATE_REPORTS_PERMISSION)
+            .await
+        {
+            Ok(moderator_ids) => moderator_ids,
+            Err(e) => {
+                warn!("Failed to load moderators for report {}: {}", report.id, e);
+                return;
+            }
+        };
+
+    let rooms: Vec<String> = moderator_ids
+        .iter()
+        .map(|user_id| user_room(user_id))
+        .collect();
+
+    if rooms.is_empty() {
+        return;
+    }
+
+    if let Err(e) = app_state
+        .io
+        .to(rooms)
+        .emit(event, &json!({ "report": report.to_resource() }))
+        .await
+    {
+        warn!("Failed to notify moderators of report {}: {}", report.id, e);
+    }
+}
@@ -0,0 +1,326 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use reqwest::redirect::{Attempt, Policy};
+use reqwest::{Client, StatusCode};
+use std::error::Error;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use url::{Host, Url};
+
+/// Ports user supplied URLs may point at.
+pub const ALLOWED_PORTS: [u16; 4] = [80, 443, 8080, 8443];
+const MAX_REDIRECTS: usize = 5;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Covers the whole exchange, including reading the body.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum FetchError {
+    InvalidUrl,
+    UnsupportedScheme(String),
+    ForbiddenPort(u16),
+    /// The host is or resolves to a private, loopback or otherwise internal
+    /// address.
+    BlockedAddress,
+    TooManyRedirects,
+    Status(StatusCode),
+    Request(reqwest::Error),
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::InvalidUrl => write!(f, "Malformed url"),
+            FetchError::UnsupportedScheme(scheme) => {
+                write!(f, "Unsupported URL scheme: {}", scheme)
+            }
+            FetchError::ForbiddenPort(port) => write!(f, "Port {} is not allowed", port),
+            FetchError::BlockedAddress => write!(f, "URL points at a disallowed address"),
+            FetchError::TooManyRedirects => write!(f, "Too many redirects"),
+            FetchError::Status(status) => write!(f, "Failed to fetch URL: HTTP {}", status),
+            FetchError::Request(e) => write!(f, "Failed to fetch URL: {}", e),
+        }
+    }
+}
+
+impl Error for FetchError {}
+
+impl From<reqwest::Error> for FetchError {
+    /// Unwraps the errors raised by the resolver and the redirect policy so
+    /// callers can tell a blocked target from a failing one.
+    fn from(e: reqwest::Error) -> Self {
+        let mut source = e.source();
+        while let Some(inner) = source {
+            match inner.downcast_ref::<FetchError>() {
+                Some(FetchError::BlockedAddress) => return FetchError::BlockedAddress,
+                Some(FetchError::ForbiddenPort(port)) => return FetchError::ForbiddenPort(*port),
+                Some(FetchError::UnsupportedScheme(scheme)) => {
+                    return FetchError::UnsupportedScheme(scheme.clone())
+                }
+                Some(FetchError::TooManyRedirects) => return FetchError::TooManyRedirects,
+                _ => source = inner.source(),
+            }
+        }
+
+        FetchError::Request(e)
+    }
+}
+
+impl FetchError {
+    /// Whether the URL itself was refused rather than the remote failing.
+    pub fn is_rejected_url(&self) -> bool {
+        matches!(
+            self,
+            FetchError::InvalidUrl
+                | FetchError::UnsupportedScheme(_)
+                | FetchError::ForbiddenPort(_)
+                | FetchError::BlockedAddress
+        )
+    }
+}
+
+/// Whether `ip` is reachable on the public internet. Private, loopback,
+/// link-local, shared, documentation and reserved ranges are not.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => is_public_ipv6(ip),
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        // Carrier-grade NAT, 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments, 192.0.0.0/24
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking, 198.18.0.0/15
+        || (a == 198 && (18..20).contains(&b))
+        // Reserved, 240.0.0.0/4
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    if let Some(ipv4) = ip.to_ipv4_mapped() {
+        return is_public_ipv4(ipv4);
+    }
+
+    let segments = ip.segments();
+    // NAT64, 64:ff9b::/96, embeds an IPv4 address
+    if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+        let [.., high, low] = segments;
+        return is_public_ipv4(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)));
+    }
+
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local, fc00::/7
+        || (segments[0] & 0xfe00) == 0xfc00
+        // Link-local, fe80::/10
+        || (segments[0] & 0xffc0) == 0xfe80
+        // Documentation, 2001:db8::/32
+        || (segments[0] == 0x2001 && segments[1] == 0x0db8))
+}
+
+/// Checks the parts of `url` that can be checked without resolving it.
+/// Hostnames are checked by the client's resolver on every connection.
+pub fn check_url(url: &Url) -> Result<(), FetchError> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(FetchError::UnsupportedScheme(url.scheme().to_string()));
+    }
+
+    let port = url.port_or_known_default().ok_or(FetchError::InvalidUrl)?;
+    if !ALLOWED_PORTS.contains(&port) {
+        return Err(FetchError::ForbiddenPort(port));
+    }
+
+    let is_public = match url.host() {
+        Some(Host::Ipv4(ip)) => is_public_ipv4(ip),
+        Some(Host::Ipv6(ip)) => is_public_ipv6(ip),
+        Some(Host::Domain(_)) => true,
+        None => return Err(FetchError::InvalidUrl),
+    };
+
+    if !is_public {
+        return Err(FetchError::BlockedAddress);
+    }
+
+    Ok(())
+}
+
+/// Resolves hostnames like the system resolver but drops internal
+/// addresses, so neither the first request nor a redirect can reach them.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_address(addr.ip()))
+                .collect();
+
+            if addrs.is_empty() {
+                return Err(FetchError::BlockedAddress.into());
+            }
+
+            Ok::<Addrs, Box<dyn Error + Send + Sync>>(Box::new(addrs.into_iter()))
+        })
+    }
+}
+
+fn follow_redirect(attempt: Attempt) -> reqwest::redirect::Action {
+    if attempt.previous().len() >= MAX_REDIRECTS {
+        return attempt.error(FetchError::TooManyRedirects);
+    }
+
+    match check_url(attempt.url()) {
+        Ok(()) => attempt.follow(),
+        Err(e) => attempt.error(e),
+    }
+}
+
+/// Client shared by every fetch of a user supplied URL.
+pub fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .user_agent("Mozilla/5.0 (compatible; RustBot/1.0)")
+            .dns_resolver(Arc::new(PublicResolver))
+            .redirect(Policy::custom(follow_redirect))
+            // A proxy would resolve hosts itself, past our resolver
+            .no_proxy()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("failed to build fetch HTTP client")
+    })
+}
+
+#[derive(Debug)]
+pub struct FetchedResponse {
+    /// Where the request ended up after redirects.
+    pub url: Url,
+    pub headers: HeaderMap,
+    /// At most the requested number of bytes; the rest is never read.
+    pub body: Vec<u8>,
+    pub truncated: bool,
+}
+
+impl FetchedResponse {
+    pub fn content_type(&self) -> &str {
+        self.headers
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .unwrap_or("")
+    }
+}
+
+/// GETs a user supplied URL, refusing internal targets and reading at most
+/// `max_bytes` of the body. `accept` decides from the `Content-Type` whether
+/// the body is worth downloading at all.
+pub async fn fetch(
+    input_url: &str,
+    max_bytes: usize,
+    accept: impl Fn(&str) -> bool,
+) -> Result<FetchedResponse, FetchError> {
+    let url = Url::parse(input_url).map_err(|_| FetchError::InvalidUrl)?;
+    check_url(&url)?;
+
+    let mut response = client().get(url).send().await?;
+
+    if !response.status().is_success() {
+        return Err(FetchError::Status(response.status()));
+    }
+
+    let url = response.url().clone();
+    let headers = response.headers().clone();
+
+    let mut fetched = FetchedResponse {
+        url,
+        headers,
+        body: Vec::new(),
+        truncated: false,
+    };
+
+    if !accept(fetched.content_type()) {
+        return Ok(fetched);
+    }
+
+    while let Some(chunk) = response.chunk().await? {
+        let remaining = max_bytes - fetched.body.len();
+        if chunk.len() > remaining {
+            fetched.body.extend_from_slice(&chunk[..remaining]);
+            fetched.truncated = true;
+            break;
+        }
+        fetched.body.extend_from_slice(&chunk);
+    }
+
+    Ok(fetched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_addresses_are_not_public() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+
+        for ip in ["93.184.216.34", "2606:2800:220:1:248:1893:25c8:1946"] {
+            assert!(is_public_address(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn urls_are_checked_before_resolving() {
+        let check = |url: &str| check_url(&Url::parse(url).unwrap());
+
+        assert!(check("https://example.com/page").is_ok());
+        assert!(check("http://example.com:8080/").is_ok());
+        assert!(matches!(
+            check("http://127.0.0.1/"),
+            Err(FetchError::BlockedAddress)
+        ));
+        assert!(matches!(
+            check("http://[::1]/"),
+            Err(FetchError::BlockedAddress)
+        ));
+        assert!(matches!(
+            check("http://example.com:22/"),
+            Err(FetchError::ForbiddenPort(22))
+        ));
+        assert!(matches!(
+            check("ftp://example.com/"),
+            Err(FetchError::UnsupportedScheme(_))
+        ));
+    }
+}
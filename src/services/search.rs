@@ -0,0 +1,56 @@
+/// Turns free text into a MySQL boolean mode FULLTEXT query requiring every
+/// term as a prefix, e.g. `deploy fail` becomes `+deploy* +fail*`. Operator
+/// and punctuation characters separate terms, like they do in the index, so
+/// user input cannot alter the query syntax and `e-mail` finds `e mail`.
+pub fn fulltext_boolean_query(text: &str) -> Option<String> {
+    let terms = text
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("+{}*", term))
+        .collect::<Vec<_>>();
+
+    if terms.is_empty() {
+        return None;
+    }
+
+    Some(terms.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requires_every_term_as_a_prefix() {
+        assert_eq!(
+            fulltext_boolean_query("  deploy   fail ").as_deref(),
+            Some("+deploy* +fail*")
+        );
+        assert_eq!(fulltext_boolean_query("   "), None);
+    }
+
+    #[test]
+    fn splits_on_apostrophes_and_hyphens() {
+        assert_eq!(
+            fulltext_boolean_query("don't re-deploy").as_deref(),
+            Some("+don* +t* +re* +deploy*")
+        );
+    }
+
+    #[test]
+    fn escapes_boolean_operators() {
+        assert_eq!(
+            fulltext_boolean_query(r#"-spam +"exact phrase" (a|b) ~x <y >z @3 deploy*"#).as_deref(),
+            Some("+spam* +exact* +phrase* +a* +b* +x* +y* +z* +3* +deploy*")
+        );
+        assert_eq!(fulltext_boolean_query(r#"+-*"()~<>@"#), None);
+    }
+
+    #[test]
+    fn keeps_unicode_and_underscores() {
+        assert_eq!(
+            fulltext_boolean_query("café snake_case").as_deref(),
+            Some("+café* +snake_case*")
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use argon2::password_hash::SaltString;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+const ISSUER: &str = "rsblubber";
+const DIGITS: usize = 6;
+const STEP: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// Generates a fresh base32 encoded TOTP secret.
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+
+    match Secret::Raw(bytes.to_vec()).to_encoded() {
+        Secret::Encoded(secret) => secret,
+        Secret::Raw(_) => unreachable!("to_encoded always returns an encoded secret"),
+    }
+}
+
+fn build_totp(secret: &str, account_name: &str) -> Result<TOTP, String> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|e| format!("Invalid TOTP secret: {:?}", e))?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        DIGITS,
+        0,
+        STEP,
+        secret,
+        Some(ISSUER.to_string()),
+        account_name.replace(':', "_"),
+    )
+    .map_err(|e| format!("Invalid TOTP parameters: {}", e))
+}
+
+/// Builds the `otpauth://` URI authenticator apps use to enrol the secret.
+pub fn otpauth_uri(secret: &str, account_name: &str) -> Result<String, String> {
+    Ok(build_totp(secret, account_name)?.get_url())
+}
+
+/// Checks `code` against the current, previous and next time step. Returns
+/// the matched step so callers can persist it and refuse to accept the same
+/// (or an older) code twice.
+pub fn verify_code(secret: &str, code: &str, last_used_step: Option<i64>) -> Option<i64> {
+    verify_code_at(
+        secret,
+        code,
+        last_used_step,
+        chrono::Utc::now().timestamp() as u64,
+    )
+}
+
+fn verify_code_at(secret: &str, code: &str, last_used_step: Option<i64>, now: u64) -> Option<i64> {
+    let totp = build_totp(secret, "").ok()?;
+    let code = code.trim();
+
+    [now.saturating_sub(STEP), now, now + STEP]
+        .into_iter()
+        .find(|time| totp.check(code, *time))
+        .map(|time| (time / STEP) as i64)
+        .filter(|step| last_used_step.map_or(true, |last| *step > last))
+}
+
+/// Generates a set of single use recovery codes in `xxxxx-xxxxx` format.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let chars = (0..10)
+                .map(|_| {
+                    let idx = OsRng.next_u32() as usize % RECOVERY_CODE_ALPHABET.len();
+                    RECOVERY_CODE_ALPHABET[idx] as char
+                })
+                .collect::<String>();
+
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect()
+}
+
+/// Normalises user input so recovery codes are accepted regardless of case
+/// and surrounding whitespace.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.trim().to_lowercase()
+}
+
+/// Argon2 hash of a recovery code, as stored in `user_recovery_codes`.
+pub fn hash_recovery_code(code: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(normalize_recovery_code(code).as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| format!("Error while hashing recovery code: {}", e))
+}
+
+/// Whether user input `code` matches the stored `code_hash`.
+pub fn verify_recovery_code(code: &str, code_hash: &str) -> bool {
+    match PasswordHash::new(code_hash) {
+        Ok(parsed_hash) => Argon2::default()
+            .verify_password(normalize_recovery_code(code).as_bytes(), &parsed_hash)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+    const NOW: u64 = 1_700_000_000;
+
+    fn code_at(time: u64) -> String {
+        build_totp(SECRET, "").unwrap().generate(time)
+    }
+
+    #[test]
+    fn accepts_codes_from_adjacent_steps_only() {
+        let step = (NOW / STEP) as i64;
+
+        assert_eq!(verify_code_at(SECRET, &code_at(NOW), None, NOW), Some(step));
+        assert_eq!(
+            verify_code_at(SECRET, &code_at(NOW - STEP), None, NOW),
+            Some(step - 1)
+        );
+        assert_eq!(
+            verify_code_at(SECRET, &code_at(NOW + STEP), None, NOW),
+            Some(step + 1)
+        );
+        assert_eq!(
+            verify_code_at(SECRET, &code_at(NOW - 2 * STEP), None, NOW),
+            None
+        );
+        assert_eq!(verify_code_at(SECRET, "000000x", None, NOW), None);
+    }
+
+    #[test]
+    fn rejects_replayed_and_older_steps() {
+        let step = (NOW / STEP) as i64;
+        let code = code_at(NOW);
+
+        assert_eq!(
+            verify_code_at(SECRET, &code, Some(step - 1), NOW),
+            Some(step)
+        );
+        assert_eq!(verify_code_at(SECRET, &code, Some(step), NOW), None);
+        assert_eq!(
+            verify_code_at(SECRET, &code_at(NOW - STEP), Some(step - 1), NOW),
+            None
+        );
+    }
+
+    #[test]
+    fn recovery_codes_verify_against_their_hash() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+
+        let hash = hash_recovery_code(&codes[0]).unwrap();
+
+        assert_ne!(hash, codes[0]);
+        assert!(verify_recovery_code(&codes[0], &hash));
+        assert!(verify_recovery_code(
+            &format!("  {} ", codes[0].to_uppercase()),
+            &hash
+        ));
+        assert!(!verify_recovery_code(&codes[1], &hash));
+        assert!(!verify_recovery_code(&codes[0], "not a hash"));
+    }
+}
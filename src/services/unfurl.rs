@@ -0,0 +1,220 @@
+use crate::models::Message;
+use crate::queries;
+use crate::services::blocks::emit_message_update;
+use crate::services::messages::to_message_resources;
+use crate::services::preview_cache::get_cached_preview;
+use crate::AppState;
+use regex::Regex;
+use std::sync::{Arc, OnceLock};
+use tracing::warn;
+
+/// Links beyond this many in one message are not unfurled.
+pub const MAX_EMBEDS_PER_MESSAGE: usize = 5;
+
+fn url_regex() -> &'static Regex {
+    static URL_REGEX: OnceLock<Regex> = OnceLock::new();
+    URL_REGEX.get_or_init(|| Regex::new(r"(?i)\bhttps?://[^\s<>]+").expect("url pattern is valid"))
+}
+
+/// The distinct http(s) links in `content`, in order of appearance. Handles
+/// markdown links, `[text](url)`, and punctuation ending a sentence.
+pub fn extract_urls(content: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+
+    for found in url_regex().find_iter(content) {
+        let mut url = found.as_str();
+
+        // `[https://a](https://b)` matches as one run; the label is enough
+        if let Some(index) = url.find("](") {
+            url = &url[..index];
+        }
+
+        // A closing parenthesis belongs to the URL only if it opened one,
+        // as in Wikipedia links
+        loop {
+            let trimmed = url.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"', ']']);
+            let trimmed = if trimmed.ends_with(')')
+                && trimmed.matches(')').count() > trimmed.matches('(').count()
+            {
+                &trimmed[..trimmed.len() - 1]
+            } else {
+                trimmed
+            };
+
+            if trimmed == url {
+                break;
+            }
+            url = trimmed;
+        }
+
+        if !urls.iter().any(|existing| existing == url) {
+            urls.push(url.to_string());
+        }
+        if urls.len() == MAX_EMBEDS_PER_MESSAGE {
+            break;
+        }
+    }
+
+    urls
+}
+
+/// Fetches previews of the links in `message` in the background, stores
+/// them as its embeds and pushes the message again with `updateMessage`.
+pub fn spawn_unfurl(app_state: Arc<AppState>, message: &Message) {
+    let urls = extract_urls(message.content.as_deref().unwrap_or_default());
+    if urls.is_empty() {
+        return;
+    }
+
+    let message_id = message.id.clone();
+    let channel_id = message.channel_id.clone();
+
+    tokio::spawn(async move {
+        let mut embeds = Vec::new();
+        for url in urls {
+            // Links without a preview are simply left out
+            if let Ok(preview) = get_cached_preview(app_state.clone(), &url).await {
+                match serde_json::to_string(preview.as_ref()) {
+                    Ok(preview) => embeds.push((url, preview)),
+                    Err(e) => warn!("Failed to serialize preview of {}: {}", url, e),
+                }
+            }
+        }
+
+        if embeds.is_empty() {
+            return;
+        }
+
+        if let Err(e) =
+            queries::create_message_embeds(app_state.clone(), &message_id, &embeds).await
+        {
+            warn!("Failed to store embeds of message {}: {}", message_id, e);
+            return;
+        }
+
+        if let Err(e) = announce_embeds(app_state, &message_id, &channel_id).await {
+            warn!("Failed to announce embeds of message {}: {}", message_id, e);
+        }
+    });
+}
+
+/// Pushes `message_id` again now that it has embeds. Returns whether it
+/// was announced.
+async fn announce_embeds(
+    app_state: Arc<AppState>,
+    message_id: &str,
+    channel_id: &str,
+) -> sqlx::Result<bool> {
+    // The message may have been deleted while its links were fetched
+    let Some(message) = queries::get_message_by_id(app_state.clone(), message_id)
+        .await?
+        .filter(|message| message.deleted_at.is_none())
+    else {
+        return Ok(false);
+    };
+    // Direct conversations unfurl links too
+    let Some(channel) = queries::get_any_channel_by_id(app_state.clone(), channel_id).await? else {
+        return Ok(false);
+    };
+
+    let Some(message_resource) = to_message_resources(app_state.clone(), vec![message], None)
+        .await?
+        .pop()
+    else {
+        return Ok(false);
+    };
+
+    emit_message_update(app_state, &channel, &message_resource).await;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::queries::CreateMessageOptions;
+    use crate::socket::events::socket_publish_events;
+    use crate::test_support::{
+        connect_test_socket, create_test_user, received_events, test_app_state,
+    };
+    use sqlx::MySqlPool;
+
+    #[test]
+    fn extract_urls_handles_markdown_and_punctuation() {
+        assert_eq!(
+            extract_urls(
+                "See https://example.com/a. Also [docs](https://docs.example.com/b), \
+                 [https://x.example.com](https://x.example.com) and \
+                 https://en.wikipedia.org/wiki/Rust_(programming_language)!"
+            ),
+            vec![
+                "https://example.com/a",
+                "https://docs.example.com/b",
+                "https://x.example.com",
+                "https://en.wikipedia.org/wiki/Rust_(programming_language)",
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_urls_dedupes_and_caps() {
+        let content = (0..10)
+            .map(|i| format!("https://example.com/{} https://example.com/{}", i, i))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let urls = extract_urls(&content);
+
+        assert_eq!(urls.len(), MAX_EMBEDS_PER_MESSAGE);
+        assert_eq!(urls[0], "https://example.com/0");
+        assert_eq!(urls[4], "https://example.com/4");
+        assert!(extract_urls("no links, just ftp://example.com").is_empty());
+    }
+
+    #[sqlx::test]
+    async fn announces_embeds_only_to_participants(pool: MySqlPool) {
+        let app_state = test_app_state(pool);
+        let alice = create_test_user(app_state.clone(), "alice").await;
+        let bob = create_test_user(app_state.clone(), "bob").await;
+        let eve = create_test_user(app_state.clone(), "eve").await;
+        let mut alice_packets = connect_test_socket(&app_state, &alice.id).await;
+        let mut bob_packets = connect_test_socket(&app_state, &bob.id).await;
+        let mut eve_packets = connect_test_socket(&app_state, &eve.id).await;
+
+        let (channel, _) = queries::create_direct_conversation(
+            app_state.clone(),
+            &[alice.id.clone(), bob.id.clone()],
+        )
+        .await
+        .unwrap();
+        let message = queries::create_message(
+            app_state.clone(),
+            alice.id.clone(),
+            channel.id.clone(),
+            Some("https://example.com".to_string()),
+            CreateMessageOptions::default(),
+        )
+        .await
+        .unwrap();
+        queries::create_message_embeds(
+            app_state.clone(),
+            &message.id,
+            &[("https://example.com".to_string(), "{}".to_string())],
+        )
+        .await
+        .unwrap();
+
+        assert!(announce_embeds(app_state, &message.id, &channel.id)
+            .await
+            .unwrap());
+        assert_eq!(
+            received_events(&mut alice_packets),
+            vec![socket_publish_events::UPDATE_MESSAGE]
+        );
+        assert_eq!(
+            received_events(&mut bob_packets),
+            vec![socket_publish_events::UPDATE_MESSAGE]
+        );
+        assert!(received_events(&mut eve_packets).is_empty());
+    }
+}
@@ -1,20 +1,29 @@
-use crate::auth::{extract_user_id, parse_token};
+use crate::auth::{extract_user_id, hash_api_token, is_api_token, parse_token, API_TOKEN_SCOPES};
 use crate::models::User;
-use crate::queries::get_user_by_id;
-use crate::socket::events::socket_listen_events;
+use crate::queries::{
+    get_active_api_token_by_hash, get_active_user_ban, get_user_by_id, touch_api_token,
+};
+use crate::services::outgoing_webhooks::{event_types, spawn_dispatch_event};
+use crate::socket::events::{socket_listen_events, socket_publish_events};
 use crate::socket::handlers::{
-    send_chat_message_handler, send_kick_handler, send_poke_handler,
+    add_reaction_handler, mark_read_handler, remove_reaction_handler, report_message_handler,
+    report_user_handler, send_chat_message_handler, send_kick_handler, send_poke_handler,
     send_user_audio_mute_status_changed, send_user_is_typing_handler,
     send_user_microphone_status_changed,
 };
 use crate::{AppState, UserConnection};
+use jsonwebtoken::errors::ErrorKind;
 use serde::Serialize;
-use serde_json::Value;
+use serde_json::{json, Value};
 use socketioxide::extract::{AckSender, Data, SocketRef};
 use socketioxide::SocketIo;
 use std::sync::Arc;
 use tracing::{info, warn};
-use uuid::Uuid;
+
+/// Room shared by all connections of one user.
+pub fn user_room(user_id: &str) -> String {
+    format!("user:{}", user_id)
+}
 
 #[derive(Debug, Clone, Serialize)]
 pub struct ConnectionInfo {
@@ -22,60 +31,134 @@ pub struct ConnectionInfo {
     pub user: User,
 }
 
-pub async fn on_connect(socket: SocketRef, Data(data): Data<Value>, app_state: Arc<AppState>) {
-    info!("Socket.IO connected: {:?} {:?}", socket.ns(), socket.id);
+/// Reasons a socket handshake can be rejected with. The `Display` output is
+/// sent to the client as the `connect_error` message, so it must stay stable
+/// and must never contain credentials.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketAuthError {
+    MissingToken,
+    InvalidToken,
+    ExpiredToken,
+    Banned,
+    Internal,
+}
 
-    // Extract token from connection data
-    let token = match data
+impl std::fmt::Display for SocketAuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            SocketAuthError::MissingToken => "missing",
+            SocketAuthError::InvalidToken => "invalid",
+            SocketAuthError::ExpiredToken => "expired",
+            SocketAuthError::Banned => "banned",
+            SocketAuthError::Internal => "internal",
+        };
+
+        write!(f, "{}", reason)
+    }
+}
+
+/// Connect middleware: runs before `on_connect` and rejects the handshake
+/// with a `connect_error` if the auth payload does not carry a valid token.
+pub async fn authenticate_socket(
+    socket: SocketRef,
+    Data(data): Data<Value>,
+    app_state: Arc<AppState>,
+) -> Result<(), SocketAuthError> {
+    // Extract token from the handshake auth payload
+    let token = data
         .get("token")
         .and_then(|token_val| token_val.as_str())
-        .map(|s| s.to_string())
-    {
-        Some(token) => token,
-        None => {
-            warn!("No token provided in connection data");
-            return;
-        }
-    };
+        .filter(|token| !token.is_empty())
+        .ok_or(SocketAuthError::MissingToken)?;
 
-    // Initial acknowledgment
-    socket.emit("auth", &data).ok();
+    let user_id = if is_api_token(token) {
+        // Bots may connect with an API token; a socket is a read/write
+        // session so both scopes are required
+        let api_token = get_active_api_token_by_hash(app_state.clone(), &hash_api_token(token))
+            .await
+            .map_err(|e| {
+                warn!("Failed to fetch API token for socket {}: {}", socket.id, e);
+                SocketAuthError::Internal
+            })?
+            .ok_or(SocketAuthError::InvalidToken)?;
 
-    // Authenticate and establish connection
-    if let Err(e) = authenticate_socket(&socket, &token, app_state.clone()).await {
-        warn!("Authentication failed: {}", e);
-        let _ = socket.disconnect();
-        return;
-    }
+        if !API_TOKEN_SCOPES
+            .iter()
+            .all(|scope| api_token.has_scope(scope))
+        {
+            return Err(SocketAuthError::InvalidToken);
+        }
 
-    // Register event handlers
-    register_event_handlers(&socket, app_state.clone());
-}
+        if let Err(e) = touch_api_token(app_state.clone(), &api_token.id).await {
+            warn!("Failed to update API token usage: {}", e);
+        }
 
-async fn authenticate_socket(
-    socket: &SocketRef,
-    token: &str,
-    app_state: Arc<AppState>,
-) -> Result<(), String> {
-    // Parse JWT token
-    let claims = parse_token(token, app_state.config.jwt_secret.as_ref())
-        .map_err(|e| format!("Failed to parse token: {}", e))?;
+        api_token.user_id
+    } else {
+        // Parse JWT token
+        let claims = parse_token(token, app_state.config.jwt_secret.as_ref()).map_err(|e| {
+            warn!("Socket {} sent an unusable token: {}", socket.id, e);
+            match e.kind() {
+                ErrorKind::ExpiredSignature => SocketAuthError::ExpiredToken,
+                _ => SocketAuthError::InvalidToken,
+            }
+        })?;
 
-    // Extract user ID
-    let user_id =
-        extract_user_id(&claims).map_err(|e| format!("Failed to extract user ID: {}", e))?;
+        // Extract user ID
+        extract_user_id(&claims)
+            .map_err(|e| {
+                warn!(
+                    "Socket {} sent a token without a valid subject: {}",
+                    socket.id, e
+                );
+                SocketAuthError::InvalidToken
+            })?
+            .to_string()
+    };
 
     // Fetch user from database
-    let user = get_user_by_id(app_state.clone(), user_id.to_string())
+    let user = get_user_by_id(app_state.clone(), user_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => SocketAuthError::InvalidToken,
+            e => {
+                warn!("Failed to fetch user for socket {}: {}", socket.id, e);
+                SocketAuthError::Internal
+            }
+        })?;
+
+    let ban = get_active_user_ban(app_state.clone(), &user.id)
         .await
-        .map_err(|e| format!("Failed to fetch user: {}", e))?;
+        .map_err(|e| {
+            warn!("Failed to fetch bans for socket {}: {}", socket.id, e);
+            SocketAuthError::Internal
+        })?;
+
+    if ban.is_some() {
+        return Err(SocketAuthError::Banned);
+    }
 
     // Store connection info
     socket.extensions.insert(ConnectionInfo {
         token: token.to_string(),
-        user: user.clone(),
+        user,
     });
 
+    Ok(())
+}
+
+pub async fn on_connect(socket: SocketRef, app_state: Arc<AppState>) {
+    info!("Socket.IO connected: {:?} {:?}", socket.ns(), socket.id);
+
+    // The auth middleware guarantees the connection info is present
+    let Some(connection_info) = socket.extensions.get::<ConnectionInfo>() else {
+        warn!("Socket {} connected without connection info", socket.id);
+        let _ = socket.disconnect();
+        return;
+    };
+
+    let user = connection_info.user;
+
     app_state.connected_users.insert(
         user.id.clone(),
         UserConnection {
@@ -88,15 +171,33 @@ async fn authenticate_socket(
         },
     );
 
-    Ok(())
+    // Every connection of a user joins the same room, e.g. to sync read state
+    socket.join(user_room(&user.id));
+
+    spawn_dispatch_event(
+        app_state.clone(),
+        event_types::USER_JOINED,
+        json!({ "user": user.to_resource() }),
+    );
+
+    // Let the client know who it is authenticated as
+    socket
+        .emit(
+            socket_publish_events::AUTH,
+            &json!({ "user": user.to_resource() }),
+        )
+        .ok();
+
+    // Register event handlers
+    register_event_handlers(&socket, app_state.clone());
 }
 
 fn register_event_handlers(socket: &SocketRef, app_state: Arc<AppState>) {
     let app_state_clone = app_state.clone();
     socket.on(
         socket_listen_events::SEND_CHAT_MESSAGE,
-        |io: SocketIo, socket: SocketRef, Data(payload): Data<Value>| async move {
-            send_chat_message_handler(&io, &socket, Data(payload), app_state_clone).await;
+        |io: SocketIo, socket: SocketRef, Data(payload): Data<Value>, ack: AckSender| async move {
+            send_chat_message_handler(&io, &socket, Data(payload), ack, app_state_clone).await;
         },
     );
 
@@ -127,8 +228,9 @@ fn register_event_handlers(socket: &SocketRef, app_state: Arc<AppState>) {
     let app_state_clone = app_state.clone();
     socket.on(
         socket_listen_events::SEND_USER_MICROPHONE_STATUS_CHANGED,
-        |io: SocketIo, socket: SocketRef, Data(payload): Data<Value>| async move {
-            send_user_microphone_status_changed(&io, &socket, Data(payload), app_state_clone).await;
+        |io: SocketIo, socket: SocketRef, Data(payload): Data<Value>, ack: AckSender| async move {
+            send_user_microphone_status_changed(&io, &socket, Data(payload), ack, app_state_clone)
+                .await;
         },
     );
 
@@ -139,4 +241,44 @@ fn register_event_handlers(socket: &SocketRef, app_state: Arc<AppState>) {
             send_user_audio_mute_status_changed(&io, &socket, Data(payload), app_state_clone).await;
         },
     );
+
+    let app_state_clone = app_state.clone();
+    socket.on(
+        socket_listen_events::ADD_REACTION,
+        |io: SocketIo, socket: SocketRef, Data(payload): Data<Value>, ack: AckSender| async move {
+            add_reaction_handler(&io, &socket, Data(payload), ack, app_state_clone).await;
+        },
+    );
+
+    let app_state_clone = app_state.clone();
+    socket.on(
+        socket_listen_events::REMOVE_REACTION,
+        |io: SocketIo, socket: SocketRef, Data(payload): Data<Value>, ack: AckSender| async move {
+            remove_reaction_handler(&io, &socket, Data(payload), ack, app_state_clone).await;
+        },
+    );
+
+    let app_state_clone = app_state.clone();
+    socket.on(
+        socket_listen_events::MARK_READ,
+        |socket: SocketRef, Data(payload): Data<Value>, ack: AckSender| async move {
+            mark_read_handler(&socket, Data(payload), ack, app_state_clone).await;
+        },
+    );
+
+    let app_state_clone = app_state.clone();
+    socket.on(
+        socket_listen_events::REPORT_MESSAGE,
+        |socket: SocketRef, Data(payload): Data<Value>, ack: AckSender| async move {
+            report_message_handler(&socket, Data(payload), ack, app_state_clone).await;
+        },
+    );
+
+    let app_state_clone = app_state.clone();
+    socket.on(
+        socket_listen_events::REPORT_USER,
+        |socket: SocketRef, Data(payload): Data<Value>, ack: AckSender| async move {
+            report_user_handler(&socket, Data(payload), ack, app_state_clone).await;
+        },
+    );
 }
@@ -8,9 +8,15 @@ pub mod socket_listen_events {
     pub const SEND_USER_IS_TYPING: &str = "sendUserIsTyping";
     pub const SEND_USER_AUDIO_MUTE_STATUS_CHANGED: &str = "sendUserAudioMuteStatusChanged";
     pub const SEND_USER_MICROPHONE_STATUS_CHANGED: &str = "sendUserMicrophoneStatusChanged";
+    pub const ADD_REACTION: &str = "addReaction";
+    pub const REMOVE_REACTION: &str = "removeReaction";
+    pub const MARK_READ: &str = "markRead";
+    pub const REPORT_MESSAGE: &str = "reportMessage";
+    pub const REPORT_USER: &str = "reportUser";
 }
 
 pub mod socket_publish_events {
+    pub const AUTH: &str = "auth";
     pub const RECEIVE_CHAT_MESSAGE: &str = "receiveChatMessage";
     pub const RECEIVE_POKE: &str = "receivePoke";
     pub const RECEIVE_KICK: &str = "receiveKick";
@@ -20,4 +26,12 @@ pub mod socket_publish_events {
     pub const RECEIVE_USER_IS_TYPING: &str = "receiveUserIsTyping";
     pub const RECEIVE_USER_AUDIO_MUTE_STATUS_CHANGED: &str = "receiveUserAudioMuteStatusChanged";
     pub const RECEIVE_USER_MICROPHONE_STATUS_CHANGED: &str = "receiveUserMicrophoneStatusChanged";
+    pub const RECEIVE_REACTION_ADDED: &str = "receiveReactionAdded";
+    pub const RECEIVE_REACTION_REMOVED: &str = "receiveReactionRemoved";
+    pub const RECEIVE_MENTION: &str = "receiveMention";
+    pub const UPDATE_READ_STATE: &str = "updateReadState";
+    pub const RECEIVE_BAN: &str = "receiveBan";
+    pub const RECEIVE_TIMEOUT: &str = "receiveTimeout";
+    pub const RECEIVE_REPORT: &str = "receiveReport";
+    pub const UPDATE_REPORT: &str = "updateReport";
 }
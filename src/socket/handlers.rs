@@ -1,5 +1,22 @@
-use crate::queries::create_message;
-use crate::responses::MessageResource;
+use crate::errors::AppError;
+use crate::models::Report;
+use crate::queries::{self, create_message, CreateMessageOptions};
+use crate::responses::{MessageResource, ThreadSummaryResource};
+use crate::services::audit_log::{self, audit_actions, audit_target_types};
+use crate::services::automod::{flag_message, screen_message, AutomodVerdict};
+use crate::services::blocks::{emit_chat_message, is_blocked_in_channel};
+use crate::services::direct_messages::emit_to_channel;
+use crate::services::mentions::{notify_mentions, record_mentions};
+use crate::services::messages::{
+    attach_reply_references, thread_summary_for, validate_message_references,
+};
+use crate::services::moderation::{check_microphone_change, timed_out_message};
+use crate::services::outgoing_webhooks::{event_types, spawn_dispatch_event};
+use crate::services::pokes::{send_poke, PokeError};
+use crate::services::reactions::normalize_emoji;
+use crate::services::read_state::{mark_channel_read, MarkReadError};
+use crate::services::reports::{report_message, report_user, ReportError};
+use crate::services::unfurl::spawn_unfurl;
 use crate::socket::connection::ConnectionInfo;
 use crate::socket::events::socket_publish_events;
 use crate::AppState;
@@ -19,11 +36,43 @@ struct SendMessagePayload {
 
     #[serde(rename = "attachmentIds")]
     attachment_ids: Vec<String>, // optional, but include it if it's in the payload
+
+    #[serde(rename = "replyToMessageId")]
+    reply_to_message_id: Option<String>,
+
+    #[serde(rename = "threadId")]
+    thread_id: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct ReceiveChatMessagePayload {
-    message: MessageResource,
+    pub message: MessageResource,
+    /// Updated summary of the thread the message was posted to, if any.
+    pub thread: Option<ThreadSummaryResource>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReactionPayload {
+    #[serde(rename = "messageId")]
+    message_id: String,
+
+    emoji: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MicrophoneStatusPayload {
+    #[serde(rename = "isMicrophoneMuted")]
+    is_microphone_muted: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[allow(non_snake_case)]
+struct ReceiveReactionPayload {
+    messageId: String,
+    channelId: String,
+    userId: String,
+    emoji: String,
+    count: i64,
 }
 
 // info!("~~ Cnt ~~ : {:?}", app_state.cnt);
@@ -38,12 +87,15 @@ pub async fn send_chat_message_handler(
     io: &SocketIo,
     socket: &SocketRef,
     Data(payload): Data<Value>,
+    ack: AckSender,
     app_state: Arc<AppState>,
 ) {
     let connection_info = match socket.extensions.get::<ConnectionInfo>() {
         Some(info) => info,
         None => {
             warn!("Received message but no connection info found");
+            let error = AppError::Unauthorized("Not authenticated".to_string());
+            let _ = ack.send(&error.to_ack());
             return;
         }
     };
@@ -53,34 +105,188 @@ pub async fn send_chat_message_handler(
         Ok(payload) => payload,
         Err(e) => {
             warn!("Failed to deserialize message payload: {}", e);
+            let error = AppError::BadRequest(format!("Invalid message payload: {}", e));
+            let _ = ack.send(&error.to_ack());
             return;
         }
     };
 
     info!("Creating message from user {}: {:?}", user_id, payload);
 
-    let message =
-        match create_message(app_state, user_id, payload.channel_id, payload.content).await {
-            Ok(message) => message,
+    let channel =
+        match queries::get_accessible_channel(app_state.clone(), &payload.channel_id, &user_id)
+            .await
+        {
+            Ok(Some(channel)) => channel,
+            Ok(None) => {
+                warn!(
+                    "Rejected message from user {}: channel {} not found",
+                    user_id, payload.channel_id
+                );
+                let error = AppError::NotFound("Channel not found".to_string());
+                let _ = ack.send(&error.to_ack());
+                return;
+            }
             Err(e) => {
-                warn!("Failed to create message: {}", e);
+                warn!("Failed to load channel {}: {}", payload.channel_id, e);
+                let _ = ack.send(&AppError::from(e).to_ack());
                 return;
             }
         };
 
+    match queries::get_active_user_timeout(app_state.clone(), &user_id).await {
+        Ok(None) => {}
+        Ok(Some(timeout)) => {
+            let error = AppError::TimedOut {
+                message: timed_out_message(&timeout),
+                expires_at: timeout.expires_at,
+            };
+            let _ = ack.send(&error.to_ack());
+            return;
+        }
+        Err(e) => {
+            warn!("Failed to load timeouts of user {}: {}", user_id, e);
+            let _ = ack.send(&AppError::from(e).to_ack());
+            return;
+        }
+    }
+
+    match is_blocked_in_channel(app_state.clone(), &user_id, &channel).await {
+        Ok(false) => {}
+        Ok(true) => {
+            let error = AppError::Blocked("You cannot message this user".to_string());
+            let _ = ack.send(&error.to_ack());
+            return;
+        }
+        Err(e) => {
+            warn!("Failed to load blocks of user {}: {}", user_id, e);
+            let _ = ack.send(&AppError::from(e).to_ack());
+            return;
+        }
+    }
+
+    if let Err(e) = validate_message_references(
+        app_state.clone(),
+        &payload.channel_id,
+        payload.reply_to_message_id.as_deref(),
+        payload.thread_id.as_deref(),
+    )
+    .await
+    {
+        warn!("Rejected message from user {}: {}", user_id, e);
+        let _ = ack.send(&AppError::from(e).to_ack());
+        return;
+    }
+
+    let verdict = match payload
+        .content
+        .as_deref()
+        .filter(|content| !content.is_empty())
+    {
+        Some(content) => {
+            match screen_message(app_state.clone(), &connection_info.user, &channel, content).await
+            {
+                Ok(verdict) => verdict,
+                Err(e) => {
+                    warn!("Failed to run automod rules: {}", e);
+                    let _ = ack.send(&AppError::from(e).to_ack());
+                    return;
+                }
+            }
+        }
+        None => AutomodVerdict::Allow,
+    };
+    if let AutomodVerdict::Refuse(violation) = &verdict {
+        let _ = ack.send(&AppError::from(violation).to_ack());
+        return;
+    }
+
+    let message = match create_message(
+        app_state.clone(),
+        user_id,
+        payload.channel_id,
+        payload.content,
+        CreateMessageOptions {
+            reply_to_message_id: payload.reply_to_message_id,
+            thread_id: payload.thread_id,
+            ..Default::default()
+        },
+    )
+    .await
+    {
+        Ok(message) => message,
+        Err(e) => {
+            warn!("Failed to create message: {}", e);
+            let _ = ack.send(&AppError::from(e).to_ack());
+            return;
+        }
+    };
+
     info!("Message saved: {:?}", message);
 
-    if let Err(e) = io
-        .emit(
-            socket_publish_events::RECEIVE_CHAT_MESSAGE,
-            &ReceiveChatMessagePayload {
-                message: message.to_resource(connection_info.user.to_resource()),
-            },
+    let mentions = match record_mentions(app_state.clone(), &message, &connection_info.user).await {
+        Ok(mentions) => mentions,
+        Err(e) => {
+            warn!("Failed to record mentions of message {}: {}", message.id, e);
+            vec![]
+        }
+    };
+
+    let mut resources = [message.to_resource(connection_info.user.to_resource())];
+    if let Err(e) = attach_reply_references(app_state.clone(), &mut resources).await {
+        warn!("Failed to load quoted message: {}", e);
+    }
+    let [mut message_resource] = resources;
+    message_resource.mentions = mentions
+        .iter()
+        .map(|mention| mention.to_resource())
+        .collect();
+
+    let thread = match thread_summary_for(app_state.clone(), &message).await {
+        Ok(thread) => thread,
+        Err(e) => {
+            warn!("Failed to load thread summary: {}", e);
+            None
+        }
+    };
+
+    let chat_message_payload = ReceiveChatMessagePayload {
+        message: message_resource,
+        thread,
+    };
+
+    // Direct conversations are private to their participants
+    if !channel.is_direct() {
+        spawn_dispatch_event(
+            app_state.clone(),
+            event_types::MESSAGE_CREATED,
+            json!({ "message": chat_message_payload.message }),
+        );
+    }
+
+    emit_chat_message(app_state.clone(), &channel, &chat_message_payload).await;
+
+    let _ = ack.send(&json!({ "success": true, "messageId": chat_message_payload.message.id }));
+
+    if let AutomodVerdict::Flag(violation) = &verdict {
+        flag_message(
+            app_state.clone(),
+            &message,
+            &connection_info.user,
+            violation,
         )
-        .await
-    {
-        warn!("Failed to emit message: {}", e);
+        .await;
     }
+
+    spawn_unfurl(app_state.clone(), &message);
+
+    notify_mentions(
+        app_state,
+        &channel,
+        &chat_message_payload.message,
+        &mentions,
+    )
+    .await;
 }
 
 pub async fn send_poke_handler(
@@ -98,52 +304,28 @@ pub async fn send_poke_handler(
         }
     };
 
-    // Extract receiver user ID from payload
-    let receiver_user_id = match payload.get("userId").and_then(|id| id.as_str()) {
-        Some(id) => id,
-        None => {
-            let _ = ack.send(&json!({
-                "success": false,
-                "error": "No userId provided in payload"
-            }));
-            return;
-        }
-    };
+    let receiver_user_id = payload
+        .get("userId")
+        .and_then(|id| id.as_str())
+        .unwrap_or("");
 
     let message = payload
         .get("message")
         .and_then(|m| m.as_str())
         .unwrap_or("");
 
-    let created_at = payload
-        .get("createdAt")
-        .and_then(|dt| dt.as_str())
-        .map(|s| s.to_string())
-        .unwrap_or_else(|| chrono::Utc::now().to_rfc3339());
-
-    // Check if receiver exists and is connected
-    if let Some(connection) = app_state.connected_users.get(receiver_user_id) {
-        // Prepare the payload
-        let poke_payload = json!({
-            "user": connection_info.user.to_resource(),
-            "message": message,
-            "createdAt": created_at
-        });
-
-        // Emit event to the specific receiver
-        connection
-            .socket
-            .emit(socket_publish_events::RECEIVE_POKE, &poke_payload)
-            .ok();
+    // A client-supplied `createdAt` is ignored
+    match send_poke(app_state, &connection_info.user, receiver_user_id, message).await {
+        Ok(()) => {
+            let _ = ack.send(&json!({ "success": true }));
+        }
+        Err(e) => {
+            if let PokeError::Database(e) = &e {
+                warn!("Failed to poke user {}: {}", receiver_user_id, e);
+            }
 
-        // Send success callback
-        let _ = ack.send(&json!({ "success": true }));
-    } else {
-        // User not connected
-        let _ = ack.send(&json!({
-            "success": false,
-            "error": format!("User with ID {} is currently not connected!", receiver_user_id)
-        }));
+            let _ = ack.send(&AppError::from(e).to_ack());
+        }
     }
 }
 
@@ -162,14 +344,31 @@ pub async fn send_kick_handler(
         }
     };
 
+    match queries::user_has_permission(app_state.clone(), &connection_info.user.id, "kick_users")
+        .await
+    {
+        Ok(true) => {}
+        Ok(false) => {
+            let error = AppError::Forbidden("Missing permission: kick_users".to_string());
+            let _ = ack.send(&error.to_ack());
+            return;
+        }
+        Err(e) => {
+            warn!(
+                "Failed to check permissions of user {}: {}",
+                connection_info.user.id, e
+            );
+            let _ = ack.send(&AppError::from(e).to_ack());
+            return;
+        }
+    }
+
     // Extract receiver user ID from payload
     let receiver_user_id = match payload.get("userId").and_then(|id| id.as_str()) {
         Some(id) => id,
         None => {
-            let _ = ack.send(&json!({
-                "success": false,
-                "error": "No userId provided in payload"
-            }));
+            let error = AppError::Validation("No userId provided in payload".to_string());
+            let _ = ack.send(&error.to_ack());
             return;
         }
     };
@@ -197,20 +396,357 @@ pub async fn send_kick_handler(
             .emit(socket_publish_events::RECEIVE_KICK, &kick_payload)
             .ok();
 
+        spawn_dispatch_event(
+            app_state.clone(),
+            event_types::USER_KICKED,
+            json!({
+                "user": connection.user.to_resource(),
+                "kickedBy": connection_info.user.to_resource(),
+                "reason": reason,
+            }),
+        );
+
         // todo: kill livekit connections
 
         // Disconnect socket
         // todo: maybe handle error case?
         let _ = connection.socket.clone().disconnect();
+        // Release the map entry before awaiting
+        drop(connection);
+
+        audit_log::record(
+            app_state.clone(),
+            &connection_info.user.id,
+            audit_actions::USER_KICKED,
+            audit_target_types::USER,
+            receiver_user_id,
+            Some(reason).filter(|reason| !reason.is_empty()),
+            audit_log::diff(
+                &json!({ "connected": true }),
+                &json!({ "connected": false }),
+            ),
+        )
+        .await;
 
         // Send success callback
         let _ = ack.send(&json!({ "success": true }));
     } else {
         // User not connected
-        let _ = ack.send(&json!({
-            "success": false,
-            "error": format!("User with ID {} is currently not connected!", receiver_user_id)
-        }));
+        let error = AppError::NotConnected(format!(
+            "User with ID {} is currently not connected!",
+            receiver_user_id
+        ));
+        let _ = ack.send(&error.to_ack());
+    }
+}
+
+async fn toggle_reaction(
+    io: &SocketIo,
+    socket: &SocketRef,
+    payload: Value,
+    ack: AckSender,
+    app_state: Arc<AppState>,
+    add: bool,
+) {
+    let connection_info = match socket.extensions.get::<ConnectionInfo>() {
+        Some(info) => info,
+        None => {
+            warn!("Received reaction but no connection info found");
+            return;
+        }
+    };
+
+    let payload: ReactionPayload = match serde_json::from_value(payload) {
+        Ok(payload) => payload,
+        Err(e) => {
+            let error = AppError::BadRequest(format!("Invalid reaction payload: {}", e));
+            let _ = ack.send(&error.to_ack());
+            return;
+        }
+    };
+
+    let emoji = match normalize_emoji(&payload.emoji) {
+        Some(emoji) => emoji,
+        None => {
+            let error = AppError::Validation("Invalid emoji".to_string());
+            let _ = ack.send(&error.to_ack());
+            return;
+        }
+    };
+
+    let message = match queries::get_message_by_id(app_state.clone(), &payload.message_id).await {
+        Ok(Some(message)) => message,
+        Ok(None) => {
+            let error =
+                AppError::NotFound(format!("Message with ID {} not found", payload.message_id));
+            let _ = ack.send(&error.to_ack());
+            return;
+        }
+        Err(e) => {
+            warn!("Failed to load message {}: {}", payload.message_id, e);
+            let _ = ack.send(&AppError::from(e).to_ack());
+            return;
+        }
+    };
+
+    let user_id = &connection_info.user.id;
+
+    match queries::get_active_user_timeout(app_state.clone(), user_id).await {
+        Ok(None) => {}
+        Ok(Some(timeout)) => {
+            let error = AppError::TimedOut {
+                message: timed_out_message(&timeout),
+                expires_at: timeout.expires_at,
+            };
+            let _ = ack.send(&error.to_ack());
+            return;
+        }
+        Err(e) => {
+            warn!("Failed to load timeouts of user {}: {}", user_id, e);
+            let _ = ack.send(&AppError::from(e).to_ack());
+            return;
+        }
+    }
+
+    let channel = match queries::get_accessible_channel(
+        app_state.clone(),
+        &message.channel_id,
+        user_id,
+    )
+    .await
+    {
+        Ok(Some(channel)) => channel,
+        Ok(None) => {
+            let error =
+                AppError::NotFound(format!("Message with ID {} not found", payload.message_id));
+            let _ = ack.send(&error.to_ack());
+            return;
+        }
+        Err(e) => {
+            warn!("Failed to load channel {}: {}", message.channel_id, e);
+            let _ = ack.send(&AppError::from(e).to_ack());
+            return;
+        }
+    };
+
+    let result = if add {
+        queries::add_message_reaction(app_state.clone(), &message.id, user_id, &emoji).await
+    } else {
+        queries::remove_message_reaction(app_state.clone(), &message.id, user_id, &emoji).await
+    };
+
+    let changed = match result {
+        Ok(changed) => changed,
+        Err(e) => {
+            warn!("Failed to update reaction on message {}: {}", message.id, e);
+            let _ = ack.send(&AppError::from(e).to_ack());
+            return;
+        }
+    };
+
+    let count = match queries::count_message_reactions(app_state.clone(), &message.id, &emoji).await
+    {
+        Ok(count) => count,
+        Err(e) => {
+            warn!("Failed to count reactions on message {}: {}", message.id, e);
+            let _ = ack.send(&AppError::from(e).to_ack());
+            return;
+        }
+    };
+
+    // Adding an existing or removing a missing reaction is a no-op
+    if changed {
+        let event = if add {
+            socket_publish_events::RECEIVE_REACTION_ADDED
+        } else {
+            socket_publish_events::RECEIVE_REACTION_REMOVED
+        };
+
+        let reaction_payload = ReceiveReactionPayload {
+            messageId: message.id.clone(),
+            channelId: message.channel_id.clone(),
+            userId: user_id.clone(),
+            emoji: emoji.clone(),
+            count,
+        };
+
+        emit_to_channel(app_state.clone(), &channel, event, &reaction_payload).await;
+    }
+
+    let _ = ack.send(&json!({ "success": true, "emoji": emoji, "count": count }));
+}
+
+pub async fn add_reaction_handler(
+    io: &SocketIo,
+    socket: &SocketRef,
+    Data(payload): Data<Value>,
+    ack: AckSender,
+    app_state: Arc<AppState>,
+) {
+    toggle_reaction(io, socket, payload, ack, app_state, true).await;
+}
+
+pub async fn remove_reaction_handler(
+    io: &SocketIo,
+    socket: &SocketRef,
+    Data(payload): Data<Value>,
+    ack: AckSender,
+    app_state: Arc<AppState>,
+) {
+    toggle_reaction(io, socket, payload, ack, app_state, false).await;
+}
+
+#[derive(Debug, Deserialize)]
+struct MarkReadPayload {
+    #[serde(rename = "channelId")]
+    channel_id: String,
+
+    #[serde(rename = "messageId")]
+    message_id: String,
+}
+
+pub async fn mark_read_handler(
+    socket: &SocketRef,
+    Data(payload): Data<Value>,
+    ack: AckSender,
+    app_state: Arc<AppState>,
+) {
+    let connection_info = match socket.extensions.get::<ConnectionInfo>() {
+        Some(info) => info,
+        None => {
+            warn!("Received read marker but no connection info found");
+            return;
+        }
+    };
+
+    let payload: MarkReadPayload = match serde_json::from_value(payload) {
+        Ok(payload) => payload,
+        Err(e) => {
+            let error = AppError::BadRequest(format!("Invalid read marker payload: {}", e));
+            let _ = ack.send(&error.to_ack());
+            return;
+        }
+    };
+
+    // The new state reaches this socket through the user room as well
+    match mark_channel_read(
+        app_state,
+        &connection_info.user.id,
+        &payload.channel_id,
+        &payload.message_id,
+    )
+    .await
+    {
+        Ok(read_state) => {
+            let _ = ack.send(&json!({ "success": true, "readState": read_state }));
+        }
+        Err(e) => {
+            if let MarkReadError::Database(e) = &e {
+                warn!("Failed to mark channel {} read: {}", payload.channel_id, e);
+            }
+
+            let _ = ack.send(&AppError::from(e).to_ack());
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReportMessagePayload {
+    #[serde(rename = "messageId")]
+    message_id: String,
+
+    reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReportUserPayload {
+    #[serde(rename = "userId")]
+    user_id: String,
+
+    reason: Option<String>,
+}
+
+pub async fn report_message_handler(
+    socket: &SocketRef,
+    Data(payload): Data<Value>,
+    ack: AckSender,
+    app_state: Arc<AppState>,
+) {
+    let connection_info = match socket.extensions.get::<ConnectionInfo>() {
+        Some(info) => info,
+        None => {
+            warn!("Received report but no connection info found");
+            return;
+        }
+    };
+
+    let payload: ReportMessagePayload = match serde_json::from_value(payload) {
+        Ok(payload) => payload,
+        Err(e) => {
+            let error = AppError::BadRequest(format!("Invalid report payload: {}", e));
+            let _ = ack.send(&error.to_ack());
+            return;
+        }
+    };
+
+    let result = report_message(
+        app_state,
+        &connection_info.user,
+        &payload.message_id,
+        payload.reason.as_deref(),
+    )
+    .await;
+
+    send_report_ack(ack, result);
+}
+
+pub async fn report_user_handler(
+    socket: &SocketRef,
+    Data(payload): Data<Value>,
+    ack: AckSender,
+    app_state: Arc<AppState>,
+) {
+    let connection_info = match socket.extensions.get::<ConnectionInfo>() {
+        Some(info) => info,
+        None => {
+            warn!("Received report but no connection info found");
+            return;
+        }
+    };
+
+    let payload: ReportUserPayload = match serde_json::from_value(payload) {
+        Ok(payload) => payload,
+        Err(e) => {
+            let error = AppError::BadRequest(format!("Invalid report payload: {}", e));
+            let _ = ack.send(&error.to_ack());
+            return;
+        }
+    };
+
+    let result = report_user(
+        app_state,
+        &connection_info.user,
+        &payload.user_id,
+        payload.reason.as_deref(),
+    )
+    .await;
+
+    send_report_ack(ack, result);
+}
+
+/// Reporters only learn the id of their report, not the moderation state.
+fn send_report_ack(ack: AckSender, result: Result<Report, ReportError>) {
+    match result {
+        Ok(report) => {
+            let _ = ack.send(&json!({ "success": true, "reportId": report.id }));
+        }
+        Err(e) => {
+            if let ReportError::Database(e) = &e {
+                warn!("Failed to file report: {}", e);
+            }
+
+            let _ = ack.send(&AppError::from(e).to_ack());
+        }
     }
 }
 
@@ -222,12 +758,64 @@ pub async fn send_user_is_typing_handler(
 ) {
 }
 
+/// Updates the microphone state of the sender and tells everyone about it.
+/// Timed out users cannot unmute.
 pub async fn send_user_microphone_status_changed(
     io: &SocketIo,
     socket: &SocketRef,
     Data(payload): Data<Value>,
+    ack: AckSender,
     app_state: Arc<AppState>,
 ) {
+    let Some(connection_info) = socket.extensions.get::<ConnectionInfo>() else {
+        warn!("Received microphone status but no connection info found");
+        let error = AppError::Unauthorized("Not authenticated".to_string());
+        let _ = ack.send(&error.to_ack());
+        return;
+    };
+    let user_id = &connection_info.user.id;
+
+    let payload: MicrophoneStatusPayload = match serde_json::from_value(payload) {
+        Ok(payload) => payload,
+        Err(e) => {
+            let error = AppError::BadRequest(format!("Invalid payload: {}", e));
+            let _ = ack.send(&error.to_ack());
+            return;
+        }
+    };
+
+    let timeout = match queries::get_active_user_timeout(app_state.clone(), user_id).await {
+        Ok(timeout) => timeout,
+        Err(e) => {
+            warn!("Failed to load timeouts of user {}: {}", user_id, e);
+            let _ = ack.send(&AppError::from(e).to_ack());
+            return;
+        }
+    };
+
+    if let Err(error) = check_microphone_change(timeout.as_ref(), payload.is_microphone_muted) {
+        let _ = ack.send(&error.to_ack());
+        return;
+    }
+
+    if let Some(mut connection) = app_state.connected_users.get_mut(user_id) {
+        connection.is_mic_muted = payload.is_microphone_muted;
+    }
+
+    if let Err(e) = io
+        .emit(
+            socket_publish_events::RECEIVE_USER_MICROPHONE_STATUS_CHANGED,
+            &json!({ "userId": user_id, "isMicrophoneMuted": payload.is_microphone_muted }),
+        )
+        .await
+    {
+        warn!(
+            "Failed to announce microphone status of user {}: {}",
+            user_id, e
+        );
+    }
+
+    let _ = ack.send(&json!({ "success": true }));
 }
 
 pub async fn send_user_audio_mute_status_changed(
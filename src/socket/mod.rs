@@ -1,4 +1,4 @@
 pub mod connection;
-mod events;
+pub mod events;
 pub mod handlers;
 pub mod listeners;
@@ -0,0 +1,102 @@
+use crate::config::Config;
+use crate::models::User;
+use crate::queries;
+use crate::services::automod::{FilterCache, FloodTracker};
+use crate::services::image_proxy::ImageProxyCache;
+use crate::services::login_throttle::LoginThrottle;
+use crate::services::preview_cache::PreviewCache;
+use crate::services::rate_limit::RateLimiter;
+use crate::socket::connection::user_room;
+use crate::AppState;
+use engineioxide::Packet;
+use serde_json::Value;
+use socketioxide::extract::{Data, SocketRef};
+use socketioxide::SocketIo;
+use sqlx::MySqlPool;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::Receiver;
+use uuid::Uuid;
+
+/// App state over `pool` for tests touching the database. Sockets connected
+/// with `connect_test_socket` join the room of the user id they pass.
+pub fn test_app_state(pool: MySqlPool) -> Arc<AppState> {
+    let (_, io) = SocketIo::new_layer();
+
+    io.ns("/", |socket: SocketRef, Data(user_id): Data<String>| {
+        socket.join(user_room(&user_id));
+    });
+
+    Arc::new(AppState {
+        db: pool,
+        config: Config {
+            database_url: String::new(),
+            jwt_secret: "test-secret".to_string(),
+            s3_key: String::new(),
+            s3_secret: String::new(),
+            s3_bucket: String::new(),
+            s3_region: String::new(),
+            s3_endpoint: String::new(),
+            livekit_server_url: String::new(),
+            livekit_turn_url: String::new(),
+            livekit_api_key: String::new(),
+            livekit_secret_key: String::new(),
+        },
+        cnt: Mutex::from(0),
+        connected_users: dashmap::DashMap::new(),
+        login_throttle: LoginThrottle::new(),
+        webhook_rate_limiter: RateLimiter::new(),
+        poke_rate_limiter: RateLimiter::new(),
+        automod_flood_tracker: FloodTracker::new(),
+        automod_filter_cache: FilterCache::new(),
+        link_preview_cache: PreviewCache::new(),
+        image_proxy_cache: ImageProxyCache::new(),
+        io,
+    })
+}
+
+/// A regular user named `username` that cannot log in.
+pub async fn create_test_user(app_state: Arc<AppState>, username: &str) -> User {
+    let id = Uuid::new_v4().to_string();
+
+    sqlx::query!(
+        "INSERT INTO users (id, username, display_name, password) VALUES (?, ?, ?, '!')",
+        id,
+        username,
+        username
+    )
+    .execute(&app_state.db)
+    .await
+    .unwrap();
+
+    queries::get_user_by_id(app_state, id).await.unwrap()
+}
+
+/// Connects a socket for `user_id` and returns what the server sends it.
+pub async fn connect_test_socket(app_state: &AppState, user_id: &str) -> Receiver<Packet> {
+    let (_, packets) = app_state.io.new_dummy_sock("/", user_id).await;
+
+    packets
+}
+
+/// Names of the events received on `packets` so far.
+pub fn received_events(packets: &mut Receiver<Packet>) -> Vec<String> {
+    let mut events = Vec::new();
+
+    while let Ok(packet) = packets.try_recv() {
+        // Events are encoded as `2["name",...]`, behind the engine.io `4`
+        let Packet::Message(message) = packet else {
+            continue;
+        };
+        let Some(payload) = message.strip_prefix("42") else {
+            continue;
+        };
+
+        if let Ok(Value::Array(values)) = serde_json::from_str(payload) {
+            if let Some(Value::String(event)) = values.first() {
+                events.push(event.clone());
+            }
+        }
+    }
+
+    events
+}